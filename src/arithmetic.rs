@@ -1,19 +1,45 @@
-#![allow(dead_code, non_snake_case)]
+#![allow(dead_code, non_snake_case, clippy::too_many_arguments)]
 
-use crate::logic::bit::{I, O};
 use crate::logic::*;
 
-pub fn HalfAdder(a: bit, b: bit) -> [bit; 2] {
-    [And(a, b), Xor(a, b)]
+pub fn HalfAdder<S: Signal>(a: S, b: S) -> [S; 2] {
+    S::begin("HalfAdder", &[("a", &[a]), ("b", &[b])]);
+    let out = [And(a, b), Xor(a, b)];
+    S::end(&[("carry", &[out[0]]), ("sum", &[out[1]])]);
+    out
 }
 
-pub fn FullAdder(a: bit, b: bit, c: bit) -> [bit; 2] {
+pub fn FullAdder<S: Signal>(a: S, b: S, c: S) -> [S; 2] {
+    S::begin("FullAdder", &[("a", &[a]), ("b", &[b]), ("c", &[c])]);
     let res1 = HalfAdder(a, b);
     let res2 = HalfAdder(c, res1[1]);
-    [Or(res1[0], res2[0]), res2[1]]
+    let out = [Or(res1[0], res2[0]), res2[1]];
+    S::end(&[("carry", &[out[0]]), ("sum", &[out[1]])]);
+    out
 }
 
-pub fn Add16(a: Word, b: Word) -> Word {
+pub fn Add16<W: Signal16>(a: W, b: W) -> W {
+    W::Bit::begin("Add16", &[("a", &a.bits()), ("b", &b.bits())]);
+    let (out, _, _) = ripple(a, b);
+    W::Bit::end(&[("out", &out.bits())]);
+    out
+}
+
+// (和, 最上位からの桁上がり, 符号付きのあふれ)。あふれは最上位へ入る桁上がりと出る桁上がりが違うとき
+pub fn Add16WithFlags<W: Signal16>(a: W, b: W) -> (W, W::Bit, W::Bit) {
+    W::Bit::begin("Add16WithFlags", &[("a", &a.bits()), ("b", &b.bits())]);
+    let (out, carry, into_msb) = ripple(a, b);
+    let overflow = Xor(into_msb, carry);
+    W::Bit::end(&[
+        ("out", &out.bits()),
+        ("carry", &[carry]),
+        ("overflow", &[overflow]),
+    ]);
+    (out, carry, overflow)
+}
+
+// 下の桁から FullAdder をつなぐ。(和, 最上位から出る桁上がり, 最上位へ入る桁上がり)
+fn ripple<W: Signal16>(a: W, b: W) -> (W, W::Bit, W::Bit) {
     let res15 = HalfAdder(a[15], b[15]);
     let res14 = FullAdder(a[14], b[14], res15[0]);
     let res13 = FullAdder(a[13], b[13], res14[0]);
@@ -30,17 +56,20 @@ pub fn Add16(a: Word, b: Word) -> Word {
     let res2 = FullAdder(a[2], b[2], res3[0]);
     let res1 = FullAdder(a[1], b[1], res2[0]);
     let res0 = FullAdder(a[0], b[0], res1[0]);
-    Word::new([
+    let out = W::new([
         res0[1], res1[1], res2[1], res3[1], res4[1], res5[1], res6[1], res7[1], res8[1], res9[1],
         res10[1], res11[1], res12[1], res13[1], res14[1], res15[1],
-    ])
+    ]);
+    (out, res0[0], res1[0])
 }
 
-pub fn Inc16(a: Word) -> Word {
-    Add16(
-        a,
-        Word::new([O, O, O, O, O, O, O, O, O, O, O, O, O, O, O, I]),
-    )
+pub fn Inc16<W: Signal16>(a: W) -> W {
+    W::Bit::begin("Inc16", &[("in", &a.bits())]);
+    let mut one = W::splat(W::Bit::LOW);
+    one[15] = W::Bit::HIGH;
+    let out = Add16(a, one);
+    W::Bit::end(&[("out", &out.bits())]);
+    out
 }
 
 /*
@@ -58,37 +87,247 @@ pub fn Inc16(a: Word) -> Word {
     zr: out = 0 で I
     ng: out < 0 で I
 */
-pub fn ALU(
-    x: Word,
-    y: Word,
-    zx: bit,
-    nx: bit,
-    zy: bit,
-    ny: bit,
-    f: bit,
-    no: bit,
-) -> (Word, bit, bit) {
-    let x1 = Mux16(x, Word::new([O; 16]), zx);
-    let x2 = Mux16(x1, Not16(x1), nx);
-    let y1 = Mux16(y, Word::new([O; 16]), zy);
-    let y2 = Mux16(y1, Not16(y1), ny);
+pub fn ALU<W: Signal16>(
+    x: W,
+    y: W,
+    zx: W::Bit,
+    nx: W::Bit,
+    zy: W::Bit,
+    ny: W::Bit,
+    f: W::Bit,
+    no: W::Bit,
+) -> (W, W::Bit, W::Bit) {
+    W::Bit::begin(
+        "ALU",
+        &[
+            ("x", &x.bits()),
+            ("y", &y.bits()),
+            ("zx", &[zx]),
+            ("nx", &[nx]),
+            ("zy", &[zy]),
+            ("ny", &[ny]),
+            ("f", &[f]),
+            ("no", &[no]),
+        ],
+    );
+    let x2 = preset(x, zx, nx);
+    let y2 = preset(y, zy, ny);
     let f_res = Mux16(And16(x2, y2), Add16(x2, y2), f);
     let out = Mux16(f_res, Not16(f_res), no);
-    let zr = Not(Or(
-        Or8Way([
-            out[0], out[1], out[2], out[3], out[4], out[5], out[6], out[7],
-        ]),
-        Or8Way([
-            out[8], out[9], out[10], out[11], out[12], out[13], out[14], out[15],
-        ]),
+    let (zr, ng) = (IsZero16(out), out[0]);
+    W::Bit::end(&[("out", &out.bits()), ("zr", &[zr]), ("ng", &[ng])]);
+    (out, zr, ng)
+}
+
+// ALU の出力に、out を計算した足し算か引き算の桁上がりとあふれを足したもの。
+// 桁上がりは u16 の overflowing_add / overflowing_sub、あふれは i16 のものと同じ。f が O (AND演算) のときはどちらも O
+pub fn ALUWithFlags<W: Signal16>(
+    x: W,
+    y: W,
+    zx: W::Bit,
+    nx: W::Bit,
+    zy: W::Bit,
+    ny: W::Bit,
+    f: W::Bit,
+    no: W::Bit,
+) -> (W, W::Bit, W::Bit, W::Bit, W::Bit) {
+    W::Bit::begin(
+        "ALUWithFlags",
+        &[
+            ("x", &x.bits()),
+            ("y", &y.bits()),
+            ("zx", &[zx]),
+            ("nx", &[nx]),
+            ("zy", &[zy]),
+            ("ny", &[ny]),
+            ("f", &[f]),
+            ("no", &[no]),
+        ],
+    );
+    let x2 = preset(x, zx, nx);
+    let y2 = preset(y, zy, ny);
+    let (sum, carry, overflow) = Add16WithFlags(x2, y2);
+    let f_res = Mux16(And16(x2, y2), sum, f);
+    let out = Mux16(f_res, Not16(f_res), no);
+    let (zr, ng) = (IsZero16(out), out[0]);
+    // no で反転すると out = !x2 + !y2 + 1 なので、桁上がりは反転し、あふれはそのまま。
+    // nx と ny が違う comp (D-A、-D、D-1 など) は引き算なので、桁上がりの代わりに借りを出す
+    let carry = And(f, Xor(Xor(carry, no), Xor(nx, ny)));
+    let overflow = And(f, overflow);
+    W::Bit::end(&[
+        ("out", &out.bits()),
+        ("zr", &[zr]),
+        ("ng", &[ng]),
+        ("carry", &[carry]),
+        ("overflow", &[overflow]),
+    ]);
+    (out, zr, ng, carry, overflow)
+}
+
+// ALU の入力の前処理。z で O にしてから n で反転する
+fn preset<W: Signal16>(a: W, z: W::Bit, n: W::Bit) -> W {
+    let a1 = Mux16(a, W::splat(W::Bit::LOW), z);
+    Mux16(a1, Not16(a1), n)
+}
+
+pub fn IsZero16<W: Signal16>(a: W) -> W::Bit {
+    W::Bit::begin("IsZero16", &[("in", &a.bits())]);
+    let out = Not(Or(
+        Or8Way([a[0], a[1], a[2], a[3], a[4], a[5], a[6], a[7]]),
+        Or8Way([a[8], a[9], a[10], a[11], a[12], a[13], a[14], a[15]]),
     ));
-    let ng = out[0];
+    W::Bit::end(&[("out", &[out])]);
+    out
+}
+
+// 1 ビット左へ。最下位には O が入る
+pub fn ShiftLeft16<W: Signal16>(a: W) -> W {
+    W::Bit::begin("ShiftLeft16", &[("in", &a.bits())]);
+    let mut out = W::splat(W::Bit::LOW);
+    for i in 0..15 {
+        out[i] = a[i + 1];
+    }
+    W::Bit::end(&[("out", &out.bits())]);
+    out
+}
+
+// 1 ビット右へ。arithmetic が I なら符号ビットを、O なら O を最上位に入れる
+pub fn ShiftRight16<W: Signal16>(a: W, arithmetic: W::Bit) -> W {
+    W::Bit::begin(
+        "ShiftRight16",
+        &[("in", &a.bits()), ("arithmetic", &[arithmetic])],
+    );
+    let mut out = W::splat(W::Bit::LOW);
+    out[0] = And(a[0], arithmetic);
+    for i in 1..16 {
+        out[i] = a[i - 1];
+    }
+    W::Bit::end(&[("out", &out.bits())]);
+    out
+}
+
+pub fn RotateLeft16<W: Signal16>(a: W) -> W {
+    W::Bit::begin("RotateLeft16", &[("in", &a.bits())]);
+    let mut out = ShiftLeft16(a);
+    out[15] = a[0];
+    W::Bit::end(&[("out", &out.bits())]);
+    out
+}
+
+pub fn RotateRight16<W: Signal16>(a: W) -> W {
+    W::Bit::begin("RotateRight16", &[("in", &a.bits())]);
+    let mut out = ShiftRight16(a, W::Bit::LOW);
+    out[0] = a[15];
+    W::Bit::end(&[("out", &out.bits())]);
+    out
+}
+
+/*
+  入力:
+    a:          Word
+    amount:     ずらす量 (0 番目が 8 の位)
+    right:      I なら右、O なら左
+    arithmetic: 右のとき、I なら符号ビットで埋める
+  左は前後でビットの並びを逆にして、右シフトの段を使い回す。
+  8, 4, 2, 1 の 4 段の Mux16 なので、段数は量によらない
+*/
+pub fn BarrelShifter16<W: Signal16>(
+    a: W,
+    amount: [W::Bit; 4],
+    right: W::Bit,
+    arithmetic: W::Bit,
+) -> W {
+    W::Bit::begin(
+        "BarrelShifter16",
+        &[
+            ("in", &a.bits()),
+            ("amount", &amount),
+            ("right", &[right]),
+            ("arithmetic", &[arithmetic]),
+        ],
+    );
+    let x = Mux16(reverse(a), a, right);
+    let fill = And(a[0], And(arithmetic, right));
+    let x = Mux16(x, shift_right(x, 8, fill), amount[0]);
+    let x = Mux16(x, shift_right(x, 4, fill), amount[1]);
+    let x = Mux16(x, shift_right(x, 2, fill), amount[2]);
+    let x = Mux16(x, shift_right(x, 1, fill), amount[3]);
+    let out = Mux16(reverse(x), x, right);
+    W::Bit::end(&[("out", &out.bits())]);
+    out
+}
+
+/*
+  CPU の計算部分。ALU の隣にシフト命令用の部品を置き、出力を選ぶ
+  入力:
+    instruction: C 命令
+    d, a, m:     D、A、M の値
+    extended:    I なら 101 で始まる命令をシフト命令として扱う
+  シフト命令では c1 が I なら左、O なら右 (算術シフト)。
+  c2 が I なら D を、O なら a ビットで選んだ A か M をずらす
+*/
+pub fn Compute<W: Signal16>(
+    instruction: W,
+    d: W,
+    a: W,
+    m: W,
+    extended: W::Bit,
+) -> (W, W::Bit, W::Bit) {
+    W::Bit::begin(
+        "Compute",
+        &[
+            ("instruction", &instruction.bits()),
+            ("d", &d.bits()),
+            ("a", &a.bits()),
+            ("m", &m.bits()),
+            ("extended", &[extended]),
+        ],
+    );
+    let i = instruction;
+    let y = Mux16(a, m, i[3]);
+    let (alu_out, _, _) = ALU(d, y, i[4], i[5], i[6], i[7], i[8], i[9]);
+    let x = Mux16(y, d, i[5]);
+    let shifted = Mux16(ShiftRight16(x, W::Bit::HIGH), ShiftLeft16(x), i[4]);
+    let is_shift = And(extended, And(And(i[0], i[2]), Not(i[1])));
+    let out = Mux16(alu_out, shifted, is_shift);
+    let (zr, ng) = (IsZero16(out), out[0]);
+    W::Bit::end(&[("out", &out.bits()), ("zr", &[zr]), ("ng", &[ng])]);
     (out, zr, ng)
 }
 
+// 配線だけでできる部品
+fn reverse<W: Signal16>(a: W) -> W {
+    let mut out = W::splat(W::Bit::LOW);
+    for i in 0..16 {
+        out[i] = a[15 - i];
+    }
+    out
+}
+
+fn shift_right<W: Signal16>(a: W, k: usize, fill: W::Bit) -> W {
+    let mut out = W::splat(fill);
+    for i in k..16 {
+        out[i] = a[i - k];
+    }
+    out
+}
+
+// for_add16 の (a, b, a + b)。fault の故障検出率もこの組で測る
+#[cfg(test)]
+pub(crate) const ADD16_VECTORS: [(u16, u16, u16); 3] = [
+    (0xffff, 0x0000, 0xffff),
+    (0b0000111101010011, 0b0110010010011010, 0b0111001111101101),
+    (0xffff, 0b0110010010011010, 0b0110010010011001),
+];
+
 #[cfg(test)]
 mod tests {
-    use super::{Add16, FullAdder, HalfAdder, Inc16, ALU};
+    use super::{
+        ALUWithFlags, Add16, Add16WithFlags, BarrelShifter16, Compute, FullAdder, HalfAdder, Inc16,
+        RotateLeft16, RotateRight16, ShiftLeft16, ShiftRight16, ADD16_VECTORS, ALU,
+    };
+    use crate::disasm::{is_shift, COMPS, SHIFTS};
+    use crate::emulator::{alu, shift};
     use crate::logic::bit::{I, O};
     use crate::logic::Word;
     #[test]
@@ -113,24 +352,15 @@ mod tests {
 
     #[test]
     fn for_add16() {
-        assert_eq!(
-            Add16(Word::new([I; 16]), Word::new([O; 16])),
-            Word::new([I; 16])
-        );
-        assert_eq!(
-            Add16(
-                Word::new([O, O, O, O, I, I, I, I, O, I, O, I, O, O, I, I]),
-                Word::new([O, I, I, O, O, I, O, O, I, O, O, I, I, O, I, O])
-            ),
-            Word::new([O, I, I, I, O, O, I, I, I, I, I, O, I, I, O, I])
-        );
-        assert_eq!(
-            Add16(
-                Word::new([I, I, I, I, I, I, I, I, I, I, I, I, I, I, I, I]),
-                Word::new([O, I, I, O, O, I, O, O, I, O, O, I, I, O, I, O])
-            ),
-            Word::new([O, I, I, O, O, I, O, O, I, O, O, I, I, O, O, I])
-        );
+        for (a, b, sum) in ADD16_VECTORS.iter() {
+            assert_eq!(
+                Add16(Word::from(*a), Word::from(*b)),
+                Word::from(*sum),
+                "{:#06x} + {:#06x}",
+                a,
+                b
+            );
+        }
     }
 
     #[test]
@@ -275,4 +505,166 @@ mod tests {
             )
         );
     }
+
+    const SAMPLES: [u16; 8] = [0, 1, 0x8000, 0x8001, 0x7fff, 0xffff, 0x1234, 0xbeef];
+
+    #[test]
+    fn for_shift16() {
+        for x in SAMPLES.iter() {
+            let w = Word::from(*x);
+            assert_eq!(ShiftLeft16(w), Word::from(x << 1));
+            assert_eq!(ShiftRight16(w, O), Word::from(x >> 1));
+            assert_eq!(ShiftRight16(w, I), Word::from((*x as i16) >> 1));
+            assert_eq!(RotateLeft16(w), Word::from(x.rotate_left(1)));
+            assert_eq!(RotateRight16(w), Word::from(x.rotate_right(1)));
+        }
+    }
+
+    #[test]
+    fn for_barrelshifter16() {
+        for x in SAMPLES.iter() {
+            let w = Word::from(*x);
+            for k in 0..16u16 {
+                let amount = Word::from(k);
+                let amount = [amount[12], amount[13], amount[14], amount[15]];
+                assert_eq!(BarrelShifter16(w, amount, O, O), Word::from(x << k));
+                assert_eq!(BarrelShifter16(w, amount, O, I), Word::from(x << k));
+                assert_eq!(BarrelShifter16(w, amount, I, O), Word::from(x >> k));
+                assert_eq!(
+                    BarrelShifter16(w, amount, I, I),
+                    Word::from((*x as i16) >> k),
+                    "{:#06x} >> {}",
+                    x,
+                    k
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn for_compute() {
+        let (d, a, m) = (0x8001u16, 0x1234u16, 0x4003u16);
+        let c_instructions = COMPS.iter().map(|(bits, _)| 0xe000 | bits << 6);
+        let shifts = SHIFTS.iter().map(|(bits, _)| 0xa000 | bits << 6);
+        for instruction in c_instructions.chain(shifts) {
+            let y = if instruction & 0x1000 != 0 { m } else { a };
+            let control = ((instruction >> 6) & 0x3f) as u8;
+            let run = |extended| {
+                Compute(
+                    Word::from(instruction),
+                    Word::from(d),
+                    Word::from(a),
+                    Word::from(m),
+                    extended,
+                )
+            };
+            let expected = if is_shift(instruction) {
+                shift(d, y, control)
+            } else {
+                alu(d, y, control)
+            };
+            let (out, zr, ng) = run(I);
+            assert_eq!(u16::from(out), expected, "{:016b}", instruction);
+            assert_eq!(zr == I, expected == 0);
+            assert_eq!(ng == I, expected & 0x8000 != 0);
+            // 拡張しなければ ALU の出力のまま
+            assert_eq!(u16::from(run(O).0), alu(d, y, control));
+        }
+    }
+
+    #[test]
+    fn for_add16_flags() {
+        for x in SAMPLES.iter() {
+            for y in SAMPLES.iter().chain([0x4000u16, 0xc000].iter()) {
+                let (out, carry, overflow) = Add16WithFlags(Word::from(*x), Word::from(*y));
+                let (sum, expected_carry) = x.overflowing_add(*y);
+                let (_, expected_overflow) = (*x as i16).overflowing_add(*y as i16);
+                assert_eq!(out, Word::from(sum));
+                assert_eq!(carry == I, expected_carry, "{:#06x} + {:#06x}", x, y);
+                assert_eq!(overflow == I, expected_overflow, "{:#06x} + {:#06x}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn for_alu_flags() {
+        for x in SAMPLES.iter() {
+            for y in SAMPLES.iter() {
+                for control in 0..64u8 {
+                    let on = |i: u8| (control >> (5 - i)) & 1 == 1;
+                    let c = |i: u8| if on(i) { I } else { O };
+                    let (out, zr, ng, carry, overflow) = ALUWithFlags(
+                        Word::from(*x),
+                        Word::from(*y),
+                        c(0),
+                        c(1),
+                        c(2),
+                        c(3),
+                        c(4),
+                        c(5),
+                    );
+                    assert_eq!(u16::from(out), alu(*x, *y, control));
+                    assert_eq!((zr == I, ng == I), (out == Word::from(0u16), out[0] == I));
+                    if !on(4) {
+                        assert_eq!((carry, overflow), (O, O));
+                    }
+                }
+            }
+        }
+        // Hack の 18 個の comp (D が x、A が y)。定数も 0 + 0、0 + 1、0 - 1 として計算している
+        let add = |x: u16, y: u16| {
+            (
+                x.overflowing_add(y).1,
+                (x as i16).overflowing_add(y as i16).1,
+            )
+        };
+        let sub = |x: u16, y: u16| {
+            (
+                x.overflowing_sub(y).1,
+                (x as i16).overflowing_sub(y as i16).1,
+            )
+        };
+        for (code, comp) in COMPS[..18].iter() {
+            for d in SAMPLES.iter() {
+                for a in SAMPLES.iter() {
+                    let (d, a) = (*d, *a);
+                    let expected = match *comp {
+                        "0" => add(0, 0),
+                        "1" => add(0, 1),
+                        "-1" => sub(0, 1),
+                        "-D" => sub(0, d),
+                        "-A" => sub(0, a),
+                        "D+1" => add(d, 1),
+                        "A+1" => add(a, 1),
+                        "D-1" => sub(d, 1),
+                        "A-1" => sub(a, 1),
+                        "D+A" => add(d, a),
+                        "D-A" => sub(d, a),
+                        "A-D" => sub(a, d),
+                        _ => (false, false),
+                    };
+                    let c = |i: u16| if (code >> (5 - i)) & 1 == 1 { I } else { O };
+                    let (out, _, _, carry, overflow) = ALUWithFlags(
+                        Word::from(d),
+                        Word::from(a),
+                        c(0),
+                        c(1),
+                        c(2),
+                        c(3),
+                        c(4),
+                        c(5),
+                    );
+                    assert_eq!(u16::from(out), alu(d, a, (code & 0x3f) as u8), "{}", comp);
+                    assert_eq!(
+                        (carry == I, overflow == I),
+                        expected,
+                        "{} with D={} A={}",
+                        comp,
+                        d,
+                        a
+                    );
+                }
+            }
+        }
+    }
 }
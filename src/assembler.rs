@@ -0,0 +1,483 @@
+#![allow(dead_code)]
+
+use crate::disasm::{COMPS, JUMPS, SHIFTS};
+use crate::logic::Word;
+use crate::rom::ROM_SIZE;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+const PREDEFINED: [(&str, u16); 7] = [
+    ("SP", 0),
+    ("LCL", 1),
+    ("ARG", 2),
+    ("THIS", 3),
+    ("THAT", 4),
+    ("SCREEN", 16384),
+    ("KBD", 24576),
+];
+
+// 変数は RAM[16] から順に割り当てる
+const VARIABLE_BASE: u16 = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Display for AsmError {
+    fn fmt(&self, dest: &mut Formatter) -> fmt::Result {
+        write!(
+            dest,
+            "{}:{}:{}: {}",
+            self.file, self.line, self.column, self.message
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Label,
+    Variable,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub address: u16,
+    pub kind: SymbolKind,
+}
+
+// 命令かラベルを書いたソースの一行
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLine {
+    pub line: usize,
+    pub text: String,
+    pub address: Option<u16>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    pub code: Vec<Word>,
+    pub source: Vec<SourceLine>,
+    // ラベル、変数の順にそれぞれ定義順
+    pub symbols: Vec<Symbol>,
+}
+
+impl Program {
+    pub fn to_listing(&self) -> String {
+        let mut listing = format!("{:>5}  {:<16}  {:<4}  source\n", "ROM", "binary", "hex");
+        for line in self.source.iter() {
+            match line.address {
+                Some(address) => {
+                    let word = u16::from(self.code[address as usize]);
+                    listing.push_str(&format!(
+                        "{:>5}  {:016b}  {:04X}  {}\n",
+                        address, word, word, line.text
+                    ));
+                }
+                None => {
+                    listing.push_str(&format!("{:>5}  {:16}  {:4}  {}\n", "", "", "", line.text))
+                }
+            }
+        }
+        listing
+    }
+
+    // debugger::parse_symbols で読める形
+    pub fn to_symbols(&self) -> String {
+        self.symbols
+            .iter()
+            .map(|symbol| {
+                let kind = match symbol.kind {
+                    SymbolKind::Label => "label",
+                    SymbolKind::Variable => "variable",
+                };
+                format!("{} {} {}\n", symbol.name, symbol.address, kind)
+            })
+            .collect()
+    }
+}
+
+// 一行にある命令の本体と、その行の中での開始位置 (0 から)
+struct Statement<'a> {
+    line: usize,
+    column: usize,
+    body: &'a str,
+    text: &'a str,
+}
+
+// エラーは最初の一つで止めず、まとめて返す
+pub fn assemble(file: &str, src: &str) -> Result<Program, Vec<AsmError>> {
+    assemble_with(file, src, false)
+}
+
+// extended ならシフト命令 (D<< など) も受け付ける
+pub fn assemble_with(file: &str, src: &str, extended: bool) -> Result<Program, Vec<AsmError>> {
+    let mut errors = vec![];
+    let error = |line: usize, column: usize, message: String| AsmError {
+        file: file.to_string(),
+        line,
+        column: column + 1,
+        message,
+    };
+
+    let mut statements = vec![];
+    for (index, text) in src.lines().enumerate() {
+        let code = text.split("//").next().unwrap_or("");
+        let body = code.trim();
+        if body.is_empty() {
+            continue;
+        }
+        statements.push(Statement {
+            line: index + 1,
+            column: code.len() - code.trim_start().len(),
+            body,
+            text: text.trim(),
+        });
+    }
+
+    // 一周目: ラベルのアドレスを決める
+    let mut symbols: Vec<Symbol> = vec![];
+    let mut defined_at = vec![];
+    let mut address = 0usize;
+    for statement in statements.iter() {
+        match label(statement.body) {
+            Some(name) => {
+                let column = statement.column + 1;
+                if let Err(message) = check_symbol(name) {
+                    errors.push(error(statement.line, column, message));
+                } else if is_predefined(name) {
+                    errors.push(error(
+                        statement.line,
+                        column,
+                        format!("`{}` is a predefined symbol.", name),
+                    ));
+                } else if let Some(i) = symbols.iter().position(|s| s.name == name) {
+                    errors.push(error(
+                        statement.line,
+                        column,
+                        format!(
+                            "duplicate label `{}`, first defined at line {}.",
+                            name, defined_at[i]
+                        ),
+                    ));
+                } else {
+                    symbols.push(Symbol {
+                        name: name.to_string(),
+                        address: address as u16,
+                        kind: SymbolKind::Label,
+                    });
+                    defined_at.push(statement.line);
+                }
+            }
+            None => address += 1,
+        }
+    }
+    if address > ROM_SIZE {
+        let last = statements.last().map_or(0, |s| s.line);
+        errors.push(error(
+            last,
+            0,
+            format!(
+                "program has {} instructions, ROM32K holds {}.",
+                address, ROM_SIZE
+            ),
+        ));
+    }
+
+    // 二周目: 命令を変換し、変数を割り当てる
+    let mut code = vec![];
+    let mut source = vec![];
+    let mut next_variable = VARIABLE_BASE;
+    for statement in statements.iter() {
+        if label(statement.body).is_some() {
+            source.push(SourceLine {
+                line: statement.line,
+                text: statement.text.to_string(),
+                address: None,
+            });
+            continue;
+        }
+        let result = match statement.body.strip_prefix('@') {
+            Some(value) => a_instruction(value, &mut symbols, &mut next_variable)
+                .map_err(|(offset, message)| (offset + 1, message)),
+            None => c_instruction(statement.body, extended),
+        };
+        match result {
+            Ok(word) => {
+                source.push(SourceLine {
+                    line: statement.line,
+                    text: statement.text.to_string(),
+                    address: Some(code.len() as u16),
+                });
+                code.push(Word::from(word));
+            }
+            Err((offset, message)) => {
+                errors.push(error(statement.line, statement.column + offset, message))
+            }
+        }
+    }
+
+    // 一周目と二周目のエラーを行の順に並べ直す
+    errors.sort_by_key(|e| (e.line, e.column));
+    if errors.is_empty() {
+        Ok(Program {
+            code,
+            source,
+            symbols,
+        })
+    } else {
+        Err(errors)
+    }
+}
+
+fn label(body: &str) -> Option<&str> {
+    body.strip_prefix('(').and_then(|b| b.strip_suffix(')'))
+}
+
+fn is_predefined(name: &str) -> bool {
+    PREDEFINED.iter().any(|(symbol, _)| *symbol == name) || register(name).is_some()
+}
+
+fn register(name: &str) -> Option<u16> {
+    match name.strip_prefix('R').and_then(|n| n.parse::<u16>().ok()) {
+        Some(n) if n < 16 && name.len() == if n < 10 { 2 } else { 3 } => Some(n),
+        _ => None,
+    }
+}
+
+// 英字、数字、_ . $ : からなり、数字で始まらない
+fn check_symbol(name: &str) -> Result<(), String> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || "_.$:".contains(c);
+    match name.chars().next() {
+        Some(c) if !c.is_ascii_digit() && name.chars().all(valid) => Ok(()),
+        _ => Err(format!("`{}` is not a valid symbol.", name)),
+    }
+}
+
+fn a_instruction(
+    value: &str,
+    symbols: &mut Vec<Symbol>,
+    next_variable: &mut u16,
+) -> Result<u16, (usize, String)> {
+    if value.starts_with(|c: char| c.is_ascii_digit()) {
+        return match value.parse::<u32>() {
+            Ok(n) if n <= 32767 => Ok(n as u16),
+            Ok(n) => Err((0, format!("constant {} is larger than 32767.", n))),
+            Err(_) => Err((0, format!("`{}` is not a constant.", value))),
+        };
+    }
+    check_symbol(value).map_err(|message| (0, message))?;
+    if let Some((_, address)) = PREDEFINED.iter().find(|(name, _)| *name == value) {
+        return Ok(*address);
+    }
+    if let Some(n) = register(value) {
+        return Ok(n);
+    }
+    if let Some(symbol) = symbols.iter().find(|s| s.name == value) {
+        return Ok(symbol.address);
+    }
+    if *next_variable == 16384 {
+        return Err((0, format!("no RAM left for variable `{}`.", value)));
+    }
+    let address = *next_variable;
+    symbols.push(Symbol {
+        name: value.to_string(),
+        address,
+        kind: SymbolKind::Variable,
+    });
+    *next_variable += 1;
+    Ok(address)
+}
+
+// dest=comp;jump。エラーの位置は本体の先頭からの文字数
+fn c_instruction(body: &str, extended: bool) -> Result<u16, (usize, String)> {
+    let (dest, rest, comp_at) = match body.find('=') {
+        Some(i) => (&body[..i], &body[i + 1..], i + 1),
+        None => ("", body, 0),
+    };
+    let (comp, jump, jump_at) = match rest.find(';') {
+        Some(i) => (&rest[..i], &rest[i + 1..], comp_at + i + 1),
+        None => (rest, "", 0),
+    };
+    let (dest, comp, jump) = (dest.trim(), comp.trim(), jump.trim());
+
+    let dest_bits = if dest.is_empty() {
+        0
+    } else {
+        parse_dest(dest).ok_or_else(|| (0, format!("unknown dest `{}`.", dest)))?
+    };
+    let find = |table: &[(u16, &str)]| {
+        table
+            .iter()
+            .find(|(_, name)| *name == comp)
+            .map(|(bits, _)| *bits)
+    };
+    let (prefix, comp_bits) = match (find(&COMPS), find(&SHIFTS)) {
+        (Some(bits), _) => (0xe000, bits),
+        (None, Some(bits)) if extended => (0xa000, bits),
+        (None, Some(_)) => {
+            return Err((
+                comp_at,
+                format!("`{}` needs the extended instruction set.", comp),
+            ))
+        }
+        (None, None) => return Err((comp_at, format!("unknown comp `{}`.", comp))),
+    };
+    let jump_bits = if jump.is_empty() {
+        0
+    } else {
+        JUMPS
+            .iter()
+            .position(|name| *name == jump)
+            .ok_or_else(|| (jump_at, format!("unknown jump `{}`.", jump)))? as u16
+    };
+    Ok(prefix | comp_bits << 6 | dest_bits << 3 | jump_bits)
+}
+
+// A, D, M の並びは問わない
+fn parse_dest(dest: &str) -> Option<u16> {
+    let mut bits = 0;
+    for c in dest.chars() {
+        let bit = match c {
+            'A' => 4,
+            'D' => 2,
+            'M' => 1,
+            _ => return None,
+        };
+        if bits & bit != 0 {
+            return None;
+        }
+        bits |= bit;
+    }
+    Some(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{assemble, assemble_with, SymbolKind};
+    use crate::emulator::Computer;
+    use crate::logic::Word;
+    use crate::rom::to_hack;
+
+    const MAX: &str = "// max(R0, R1) を R2 に
+   @R0
+   D=M              // D = first number
+   @R1
+   D=D-M
+   @OUTPUT_FIRST
+   D;JGT
+   @R1
+   D=M
+   @OUTPUT_D
+   0;JMP
+(OUTPUT_FIRST)
+   @R0
+   D=M
+(OUTPUT_D)
+   @max
+   M=D
+   @R2
+   MD=D|M
+(INFINITE_LOOP)
+   @INFINITE_LOOP
+   0;JMP
+";
+
+    #[test]
+    fn for_assemble() {
+        let program = assemble("Max.asm", MAX).unwrap();
+        assert_eq!(program.code.len(), 18);
+        assert_eq!(
+            to_hack(&program.code[..6]),
+            "0000000000000000\n1111110000010000\n0000000000000001\n\
+             1111010011010000\n0000000000001010\n1110001100000001\n"
+        );
+        assert_eq!(program.code[12], Word::from(16u16));
+        assert_eq!(program.code[15], Word::from(0b1111010101011000u16));
+
+        let mut computer = Computer::new(program.code.clone()).unwrap();
+        computer.poke(0, Word::from(3u16));
+        computer.poke(1, Word::from(9u16));
+        computer.run(100).unwrap();
+        assert!(computer.is_halted());
+        assert_eq!(computer.peek(2), Word::from(9u16));
+        assert_eq!(computer.peek(16), Word::from(9u16));
+
+        let kinds = program
+            .symbols
+            .iter()
+            .map(|s| (s.name.as_str(), s.address, s.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                ("OUTPUT_FIRST", 10, SymbolKind::Label),
+                ("OUTPUT_D", 12, SymbolKind::Label),
+                ("INFINITE_LOOP", 16, SymbolKind::Label),
+                ("max", 16, SymbolKind::Variable),
+            ]
+        );
+    }
+
+    #[test]
+    fn for_listing_and_symbols() {
+        let program = assemble("Loop.asm", "(LOOP)\n  @LOOP // again\n  0;JMP\n").unwrap();
+        let listing = program.to_listing();
+        let lines = listing.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "  ROM  binary            hex   source");
+        assert_eq!(lines[1].trim_start(), "(LOOP)");
+        assert_eq!(lines[1].len(), lines[2].find('@').unwrap() + "(LOOP)".len());
+        assert_eq!(lines[2], "    0  0000000000000000  0000  @LOOP // again");
+        assert_eq!(lines[3], "    1  1110101010000111  EA87  0;JMP");
+        assert_eq!(program.to_symbols(), "LOOP 0 label\n");
+    }
+
+    #[test]
+    fn for_errors() {
+        let src = "@40000\n(LOOP)\n  D=D+Q\n(LOOP)\n  0;JUMP\n  DD=A\n@3x\n(SP)\n";
+        let errors = assemble("Bad.asm", src)
+            .unwrap_err()
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            vec![
+                "Bad.asm:1:2: constant 40000 is larger than 32767.",
+                "Bad.asm:3:5: unknown comp `D+Q`.",
+                "Bad.asm:4:2: duplicate label `LOOP`, first defined at line 2.",
+                "Bad.asm:5:5: unknown jump `JUMP`.",
+                "Bad.asm:6:3: unknown dest `DD`.",
+                "Bad.asm:7:2: `3x` is not a constant.",
+                "Bad.asm:8:2: `SP` is a predefined symbol.",
+            ]
+        );
+    }
+
+    #[test]
+    fn for_extended() {
+        let src = "@R0\nD=M\nD=D<<\nM=D>>;JLT\nAM=M<<\n";
+        let code = assemble_with("Shift.asm", src, true).unwrap().code;
+        assert_eq!(
+            to_hack(&code[1..]),
+            "1111110000010000\n1010110000010000\n1010010000001100\n1011100000101000\n"
+        );
+        let errors = assemble("Shift.asm", src)
+            .unwrap_err()
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            vec![
+                "Shift.asm:3:3: `D<<` needs the extended instruction set.",
+                "Shift.asm:4:3: `D>>` needs the extended instruction set.",
+                "Shift.asm:5:4: `M<<` needs the extended instruction set.",
+            ]
+        );
+    }
+}
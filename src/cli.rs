@@ -0,0 +1,1003 @@
+#![allow(dead_code)]
+
+use crate::assembler;
+use crate::debugger::{self, Debugger};
+use crate::disasm;
+use crate::emulator::Computer;
+use crate::jack_compiler::{self, Options};
+use crate::keyboard::Keyboard;
+use crate::logic::bit;
+use crate::logic::Word;
+use crate::netlist::{Net, Netlist};
+use crate::translator;
+use crate::truth_table::{Format, TruthTable};
+use crate::{arithmetic, jack, jack_check, logic, rom, screen, snapshot, vm};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+
+pub const EXIT_OK: i32 = 0;
+// 実行時エラー、入力ファイルの誤り、テストの失敗
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+
+const USAGE: &str = "usage: nand2tetris-rs <command> [args]
+
+commands:
+  asm <file.asm> [--out FILE] [--listing FILE] [--symbols FILE] [--extended]
+                       assemble Hack assembly, optionally with shift instructions
+  vm <file.vm|dir> [--out FILE] [--optimize]
+                       translate VM code to Hack assembly
+  jackc <file.jack|dir> [--fold] [--prune] [--double]
+                       compile Jack to VM code, optionally optimizing it
+  run <file.hack|file.bin|file.snap> [--cycles N] [--keys FILE] [--screen FILE]
+      [--ram FROM[..TO]] [--save FILE] [--extended]
+                       run a program on the Hack computer, or resume a snapshot
+  debug <file.hack|file.bin> [--script FILE] [--symbols FILE] [--keys FILE]
+      [--extended]
+                       step through a program interactively or from a script
+  test <chip> [--format text|markdown|csv]
+                       print a chip's truth table and check its gates
+  disasm <file.hack|file.bin> [--labels] [--extended]
+                       disassemble a program, optionally recovering jump labels
+  help                 show this message
+";
+
+pub fn main(args: &[String], out: &mut dyn Write, err: &mut dyn Write) -> i32 {
+    let command = match args.first() {
+        Some(command) => command.as_str(),
+        None => {
+            let _ = write!(err, "{}", USAGE);
+            return EXIT_USAGE;
+        }
+    };
+    let args = &args[1..];
+    let result = match command {
+        "help" | "-h" | "--help" => {
+            let _ = write!(out, "{}", USAGE);
+            Ok(())
+        }
+        "run" => run(args, out),
+        "debug" => debug(args, out),
+        "test" => test(args, out),
+        "disasm" => disasm(args, out),
+        "asm" => asm(args, out, err),
+        "vm" => vm(args, out),
+        "jackc" => jackc(args, out, err),
+        _ => Err(Failure::Usage(format!("unknown command `{}`.", command))),
+    };
+    match result {
+        Ok(()) => EXIT_OK,
+        Err(failure) => {
+            let (code, message) = match failure {
+                Failure::Usage(message) => (EXIT_USAGE, message),
+                Failure::Error(message) => (EXIT_FAILURE, message),
+            };
+            let _ = writeln!(err, "nand2tetris-rs {}: error: {}", command, message);
+            if code == EXIT_USAGE {
+                let _ = write!(err, "{}", USAGE);
+            }
+            code
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Failure {
+    Usage(String),
+    Error(String),
+}
+
+type Args<'a> = (Vec<&'a str>, Vec<(&'a str, &'a str)>);
+
+// 位置引数と --name value 形式の引数に分ける。flags は値を取らず "" になる
+fn parse_args<'a>(
+    args: &'a [String],
+    options: &[&str],
+    flags: &[&str],
+) -> Result<Args<'a>, Failure> {
+    let mut positional = vec![];
+    let mut named = vec![];
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if let Some(name) = arg.strip_prefix("--") {
+            if flags.contains(&name) {
+                named.push((name, ""));
+                continue;
+            }
+            if !options.contains(&name) {
+                return Err(Failure::Usage(format!("unknown option `{}`.", arg)));
+            }
+            match iter.next() {
+                Some(value) => named.push((name, value.as_str())),
+                None => return Err(Failure::Usage(format!("`{}` needs a value.", arg))),
+            }
+        } else {
+            positional.push(arg.as_str());
+        }
+    }
+    Ok((positional, named))
+}
+
+fn one_file<'a>(positional: &[&'a str]) -> Result<&'a str, Failure> {
+    match positional {
+        [file] => Ok(file),
+        [] => Err(Failure::Usage("missing input file.".to_string())),
+        _ => Err(Failure::Usage("too many input files.".to_string())),
+    }
+}
+
+fn load_program(file: &str) -> Result<Vec<Word>, Failure> {
+    let result = if file.ends_with(".hack") {
+        rom::read_hack(file)
+    } else {
+        rom::read_binary(file)
+    };
+    result.map_err(|e| Failure::Error(format!("{}: {}", file, e)))
+}
+
+// "1 error" と "2 errors" を書き分ける
+fn errors_in(count: usize, file: &dyn fmt::Display) -> Failure {
+    let noun = if count == 1 { "error" } else { "errors" };
+    Failure::Error(format!("{} {} in {}.", count, noun, file))
+}
+
+// 診断は一つずつ stderr に書く
+fn asm(args: &[String], out: &mut dyn Write, err: &mut dyn Write) -> Result<(), Failure> {
+    let (positional, named) = parse_args(args, &["out", "listing", "symbols"], &["extended"])?;
+    let file = one_file(&positional)?;
+    let src = fs::read_to_string(file).map_err(|e| Failure::Error(format!("{}: {}", file, e)))?;
+    let extended = named.contains(&("extended", ""));
+    let program = match assembler::assemble_with(file, &src, extended) {
+        Ok(program) => program,
+        Err(errors) => {
+            for error in errors.iter() {
+                let _ = writeln!(err, "{}", error);
+            }
+            return Err(errors_in(errors.len(), &file));
+        }
+    };
+
+    let mut hack = Path::new(file).with_extension("hack");
+    for (name, value) in named {
+        let result = match name {
+            "out" => {
+                hack = Path::new(value).to_path_buf();
+                Ok(())
+            }
+            "listing" => fs::write(value, program.to_listing()),
+            "extended" => Ok(()),
+            _ => fs::write(value, program.to_symbols()),
+        };
+        result.map_err(|e| Failure::Error(format!("{}: {}", value, e)))?;
+    }
+    rom::write_hack(&hack, &program.code)
+        .map_err(|e| Failure::Error(format!("{}: {}", hack.display(), e)))?;
+    let _ = writeln!(
+        out,
+        "{}: {} instructions",
+        hack.display(),
+        program.code.len()
+    );
+    Ok(())
+}
+
+// ディレクトリなら中の .vm を名前順に全部読み、dir/dir.asm に書く
+fn vm(args: &[String], out: &mut dyn Write) -> Result<(), Failure> {
+    let (positional, named) = parse_args(args, &["out"], &["optimize"])?;
+    let input = Path::new(one_file(&positional)?);
+    let io_error = |path: &Path, e: io::Error| Failure::Error(format!("{}: {}", path.display(), e));
+    let (paths, mut asm) = if input.is_dir() {
+        let mut paths = fs::read_dir(input)
+            .map_err(|e| io_error(input, e))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|e| e == "vm"))
+            .collect::<Vec<_>>();
+        paths.sort();
+        let name = input.file_name().unwrap_or_default();
+        let asm = input.join(name).with_extension("asm");
+        (paths, asm)
+    } else {
+        (vec![input.to_path_buf()], input.with_extension("asm"))
+    };
+    if paths.is_empty() {
+        return Err(Failure::Error(format!(
+            "{}: no .vm files.",
+            input.display()
+        )));
+    }
+
+    let mut optimize = false;
+    for (name, value) in named {
+        match name {
+            "out" => asm = Path::new(value).to_path_buf(),
+            _ => optimize = true,
+        }
+    }
+    let mut sources = vec![];
+    for path in paths.iter() {
+        let src = fs::read_to_string(path).map_err(|e| io_error(path, e))?;
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+        sources.push((stem.to_string(), src));
+    }
+    let files = sources
+        .iter()
+        .map(|(stem, src)| (stem.as_str(), src.as_str()))
+        .collect::<Vec<_>>();
+    let program = vm::Program::parse(&files).map_err(|e| Failure::Error(e.to_string()))?;
+    fs::write(&asm, translator::translate(&program, optimize)).map_err(|e| io_error(&asm, e))?;
+    if optimize {
+        let _ = writeln!(out, "{}: {}", asm.display(), translator::report(&program));
+    } else {
+        let _ = writeln!(out, "{}", asm.display());
+    }
+    Ok(())
+}
+
+// 全クラスを読んで検査してから、X.jack ごとに隣の X.vm に書く
+fn jackc(args: &[String], out: &mut dyn Write, err: &mut dyn Write) -> Result<(), Failure> {
+    let (positional, named) = parse_args(args, &[], &["fold", "prune", "double"])?;
+    let input = Path::new(one_file(&positional)?);
+    let io_error = |path: &Path, e: io::Error| Failure::Error(format!("{}: {}", path.display(), e));
+    let paths = if input.is_dir() {
+        let mut paths = fs::read_dir(input)
+            .map_err(|e| io_error(input, e))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|e| e == "jack"))
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths
+    } else {
+        vec![input.to_path_buf()]
+    };
+    if paths.is_empty() {
+        return Err(Failure::Error(format!(
+            "{}: no .jack files.",
+            input.display()
+        )));
+    }
+
+    let mut options = Options::new();
+    for (name, _) in named {
+        options = match name {
+            "fold" => options.fold(true),
+            "prune" => options.prune(true),
+            _ => options.double(true),
+        };
+    }
+    let mut classes = vec![];
+    let mut errors = vec![];
+    for path in paths.iter() {
+        let src = fs::read_to_string(path).map_err(|e| io_error(path, e))?;
+        match jack::parse(&path.display().to_string(), &src) {
+            Ok(class) => classes.push(class),
+            Err(error) => errors.push(error),
+        }
+    }
+    if errors.is_empty() {
+        errors = jack_check::check(&classes);
+    }
+    if !errors.is_empty() {
+        for error in errors.iter() {
+            let _ = writeln!(err, "{}", error);
+        }
+        return Err(errors_in(errors.len(), &input.display()));
+    }
+    for (path, class) in paths.iter().zip(classes.iter()) {
+        let vm = path.with_extension("vm");
+        let code = jack_compiler::compile(class, &options);
+        fs::write(&vm, &code).map_err(|e| io_error(&vm, e))?;
+        let _ = writeln!(
+            out,
+            "{}: {} commands",
+            vm.display(),
+            jack_compiler::count(&code)
+        );
+    }
+    Ok(())
+}
+
+fn run(args: &[String], out: &mut dyn Write) -> Result<(), Failure> {
+    let (positional, named) = parse_args(
+        args,
+        &["cycles", "keys", "screen", "ram", "save"],
+        &["extended"],
+    )?;
+    let file = one_file(&positional)?;
+    let mut cycles = 1_000_000u64;
+    let mut extended = false;
+    let mut keyboard = None;
+    let mut image = None;
+    let mut save = None;
+    let mut ranges = vec![];
+    for (name, value) in named {
+        match name {
+            "cycles" => {
+                cycles = value
+                    .parse()
+                    .map_err(|_| Failure::Usage(format!("`{}` is not a cycle count.", value)))?
+            }
+            "keys" => {
+                let src = fs::read_to_string(value)
+                    .map_err(|e| Failure::Error(format!("{}: {}", value, e)))?;
+                keyboard = Some(
+                    Keyboard::from_script(&src)
+                        .map_err(|e| Failure::Error(format!("{}: {}", value, e)))?,
+                );
+            }
+            "screen" => image = Some(value),
+            "save" => save = Some(value),
+            "extended" => extended = true,
+            _ => ranges.push(parse_range(value)?),
+        }
+    }
+
+    // スナップショットからはキーボードの再生位置と命令セットごと再開する。--keys があれば差し替える
+    let mut computer = if file.ends_with(".snap") {
+        snapshot::read_file::<Computer, _>(file)
+            .map_err(|e| Failure::Error(format!("{}: {}", file, e)))?
+    } else {
+        Computer::new(load_program(file)?)
+            .map_err(|e| Failure::Error(format!("{}: {}", file, e)))?
+    };
+    if let Some(keyboard) = keyboard {
+        computer = computer.with_keyboard(keyboard);
+    }
+    if extended {
+        computer = computer.with_extended(true);
+    }
+    let executed = computer
+        .run(cycles)
+        .map_err(|e| Failure::Error(e.to_string()))?;
+
+    let _ = writeln!(out, "cycles: {}", executed);
+    let _ = writeln!(out, "halted: {}", computer.is_halted());
+    let _ = writeln!(out, "A: {}", computer.a().signed());
+    let _ = writeln!(out, "D: {}", computer.d().signed());
+    let _ = writeln!(out, "PC: {}", computer.pc());
+    for (from, to) in ranges {
+        for address in from..=to {
+            let value = computer.peek(address).signed();
+            let _ = writeln!(out, "RAM[{}]: {}", address, value);
+        }
+    }
+    if let Some(path) = image {
+        write_screen(path, screen::screen_region(computer.ram()))?;
+    }
+    if let Some(path) = save {
+        snapshot::write_file(path, &computer)
+            .map_err(|e| Failure::Error(format!("{}: {}", path, e)))?;
+    }
+    Ok(())
+}
+
+fn debug(args: &[String], out: &mut dyn Write) -> Result<(), Failure> {
+    let (positional, named) = parse_args(args, &["script", "symbols", "keys"], &["extended"])?;
+    let file = one_file(&positional)?;
+    let mut script = None;
+    let mut symbols = None;
+    let mut keyboard = Keyboard::new();
+    let mut extended = false;
+    for (name, value) in named {
+        if name == "extended" {
+            extended = true;
+            continue;
+        }
+        let src =
+            fs::read_to_string(value).map_err(|e| Failure::Error(format!("{}: {}", value, e)))?;
+        match name {
+            "script" => script = Some(src),
+            "symbols" => {
+                symbols = Some(
+                    debugger::parse_symbols(&src)
+                        .map_err(|e| Failure::Error(format!("{}: {}", value, e)))?,
+                )
+            }
+            _ => {
+                keyboard = Keyboard::from_script(&src)
+                    .map_err(|e| Failure::Error(format!("{}: {}", value, e)))?
+            }
+        }
+    }
+
+    let program = load_program(file)?;
+    // シンボルが無ければジャンプ先から復元したラベルを使う
+    let symbols = symbols.unwrap_or_else(|| {
+        let labels = if extended {
+            disasm::labels_extended(&program)
+        } else {
+            disasm::labels(&program)
+        };
+        labels
+            .into_iter()
+            .map(|(address, label)| (label, address))
+            .collect()
+    });
+    let computer = Computer::new(program)
+        .map_err(|e| Failure::Error(format!("{}: {}", file, e)))?
+        .with_keyboard(keyboard)
+        .with_extended(extended);
+    let mut debugger = Debugger::new(computer).with_symbols(symbols);
+    let result = match script {
+        Some(src) => debugger.repl(&mut src.as_bytes(), out, false),
+        None => debugger.repl(&mut io::stdin().lock(), out, true),
+    };
+    match result {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(message)) => Err(Failure::Error(message)),
+        Err(e) => Err(Failure::Error(e.to_string())),
+    }
+}
+
+fn disasm(args: &[String], out: &mut dyn Write) -> Result<(), Failure> {
+    let (positional, named) = parse_args(args, &[], &["labels", "extended"])?;
+    let file = one_file(&positional)?;
+    let program = load_program(file)?;
+    let listing = disasm::listing(
+        &program,
+        named.contains(&("labels", "")),
+        named.contains(&("extended", "")),
+    );
+    let _ = write!(out, "{}", listing.text);
+    match listing.invalid.as_slice() {
+        [] => Ok(()),
+        [address, ..] => Err(Failure::Error(format!(
+            "{}: {} instructions have no comp mnemonic, first at {}.",
+            file,
+            listing.invalid.len(),
+            address
+        ))),
+    }
+}
+
+fn parse_range(value: &str) -> Result<(usize, usize), Failure> {
+    let bad = || Failure::Usage(format!("`{}` is not an address or range.", value));
+    let mut bounds = value.splitn(2, "..");
+    let from = bounds
+        .next()
+        .unwrap_or("")
+        .parse::<usize>()
+        .map_err(|_| bad())?;
+    let to = match bounds.next() {
+        Some(to) => to.parse::<usize>().map_err(|_| bad())?,
+        None => from,
+    };
+    if from > to || to >= crate::emulator::RAM_SIZE {
+        return Err(bad());
+    }
+    Ok((from, to))
+}
+
+fn write_screen(path: &str, words: &[Word]) -> Result<(), Failure> {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("");
+    let result = match extension {
+        "pbm" => screen::write_pbm(path, words),
+        "ppm" => screen::write_ppm(path, words),
+        "png" => screen::write_png(path, words),
+        _ => {
+            return Err(Failure::Usage(format!(
+                "`{}`: screen images must be .pbm, .ppm or .png.",
+                path
+            )))
+        }
+    };
+    result.map_err(|e| Failure::Error(format!("{}: {}", path, e)))
+}
+
+fn test(args: &[String], out: &mut dyn Write) -> Result<(), Failure> {
+    let (positional, named) = parse_args(args, &["format"], &[])?;
+    let name = match positional.as_slice() {
+        [name] => *name,
+        [] => return Err(Failure::Usage("missing chip name.".to_string())),
+        _ => return Err(Failure::Usage("too many chip names.".to_string())),
+    };
+    let mut format = Format::Text;
+    for (_, value) in named {
+        format = match value {
+            "text" => Format::Text,
+            "markdown" => Format::Markdown,
+            "csv" => Format::Csv,
+            _ => return Err(Failure::Usage(format!("unknown format `{}`.", value))),
+        };
+    }
+    let chip = match CHIPS.iter().find(|chip| chip.name == name) {
+        Some(chip) => chip,
+        None => {
+            let names = CHIPS.iter().map(|chip| chip.name).collect::<Vec<_>>();
+            return Err(Failure::Usage(format!(
+                "unknown chip `{}`. chips: {}",
+                name,
+                names.join(", ")
+            )));
+        }
+    };
+
+    let mut n = Netlist::new();
+    let inputs = chip
+        .inputs
+        .iter()
+        .map(|name| n.input(name))
+        .collect::<Vec<_>>();
+    let outputs = n.capture(|| (chip.netlist)(&inputs));
+    for (name, net) in chip.outputs.iter().zip(outputs.iter()) {
+        n.output(name, *net);
+    }
+
+    let table = TruthTable::new(chip.inputs, chip.outputs, chip.function);
+    let _ = write!(out, "{}", table.render(format));
+    match table.check(|i| n.eval(i)) {
+        Ok(()) => Ok(()),
+        Err(mismatches) => Err(Failure::Error(format!(
+            "{} gate level rows differ, first at {}",
+            mismatches.len(),
+            mismatches[0]
+        ))),
+    }
+}
+
+struct Chip {
+    name: &'static str,
+    inputs: &'static [&'static str],
+    outputs: &'static [&'static str],
+    function: fn(&[bit]) -> Vec<bit>,
+    netlist: fn(&[Net]) -> Vec<Net>,
+}
+
+const CHIPS: [Chip; 12] = [
+    Chip {
+        name: "Nand",
+        inputs: &["a", "b"],
+        outputs: &["out"],
+        function: |i| vec![logic::Nand(i[0], i[1])],
+        netlist: |i| vec![logic::Nand(i[0], i[1])],
+    },
+    Chip {
+        name: "Not",
+        inputs: &["in"],
+        outputs: &["out"],
+        function: |i| vec![logic::Not(i[0])],
+        netlist: |i| vec![logic::Not(i[0])],
+    },
+    Chip {
+        name: "And",
+        inputs: &["a", "b"],
+        outputs: &["out"],
+        function: |i| vec![logic::And(i[0], i[1])],
+        netlist: |i| vec![logic::And(i[0], i[1])],
+    },
+    Chip {
+        name: "Or",
+        inputs: &["a", "b"],
+        outputs: &["out"],
+        function: |i| vec![logic::Or(i[0], i[1])],
+        netlist: |i| vec![logic::Or(i[0], i[1])],
+    },
+    Chip {
+        name: "Xor",
+        inputs: &["a", "b"],
+        outputs: &["out"],
+        function: |i| vec![logic::Xor(i[0], i[1])],
+        netlist: |i| vec![logic::Xor(i[0], i[1])],
+    },
+    Chip {
+        name: "Mux",
+        inputs: &["a", "b", "sel"],
+        outputs: &["out"],
+        function: |i| vec![logic::Mux(i[0], i[1], i[2])],
+        netlist: |i| vec![logic::Mux(i[0], i[1], i[2])],
+    },
+    Chip {
+        name: "DMux",
+        inputs: &["in", "sel"],
+        outputs: &["a", "b"],
+        function: |i| logic::DMux(i[0], i[1]).to_vec(),
+        netlist: |i| logic::DMux(i[0], i[1]).to_vec(),
+    },
+    Chip {
+        name: "Or8Way",
+        inputs: &["in0", "in1", "in2", "in3", "in4", "in5", "in6", "in7"],
+        outputs: &["out"],
+        function: |i| {
+            vec![logic::Or8Way([
+                i[0], i[1], i[2], i[3], i[4], i[5], i[6], i[7],
+            ])]
+        },
+        netlist: |i| {
+            vec![logic::Or8Way([
+                i[0], i[1], i[2], i[3], i[4], i[5], i[6], i[7],
+            ])]
+        },
+    },
+    Chip {
+        name: "DMux4Way",
+        inputs: &["in", "sel1", "sel0"],
+        outputs: &["a", "b", "c", "d"],
+        function: |i| logic::DMux4Way(i[0], [i[1], i[2]]).to_vec(),
+        netlist: |i| logic::DMux4Way(i[0], [i[1], i[2]]).to_vec(),
+    },
+    Chip {
+        name: "DMux8Way",
+        inputs: &["in", "sel2", "sel1", "sel0"],
+        outputs: &["a", "b", "c", "d", "e", "f", "g", "h"],
+        function: |i| logic::DMux8Way(i[0], [i[1], i[2], i[3]]).to_vec(),
+        netlist: |i| logic::DMux8Way(i[0], [i[1], i[2], i[3]]).to_vec(),
+    },
+    Chip {
+        name: "HalfAdder",
+        inputs: &["a", "b"],
+        outputs: &["carry", "sum"],
+        function: |i| arithmetic::HalfAdder(i[0], i[1]).to_vec(),
+        netlist: |i| arithmetic::HalfAdder(i[0], i[1]).to_vec(),
+    },
+    Chip {
+        name: "FullAdder",
+        inputs: &["a", "b", "c"],
+        outputs: &["carry", "sum"],
+        function: |i| arithmetic::FullAdder(i[0], i[1], i[2]).to_vec(),
+        netlist: |i| arithmetic::FullAdder(i[0], i[1], i[2]).to_vec(),
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::{main, EXIT_FAILURE, EXIT_OK, EXIT_USAGE};
+    use crate::logic::Word;
+    use crate::rom;
+    use std::env;
+    use std::fs;
+
+    fn call(args: &[&str]) -> (i32, String, String) {
+        let args = args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        let mut out = vec![];
+        let mut err = vec![];
+        let code = main(&args, &mut out, &mut err);
+        (
+            code,
+            String::from_utf8(out).unwrap(),
+            String::from_utf8(err).unwrap(),
+        )
+    }
+
+    #[test]
+    fn for_usage() {
+        let (code, out, err) = call(&[]);
+        assert_eq!(code, EXIT_USAGE);
+        assert_eq!(out, "");
+        assert!(err.starts_with("usage: nand2tetris-rs"));
+
+        let (code, out, _) = call(&["help"]);
+        assert_eq!(code, EXIT_OK);
+        assert!(out.starts_with("usage: nand2tetris-rs"));
+
+        let (code, _, err) = call(&["frobnicate"]);
+        assert_eq!(code, EXIT_USAGE);
+        assert!(
+            err.starts_with("nand2tetris-rs frobnicate: error: unknown command `frobnicate`.\n")
+        );
+    }
+
+    #[test]
+    fn for_test() {
+        let (code, out, err) = call(&["test", "Xor", "--format", "csv"]);
+        assert_eq!(code, EXIT_OK);
+        assert_eq!(out, "a,b,out\n0,0,0\n0,1,1\n1,0,1\n1,1,0\n");
+        assert_eq!(err, "");
+
+        let (code, _, err) = call(&["test", "Adder"]);
+        assert_eq!(code, EXIT_USAGE);
+        assert!(err.contains("unknown chip `Adder`."));
+
+        let (code, _, err) = call(&["test", "Xor", "--format", "html"]);
+        assert_eq!(code, EXIT_USAGE);
+        assert!(err.contains("unknown format `html`."));
+    }
+
+    #[test]
+    fn for_run() {
+        let dir = env::temp_dir().join(format!("nand2tetris-rs-cli-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let hack = dir.join("Add.hack");
+        let program = [2u16, 0xec10, 3, 0xe090, 0, 0xe308]
+            .iter()
+            .map(|i| Word::from(*i))
+            .collect::<Vec<_>>();
+        rom::write_hack(&hack, &program).unwrap();
+        let image = dir.join("screen.pbm");
+
+        let (code, out, err) = call(&[
+            "run",
+            hack.to_str().unwrap(),
+            "--ram",
+            "0..1",
+            "--screen",
+            image.to_str().unwrap(),
+        ]);
+        assert_eq!(err, "");
+        assert_eq!(code, EXIT_OK);
+        assert_eq!(
+            out,
+            "cycles: 6\nhalted: true\nA: 0\nD: 5\nPC: 6\nRAM[0]: 5\nRAM[1]: 0\n"
+        );
+        assert!(fs::read(&image).unwrap().starts_with(b"P4\n512 256\n"));
+
+        // 途中で保存して再開しても、一度に走らせたのと同じ結果になる
+        let snap = dir.join("Add.snap");
+        let (code, out, _) = call(&[
+            "run",
+            hack.to_str().unwrap(),
+            "--cycles",
+            "2",
+            "--save",
+            snap.to_str().unwrap(),
+        ]);
+        assert_eq!(code, EXIT_OK);
+        assert!(out.starts_with("cycles: 2\nhalted: false\n"));
+        let (code, out, _) = call(&["run", snap.to_str().unwrap(), "--ram", "0"]);
+        assert_eq!(code, EXIT_OK);
+        assert_eq!(
+            out,
+            "cycles: 4\nhalted: true\nA: 0\nD: 5\nPC: 6\nRAM[0]: 5\n"
+        );
+        let (code, _, err) = call(&["run", dir.join("Missing.snap").to_str().unwrap()]);
+        assert_eq!(code, EXIT_FAILURE);
+        assert!(err.contains("Missing.snap: "));
+        fs::write(dir.join("bad.snap"), "P4\n").unwrap();
+        let (code, _, err) = call(&["run", dir.join("bad.snap").to_str().unwrap()]);
+        assert_eq!(code, EXIT_FAILURE);
+        assert!(err.ends_with("bad.snap: not a snapshot.\n"));
+
+        let (code, _, err) = call(&["run", dir.join("Missing.hack").to_str().unwrap()]);
+        assert_eq!(code, EXIT_FAILURE);
+        assert!(err.starts_with("nand2tetris-rs run: error: "));
+
+        let (code, _, err) = call(&["run", hack.to_str().unwrap(), "--ram", "5..2"]);
+        assert_eq!(code, EXIT_USAGE);
+        assert!(err.contains("`5..2` is not an address or range."));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn for_asm() {
+        let dir = env::temp_dir().join(format!("nand2tetris-rs-asm-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let asm = dir.join("Add.asm");
+        fs::write(&asm, "@2\nD=A\n@3\nD=D+A\n@sum\nM=D\n(END)\n@END\n0;JMP\n").unwrap();
+        let listing = dir.join("Add.lst");
+        let symbols = dir.join("Add.sym");
+
+        let (code, out, err) = call(&[
+            "asm",
+            asm.to_str().unwrap(),
+            "--listing",
+            listing.to_str().unwrap(),
+            "--symbols",
+            symbols.to_str().unwrap(),
+        ]);
+        assert_eq!(err, "");
+        assert_eq!(code, EXIT_OK);
+        let hack = dir.join("Add.hack");
+        assert_eq!(out, format!("{}: 8 instructions\n", hack.display()));
+        assert_eq!(rom::read_hack(&hack).unwrap()[5], Word::from(0xe308u16));
+        assert_eq!(
+            fs::read_to_string(&symbols).unwrap(),
+            "END 6 label\nsum 16 variable\n"
+        );
+        assert!(fs::read_to_string(&listing)
+            .unwrap()
+            .contains("    6  0000000000000110  0006  @END\n"));
+
+        fs::write(&asm, "@2\nD=Q\n@99999\n").unwrap();
+        let (code, _, err) = call(&["asm", asm.to_str().unwrap()]);
+        assert_eq!(code, EXIT_FAILURE);
+        let file = asm.to_str().unwrap();
+        assert_eq!(
+            err,
+            format!(
+                "{0}:2:3: unknown comp `Q`.\n{0}:3:2: constant 99999 is larger than 32767.\n\
+                 nand2tetris-rs asm: error: 2 errors in {0}.\n",
+                file
+            )
+        );
+
+        // シフト命令は --extended を付けたときだけ。run と disasm も同じ
+        fs::write(&asm, "@3\nD=A\nD=D<<\n@0\nM=D\n").unwrap();
+        let (code, _, _) = call(&["asm", asm.to_str().unwrap()]);
+        assert_eq!(code, EXIT_FAILURE);
+        let (code, _, _) = call(&["asm", asm.to_str().unwrap(), "--extended"]);
+        assert_eq!(code, EXIT_OK);
+        let hack = hack.to_str().unwrap();
+        let (_, out, _) = call(&["run", hack, "--ram", "0", "--extended"]);
+        assert!(out.contains("RAM[0]: 6\n"));
+        let (_, out, _) = call(&["run", hack, "--ram", "0"]);
+        assert!(out.contains("RAM[0]: 3\n"));
+        // シフトの前で保存しても、再開するときは保存した命令セットのまま
+        let snap = dir.join("Shift.snap");
+        let snap = snap.to_str().unwrap();
+        let args = ["run", hack, "--cycles", "2", "--save", snap, "--extended"];
+        assert_eq!(call(&args).0, EXIT_OK);
+        let (_, out, _) = call(&["run", snap, "--ram", "0"]);
+        assert!(out.contains("RAM[0]: 6\n"));
+        let (code, out, _) = call(&["disasm", hack, "--extended"]);
+        assert_eq!(code, EXIT_OK);
+        assert_eq!(out, "@3\nD=A\nD=D<<\n@0\nM=D\n");
+        // debug も --extended のときだけシフト命令を実行して読む
+        let script = dir.join("script.txt");
+        fs::write(&script, "step 2\nlist 1\nstep\n").unwrap();
+        let script = script.to_str().unwrap();
+        let (code, out, _) = call(&["debug", hack, "--script", script, "--extended"]);
+        assert_eq!(code, EXIT_OK);
+        assert_eq!(
+            out,
+            "PC: 2  A: 3  D: 3  M: 0\n=> 2: D=D<<\n=> 2: D=D<<\n\
+             PC: 3  A: 3  D: 6  M: 0\n=> 3: @0\n"
+        );
+        let (code, out, _) = call(&["debug", hack, "--script", script]);
+        assert_eq!(code, EXIT_OK);
+        assert!(out.contains("=> 2: D=A\n"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn for_vm() {
+        let dir = env::temp_dir().join(format!("nand2tetris-rs-vm-{}", std::process::id()));
+        let project = dir.join("Seven");
+        fs::create_dir_all(&project).unwrap();
+        fs::write(
+            project.join("Main.vm"),
+            "function Main.main 0\npush constant 1\npush constant 6\nadd\nreturn\n",
+        )
+        .unwrap();
+        fs::write(
+            project.join("Sys.vm"),
+            "function Sys.init 0\ncall Main.main 0\npop temp 0\nlabel END\ngoto END\n",
+        )
+        .unwrap();
+
+        let (code, out, err) = call(&["vm", project.to_str().unwrap(), "--optimize"]);
+        assert_eq!(err, "");
+        assert_eq!(code, EXIT_OK);
+        let asm = project.join("Seven.asm");
+        assert!(out.starts_with(&format!("{}: ROM size: ", asm.display())));
+
+        let hack = dir.join("Seven.hack");
+        let (code, _, _) = call(&[
+            "asm",
+            asm.to_str().unwrap(),
+            "--out",
+            hack.to_str().unwrap(),
+        ]);
+        assert_eq!(code, EXIT_OK);
+        let (code, out, _) = call(&["run", hack.to_str().unwrap(), "--ram", "5"]);
+        assert_eq!(code, EXIT_OK);
+        assert!(out.ends_with("RAM[5]: 7\n"));
+
+        let (code, _, err) = call(&["vm", dir.to_str().unwrap()]);
+        assert_eq!(code, EXIT_FAILURE);
+        assert!(err.ends_with("no .vm files.\n"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn for_jackc() {
+        let dir = env::temp_dir().join(format!("nand2tetris-rs-jackc-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("Main.jack"),
+            "class Main { function int main() { return 3 * 4 + 1; } }",
+        )
+        .unwrap();
+
+        let (code, out, err) = call(&["jackc", dir.to_str().unwrap(), "--fold"]);
+        assert_eq!(err, "");
+        assert_eq!(code, EXIT_OK);
+        let vm = dir.join("Main.vm");
+        assert_eq!(out, format!("{}: 3 commands\n", vm.display()));
+        assert_eq!(
+            fs::read_to_string(&vm).unwrap(),
+            "function Main.main 0\npush constant 13\nreturn\n"
+        );
+
+        fs::write(
+            dir.join("Main.jack"),
+            "class Main { function int main() { return Main.missing(); } }",
+        )
+        .unwrap();
+        let (code, _, err) = call(&["jackc", dir.join("Main.jack").to_str().unwrap()]);
+        assert_eq!(code, EXIT_FAILURE);
+        assert!(err.contains("Main.jack:1:"));
+        assert!(err.ends_with(&format!(
+            "1 error in {}.\n",
+            dir.join("Main.jack").display()
+        )));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn for_disasm() {
+        let dir = env::temp_dir().join(format!("nand2tetris-rs-disasm-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let bin = dir.join("Loop.bin");
+        let program = [2u16, 0xea87, 2, 0xea87]
+            .iter()
+            .map(|i| Word::from(*i))
+            .collect::<Vec<_>>();
+        rom::write_binary(&bin, &program).unwrap();
+
+        let (code, out, _) = call(&["disasm", bin.to_str().unwrap()]);
+        assert_eq!(code, EXIT_OK);
+        assert_eq!(out, "@2\n0;JMP\n@2\n0;JMP\n");
+        let (code, out, _) = call(&["disasm", bin.to_str().unwrap(), "--labels"]);
+        assert_eq!(code, EXIT_OK);
+        assert_eq!(out, "@L2\n0;JMP\n(L2)\n@L2\n0;JMP\n");
+
+        rom::write_binary(&bin, &[Word::from(0xef80u16)]).unwrap();
+        let (code, out, err) = call(&["disasm", bin.to_str().unwrap()]);
+        assert_eq!(code, EXIT_FAILURE);
+        assert_eq!(
+            out,
+            "// 0: 1110111110000000 has no comp mnemonic for 0111110.\n"
+        );
+        assert!(err.ends_with("1 instructions have no comp mnemonic, first at 0.\n"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn for_debug() {
+        let dir = env::temp_dir().join(format!("nand2tetris-rs-debug-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let hack = dir.join("Add.hack");
+        let program = [2u16, 0xec10, 3, 0xe090, 0, 0xe308]
+            .iter()
+            .map(|i| Word::from(*i))
+            .collect::<Vec<_>>();
+        rom::write_hack(&hack, &program).unwrap();
+        let symbols = dir.join("Add.sym");
+        fs::write(&symbols, "STORE 4\n").unwrap();
+        let script = dir.join("script.txt");
+        fs::write(&script, "break STORE\ncontinue\nstep 2\nx 0\n").unwrap();
+
+        let (code, out, err) = call(&[
+            "debug",
+            hack.to_str().unwrap(),
+            "--symbols",
+            symbols.to_str().unwrap(),
+            "--script",
+            script.to_str().unwrap(),
+        ]);
+        assert_eq!(err, "");
+        assert_eq!(code, EXIT_OK);
+        assert_eq!(
+            out,
+            "breakpoint at 4 (STORE)\n\
+             breakpoint at 4 (STORE)\n\
+             PC: 4  A: 3  D: 5  M: 0\n=> 4 (STORE): @0\n\
+             PC: 6  A: 0  D: 5  M: 5\n\
+             RAM[0]: 5\n"
+        );
+
+        fs::write(&script, "step\nstep x\n").unwrap();
+        let (code, _, err) = call(&[
+            "debug",
+            hack.to_str().unwrap(),
+            "--script",
+            script.to_str().unwrap(),
+        ]);
+        assert_eq!(code, EXIT_FAILURE);
+        assert_eq!(
+            err,
+            "nand2tetris-rs debug: error: line 2: `x` is not a count.\n"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
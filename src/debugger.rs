@@ -0,0 +1,476 @@
+#![allow(dead_code)]
+
+use crate::disasm::{disassemble, disassemble_extended};
+use crate::emulator::{Computer, RAM_SIZE};
+use crate::logic::Word;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::io::{BufRead, Write};
+
+pub const PROMPT: &str = "(hack) ";
+
+const HELP: &str = "commands:
+  step [N]              run N instructions (default 1)
+  continue [N]          run until a breakpoint, a watchpoint or halt (at most N cycles)
+  break <pc|label>      stop before the instruction at pc
+  delete <pc|label>     remove a breakpoint
+  watch <addr>          stop when RAM[addr] changes
+  unwatch <addr>        remove a watchpoint
+  info                  list breakpoints and watchpoints
+  print                 show A, D, PC, M and the next instruction
+  x <addr> [N]          show N words of RAM from addr
+  list [N]              disassemble N instructions from pc
+  set <A|D|PC|addr> <value>
+                        change a register or RAM (value may be 0x1F, %B101 or %D-1)
+  reset                 set pc to 0
+  quit                  leave the debugger
+";
+
+const COMMANDS: [&str; 24] = [
+    "step", "s", "continue", "c", "break", "b", "delete", "d", "watch", "w", "unwatch", "info",
+    "i", "print", "p", "x", "list", "l", "set", "reset", "help", "h", "quit", "q",
+];
+
+const MAX_CYCLES: u64 = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    Continue,
+    Quit,
+}
+
+pub struct Debugger {
+    computer: Computer,
+    symbols: BTreeMap<String, u16>,
+    breakpoints: BTreeSet<u16>,
+    // 監視しているアドレスと最後に見た値
+    watchpoints: BTreeMap<usize, Word>,
+}
+
+impl Debugger {
+    pub fn new(computer: Computer) -> Self {
+        Debugger {
+            computer,
+            symbols: BTreeMap::new(),
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_symbols(mut self, symbols: BTreeMap<String, u16>) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    pub fn computer(&self) -> &Computer {
+        &self.computer
+    }
+
+    pub fn breakpoints(&self) -> &BTreeSet<u16> {
+        &self.breakpoints
+    }
+
+    // 一行のコマンドを実行する。エラーはメッセージで返す
+    pub fn execute(&mut self, line: &str, out: &mut dyn Write) -> Result<Control, String> {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let (command, args) = match words.split_first() {
+            Some((command, args)) => (*command, args),
+            None => return Ok(Control::Continue),
+        };
+        match (command, args) {
+            ("step" | "s", [] | [_]) => {
+                let count = optional_count(args, 1)?;
+                self.step(count, false, out)?;
+            }
+            ("continue" | "c", [] | [_]) => {
+                let count = optional_count(args, MAX_CYCLES)?;
+                self.step(count, true, out)?;
+            }
+            ("break" | "b", [at]) => {
+                let pc = self.location(at)?;
+                self.breakpoints.insert(pc);
+                let _ = writeln!(out, "breakpoint at {}", self.describe(pc));
+            }
+            ("delete" | "d", [at]) => {
+                let pc = self.location(at)?;
+                if !self.breakpoints.remove(&pc) {
+                    return Err(format!("no breakpoint at {}.", at));
+                }
+            }
+            ("watch" | "w", [address]) => {
+                let address = parse_address(address)?;
+                let value = self.computer.peek(address);
+                self.watchpoints.insert(address, value);
+                let _ = writeln!(out, "watchpoint on RAM[{}] = {}", address, value.signed());
+            }
+            ("unwatch", [address]) => {
+                let address = parse_address(address)?;
+                if self.watchpoints.remove(&address).is_none() {
+                    return Err(format!("no watchpoint on RAM[{}].", address));
+                }
+            }
+            ("info" | "i", []) => {
+                for pc in self.breakpoints.iter() {
+                    let _ = writeln!(out, "breakpoint at {}", self.describe(*pc));
+                }
+                for address in self.watchpoints.keys() {
+                    let _ = writeln!(out, "watchpoint on RAM[{}]", address);
+                }
+            }
+            ("print" | "p", []) => self.print(out),
+            ("x", [address] | [address, _]) => {
+                let from = parse_address(address)?;
+                let count = optional_count(&args[1..], 1)? as usize;
+                let to = from.saturating_add(count).min(RAM_SIZE);
+                for address in from..to {
+                    let value = self.computer.peek(address).signed();
+                    let _ = writeln!(out, "RAM[{}]: {}", address, value);
+                }
+            }
+            ("list" | "l", [] | [_]) => {
+                let count = optional_count(args, 10)? as usize;
+                let pc = self.computer.pc() as usize;
+                let rom = self.computer.rom().iter().enumerate();
+                for (address, instruction) in rom.skip(pc).take(count) {
+                    let mark = if address == pc { "=>" } else { "  " };
+                    let _ = writeln!(
+                        out,
+                        "{} {}: {}",
+                        mark,
+                        self.describe(address as u16),
+                        self.decode(*instruction)
+                    );
+                }
+            }
+            ("set", [target, value]) => {
+                let value = parse_value(value)?;
+                match target.to_uppercase().as_str() {
+                    "A" => self.computer.set_a(value),
+                    "D" => self.computer.set_d(value),
+                    "PC" => self.computer.set_pc(u16::from(value)),
+                    _ => {
+                        let address = parse_address(target)?;
+                        self.computer.poke(address, value);
+                        if let Some(seen) = self.watchpoints.get_mut(&address) {
+                            *seen = value;
+                        }
+                    }
+                }
+            }
+            ("reset", []) => self.computer.reset(),
+            ("help" | "h", []) => {
+                let _ = write!(out, "{}", HELP);
+            }
+            ("quit" | "q", []) => return Ok(Control::Quit),
+            _ if COMMANDS.contains(&command) => {
+                return Err(format!("wrong arguments for `{}`. try `help`.", command))
+            }
+            _ => return Err(format!("unknown command `{}`. try `help`.", command)),
+        }
+        Ok(Control::Continue)
+    }
+
+    // 対話モードではエラーを表示して続け、バッチモードでは最初のエラーで止まる
+    pub fn repl(
+        &mut self,
+        input: &mut dyn BufRead,
+        out: &mut dyn Write,
+        interactive: bool,
+    ) -> io::Result<Result<(), String>> {
+        let mut number = 0;
+        loop {
+            if interactive {
+                write!(out, "{}", PROMPT)?;
+                out.flush()?;
+            }
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(Ok(()));
+            }
+            number += 1;
+            let line = line.split('#').next().unwrap_or("");
+            match self.execute(line, out) {
+                Ok(Control::Continue) => (),
+                Ok(Control::Quit) => return Ok(Ok(())),
+                Err(message) if interactive => writeln!(out, "error: {}", message)?,
+                Err(message) => return Ok(Err(format!("line {}: {}", number, message))),
+            }
+        }
+    }
+
+    // breakpoint のときは最初の一命令を実行してから調べる
+    fn step(
+        &mut self,
+        count: u64,
+        stop_at_breakpoint: bool,
+        out: &mut dyn Write,
+    ) -> Result<(), String> {
+        for i in 0..count {
+            if self.computer.is_halted() {
+                let _ = writeln!(out, "halted at {}", self.describe(self.computer.pc()));
+                break;
+            }
+            if stop_at_breakpoint && i > 0 && self.breakpoints.contains(&self.computer.pc()) {
+                let _ = writeln!(out, "breakpoint at {}", self.describe(self.computer.pc()));
+                break;
+            }
+            self.computer.step().map_err(|e| e.to_string())?;
+            if self.check_watchpoints(out) {
+                break;
+            }
+        }
+        self.print(out);
+        Ok(())
+    }
+
+    fn check_watchpoints(&mut self, out: &mut dyn Write) -> bool {
+        let mut hit = false;
+        for (address, seen) in self.watchpoints.iter_mut() {
+            let value = self.computer.peek(*address);
+            if value != *seen {
+                let _ = writeln!(
+                    out,
+                    "watchpoint RAM[{}]: {} -> {}",
+                    address,
+                    seen.signed(),
+                    value.signed()
+                );
+                *seen = value;
+                hit = true;
+            }
+        }
+        hit
+    }
+
+    fn print(&self, out: &mut dyn Write) {
+        let a = u16::from(self.computer.a());
+        let m = if (a as usize) < RAM_SIZE {
+            self.computer.peek(a as usize).signed().to_string()
+        } else {
+            "-".to_string()
+        };
+        let _ = writeln!(
+            out,
+            "PC: {}  A: {}  D: {}  M: {}",
+            self.computer.pc(),
+            a as i16,
+            self.computer.d().signed(),
+            m
+        );
+        let pc = self.computer.pc() as usize;
+        if let Some(instruction) = self.computer.rom().get(pc) {
+            let _ = writeln!(
+                out,
+                "=> {}: {}",
+                self.describe(pc as u16),
+                self.decode(*instruction)
+            );
+        }
+    }
+
+    // シフト命令を実行するコンピュータなら、表示もシフト命令として読む
+    fn decode(&self, instruction: Word) -> String {
+        if self.computer.is_extended() {
+            disassemble_extended(instruction)
+        } else {
+            disassemble(instruction)
+        }
+    }
+
+    fn location(&self, at: &str) -> Result<u16, String> {
+        if let Some(pc) = self.symbols.get(at) {
+            return Ok(*pc);
+        }
+        at.parse::<u16>()
+            .map_err(|_| format!("`{}` is neither an address nor a known label.", at))
+    }
+
+    // "5" または "5 (LOOP)"
+    fn describe(&self, pc: u16) -> String {
+        match self.symbols.iter().find(|(_, address)| **address == pc) {
+            Some((label, _)) => format!("{} ({})", pc, label),
+            None => pc.to_string(),
+        }
+    }
+}
+
+// 一行に「ラベル アドレス」。アセンブラのシンボルマップの変数 (RAM のアドレス) は読み飛ばす
+pub fn parse_symbols(src: &str) -> Result<BTreeMap<String, u16>, String> {
+    let mut symbols = BTreeMap::new();
+    for (index, line) in src.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        match fields.as_slice() {
+            [_, _, "variable"] => (),
+            [label, address] | [label, address, "label"] => match address.parse::<u16>() {
+                Ok(address) => {
+                    symbols.insert(label.to_string(), address);
+                }
+                Err(_) => {
+                    return Err(format!(
+                        "line {}: `{}` is not an address.",
+                        index + 1,
+                        address
+                    ))
+                }
+            },
+            _ => return Err(format!("line {}: expected `LABEL address`.", index + 1)),
+        }
+    }
+    Ok(symbols)
+}
+
+fn optional_count(args: &[&str], default: u64) -> Result<u64, String> {
+    match args.first() {
+        Some(count) => count
+            .parse()
+            .map_err(|_| format!("`{}` is not a count.", count)),
+        None => Ok(default),
+    }
+}
+
+fn parse_address(address: &str) -> Result<usize, String> {
+    let address = address
+        .strip_prefix("RAM[")
+        .and_then(|a| a.strip_suffix(']'))
+        .unwrap_or(address);
+    match address.parse::<usize>() {
+        Ok(address) if address < RAM_SIZE => Ok(address),
+        _ => Err(format!("`{}` is not a RAM address.", address)),
+    }
+}
+
+// -32768..=65535 の 10 進数か、0x1F や %B101 のような書き方を受け付ける
+fn parse_value(value: &str) -> Result<Word, String> {
+    Word::parse_literal(value).map_err(|_| format!("`{}` is not a 16 bit value.", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_symbols, Control, Debugger};
+    use crate::emulator::Computer;
+    use crate::logic::Word;
+    use crate::rom::parse_hack;
+
+    // for (i = RAM[0]; i > 0; i--) RAM[1] += i
+    const SUM: &str = "0000000000000000\n1111110000010000\n0000000000001010\n\
+                       1110001100000110\n0000000000000001\n1111000010001000\n\
+                       0000000000000000\n1111110010001000\n0000000000000000\n\
+                       1110101010000111\n0000000000001010\n1110101010000111\n";
+
+    fn debugger() -> Debugger {
+        let computer = Computer::new(parse_hack(SUM).unwrap()).unwrap();
+        let symbols = parse_symbols("LOOP 0 label\nEND 10\ni 16 variable\n").unwrap();
+        assert_eq!(symbols.len(), 2);
+        Debugger::new(computer).with_symbols(symbols)
+    }
+
+    fn run(debugger: &mut Debugger, script: &str) -> (String, Result<(), String>) {
+        let mut out = vec![];
+        let result = debugger
+            .repl(&mut script.as_bytes(), &mut out, false)
+            .unwrap();
+        (String::from_utf8(out).unwrap(), result)
+    }
+
+    #[test]
+    fn for_step_and_print() {
+        let mut debugger = debugger();
+        let (out, result) = run(&mut debugger, "set RAM[0] 3\nstep 2\nlist 2\n");
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            out,
+            "PC: 2  A: 0  D: 3  M: 3\n=> 2: @10\n=> 2: @10\n   3: D;JLE\n"
+        );
+    }
+
+    #[test]
+    fn for_breakpoints() {
+        let mut debugger = debugger();
+        let (out, result) = run(&mut debugger, "set 0 3\nbreak LOOP\ncontinue\ncontinue\n");
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            out,
+            "breakpoint at 0 (LOOP)\n\
+             breakpoint at 0 (LOOP)\n\
+             PC: 0  A: 0  D: 3  M: 2\n=> 0 (LOOP): @0\n\
+             breakpoint at 0 (LOOP)\n\
+             PC: 0  A: 0  D: 2  M: 1\n=> 0 (LOOP): @0\n"
+        );
+        let (out, _) = run(&mut debugger, "delete LOOP\ncontinue\nx 0 2\n");
+        assert!(out.contains("halted at 10 (END)\n"));
+        assert!(out.ends_with("RAM[0]: 0\nRAM[1]: 6\n"));
+        // 大きすぎる個数は RAM の終わりで止める
+        let (out, result) = run(&mut debugger, "x 24575 18446744073709551615\n");
+        assert_eq!(result, Ok(()));
+        assert_eq!(out, "RAM[24575]: 0\nRAM[24576]: 0\n");
+    }
+
+    #[test]
+    fn for_watchpoints() {
+        let mut debugger = debugger();
+        let (out, _) = run(&mut debugger, "set 0 2\nwatch 1\ncontinue\ninfo\n");
+        assert_eq!(
+            out,
+            "watchpoint on RAM[1] = 0\n\
+             watchpoint RAM[1]: 0 -> 2\n\
+             PC: 6  A: 1  D: 2  M: 2\n=> 6: @0\n\
+             watchpoint on RAM[1]\n"
+        );
+        assert_eq!(debugger.computer().peek(1), Word::from(2u16));
+    }
+
+    // シフト命令を実行するコンピュータでは list も print もシフト命令として読む
+    #[test]
+    fn for_extended() {
+        let program = parse_hack("1010110000010000\n").unwrap();
+        let computer = Computer::new(program.clone()).unwrap();
+        let (out, _) = run(&mut Debugger::new(computer), "list 1\n");
+        assert_eq!(out, "=> 0: D=A\n");
+        let computer = Computer::new(program).unwrap().with_extended(true);
+        let (out, _) = run(&mut Debugger::new(computer), "list 1\nprint\n");
+        assert_eq!(out, "=> 0: D=D<<\nPC: 0  A: 0  D: 0  M: 0\n=> 0: D=D<<\n");
+    }
+
+    #[test]
+    fn for_errors() {
+        let mut debugger = debugger();
+        let (_, result) = run(&mut debugger, "print\nbreak NOWHERE\nquit\n");
+        assert_eq!(
+            result,
+            Err("line 2: `NOWHERE` is neither an address nor a known label.".to_string())
+        );
+        let mut out = vec![];
+        assert_eq!(
+            debugger.execute("set 99999 1", &mut out),
+            Err("`99999` is not a RAM address.".to_string())
+        );
+        assert_eq!(
+            debugger.execute("set A %X10000", &mut out),
+            Err("`%X10000` is not a 16 bit value.".to_string())
+        );
+        assert_eq!(
+            debugger.execute("set A 0x7FFF", &mut out),
+            Ok(Control::Continue)
+        );
+        assert_eq!(debugger.computer().a(), Word::from(32767u16));
+        assert_eq!(
+            debugger.execute("step 1 2", &mut out),
+            Err("wrong arguments for `step`. try `help`.".to_string())
+        );
+        assert_eq!(debugger.execute("quit", &mut out), Ok(Control::Quit));
+
+        // 対話モードでは続ける
+        let mut out = vec![];
+        let result = debugger
+            .repl(&mut "frob\nset D -1\np\n".as_bytes(), &mut out, true)
+            .unwrap();
+        assert_eq!(result, Ok(()));
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("(hack) error: unknown command `frob`. try `help`.\n"));
+        assert!(out.contains("D: -1"));
+    }
+}
@@ -0,0 +1,278 @@
+#![allow(dead_code)]
+
+use crate::logic::Word;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+// a ビットと c1..c6 の 7 ビットに対応する comp
+pub const COMPS: [(u16, &str); 28] = [
+    (0b0101010, "0"),
+    (0b0111111, "1"),
+    (0b0111010, "-1"),
+    (0b0001100, "D"),
+    (0b0110000, "A"),
+    (0b0001101, "!D"),
+    (0b0110001, "!A"),
+    (0b0001111, "-D"),
+    (0b0110011, "-A"),
+    (0b0011111, "D+1"),
+    (0b0110111, "A+1"),
+    (0b0001110, "D-1"),
+    (0b0110010, "A-1"),
+    (0b0000010, "D+A"),
+    (0b0010011, "D-A"),
+    (0b0000111, "A-D"),
+    (0b0000000, "D&A"),
+    (0b0010101, "D|A"),
+    (0b1110000, "M"),
+    (0b1110001, "!M"),
+    (0b1110011, "-M"),
+    (0b1110111, "M+1"),
+    (0b1110010, "M-1"),
+    (0b1000010, "D+M"),
+    (0b1010011, "D-M"),
+    (0b1000111, "M-D"),
+    (0b1000000, "D&M"),
+    (0b1010101, "D|M"),
+];
+
+// 拡張命令 (先頭が 101) の a c1 c2 c3 c4 c5 c6。c1 が左、c2 が D を選び、右は算術シフト
+pub const SHIFTS: [(u16, &str); 6] = [
+    (0b0100000, "A<<"),
+    (0b0110000, "D<<"),
+    (0b1100000, "M<<"),
+    (0b0000000, "A>>"),
+    (0b0010000, "D>>"),
+    (0b1000000, "M>>"),
+];
+
+pub const DESTS: [&str; 8] = ["", "M", "D", "MD", "A", "AM", "AD", "AMD"];
+pub const JUMPS: [&str; 8] = ["", "JGT", "JEQ", "JGE", "JLT", "JNE", "JLE", "JMP"];
+
+pub fn comp(bits: u16) -> Option<&'static str> {
+    COMPS
+        .iter()
+        .find(|(code, _)| *code == bits & 0x7f)
+        .map(|(_, name)| *name)
+}
+
+pub fn shift(bits: u16) -> Option<&'static str> {
+    SHIFTS
+        .iter()
+        .find(|(code, _)| *code == bits & 0x7f)
+        .map(|(_, name)| *name)
+}
+
+// 拡張命令として読むのは先頭 3 ビットが 101 のものだけ
+pub fn is_shift(instruction: u16) -> bool {
+    instruction & 0xe000 == 0xa000
+}
+
+// comp に対応する mnemonic が無い C 命令
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidComp(pub u16);
+
+impl Display for InvalidComp {
+    fn fmt(&self, dest: &mut Formatter) -> fmt::Result {
+        write!(
+            dest,
+            "{:016b} has no comp mnemonic for {:07b}.",
+            self.0,
+            (self.0 >> 6) & 0x7f
+        )
+    }
+}
+
+pub fn decode(instruction: Word) -> Result<String, InvalidComp> {
+    decode_with(instruction, None, false)
+}
+
+pub fn decode_extended(instruction: Word) -> Result<String, InvalidComp> {
+    decode_with(instruction, None, true)
+}
+
+// 読めない命令は 2 進数のまま印を付けて返す
+pub fn disassemble(instruction: Word) -> String {
+    decode(instruction).unwrap_or_else(|e| format!("{:016b} (invalid)", e.0))
+}
+
+pub fn disassemble_extended(instruction: Word) -> String {
+    decode_extended(instruction).unwrap_or_else(|e| format!("{:016b} (invalid)", e.0))
+}
+
+// @n のすぐ後に飛び先のある C 命令が続くとき、n をラベルとみなす
+pub fn labels(program: &[Word]) -> BTreeMap<u16, String> {
+    labels_with(program, false)
+}
+
+pub fn labels_extended(program: &[Word]) -> BTreeMap<u16, String> {
+    labels_with(program, true)
+}
+
+fn labels_with(program: &[Word], extended: bool) -> BTreeMap<u16, String> {
+    let mut labels = BTreeMap::new();
+    for pair in program.windows(2) {
+        let a = u16::from(pair[0]);
+        if a & 0x8000 == 0 && is_jump(pair[1], extended) && (a as usize) < program.len() {
+            labels.insert(a, format!("L{}", a));
+        }
+    }
+    labels
+}
+
+fn is_jump(instruction: Word, extended: bool) -> bool {
+    let c = u16::from(instruction);
+    c & 0x8000 != 0 && c & 7 != 0 && comp_of(c, extended).is_some()
+}
+
+fn comp_of(instruction: u16, extended: bool) -> Option<&'static str> {
+    if extended && is_shift(instruction) {
+        shift(instruction >> 6)
+    } else {
+        comp(instruction >> 6)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Listing {
+    pub text: String,
+    // comp が読めなかった命令のアドレス
+    pub invalid: Vec<usize>,
+}
+
+// 読めない命令はコメントとして残すので、その場合はアドレスがずれる。
+// ラベルに置き換えるのは飛び先を指す @n だけで、データを読む @n は数のまま
+pub fn listing(program: &[Word], recover_labels: bool, extended: bool) -> Listing {
+    let labels = if recover_labels {
+        labels_with(program, extended)
+    } else {
+        BTreeMap::new()
+    };
+    let mut text = String::new();
+    let mut invalid = vec![];
+    for (address, instruction) in program.iter().enumerate() {
+        if let Some(label) = labels.get(&(address as u16)) {
+            text.push_str(&format!("({})\n", label));
+        }
+        let jumps = program
+            .get(address + 1)
+            .is_some_and(|next| is_jump(*next, extended));
+        let label = if jumps {
+            labels.get(&u16::from(*instruction)).map(|l| l.as_str())
+        } else {
+            None
+        };
+        match decode_with(*instruction, label, extended) {
+            Ok(line) => text.push_str(&format!("{}\n", line)),
+            Err(e) => {
+                text.push_str(&format!("// {}: {}\n", address, e));
+                invalid.push(address);
+            }
+        }
+    }
+    Listing { text, invalid }
+}
+
+// label は A 命令をラベルで書くときの名前
+fn decode_with(
+    instruction: Word,
+    label: Option<&str>,
+    extended: bool,
+) -> Result<String, InvalidComp> {
+    let instruction = u16::from(instruction);
+    if instruction & 0x8000 == 0 {
+        return Ok(match label {
+            Some(label) => format!("@{}", label),
+            None => format!("@{}", instruction),
+        });
+    }
+    let comp = comp_of(instruction, extended).ok_or(InvalidComp(instruction))?;
+    let dest = DESTS[((instruction >> 3) & 7) as usize];
+    let jump = JUMPS[(instruction & 7) as usize];
+    let mut text = String::new();
+    if !dest.is_empty() {
+        text.push_str(dest);
+        text.push('=');
+    }
+    text.push_str(comp);
+    if !jump.is_empty() {
+        text.push(';');
+        text.push_str(jump);
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, decode_extended, disassemble, labels, listing, InvalidComp};
+    use crate::logic::Word;
+
+    #[test]
+    fn for_disassemble() {
+        let text = |i: u16| disassemble(Word::from(i));
+        assert_eq!(text(2), "@2");
+        assert_eq!(text(0x7fff), "@32767");
+        assert_eq!(text(0xec10), "D=A");
+        assert_eq!(text(0xe090), "D=D+A");
+        assert_eq!(text(0xe308), "M=D");
+        assert_eq!(text(0xea87), "0;JMP");
+        assert_eq!(text(0xfc10), "D=M");
+        assert_eq!(text(0xf4d0), "D=D-M");
+        assert_eq!(text(0xe301), "D;JGT");
+        assert_eq!(text(0xefff), "AMD=1;JMP");
+        assert_eq!(text(0xef80), "1110111110000000 (invalid)");
+        assert_eq!(decode(Word::from(0xef80u16)), Err(InvalidComp(0xef80)));
+        assert_eq!(
+            format!("{}", InvalidComp(0xef80)),
+            "1110111110000000 has no comp mnemonic for 0111110."
+        );
+    }
+
+    #[test]
+    fn for_listing() {
+        // 0 (LOOP) @0 / D=M / @6 / D;JEQ / @0 / 0;JMP / 6 (END) @6 / 0;JMP
+        let program = [0u16, 0xfc10, 6, 0xe302, 0, 0xea87, 6, 0xea87]
+            .iter()
+            .map(|i| Word::from(*i))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            labels(&program).values().collect::<Vec<_>>(),
+            vec!["L0", "L6"]
+        );
+        let recovered = listing(&program, true, false);
+        assert_eq!(
+            recovered.text,
+            "(L0)\n@0\nD=M\n@L6\nD;JEQ\n@L0\n0;JMP\n(L6)\n@L6\n0;JMP\n"
+        );
+        assert!(recovered.invalid.is_empty());
+        let plain = listing(&program[..4], false, false);
+        assert_eq!(plain.text, "@0\nD=M\n@6\nD;JEQ\n");
+
+        let broken = listing(&[Word::from(1u16), Word::from(0xef80u16)], false, false);
+        assert_eq!(
+            broken.text,
+            "@1\n// 1: 1110111110000000 has no comp mnemonic for 0111110.\n"
+        );
+        assert_eq!(broken.invalid, vec![1]);
+    }
+
+    #[test]
+    fn for_extended() {
+        let extended = |i: u16| decode_extended(Word::from(i));
+        assert_eq!(extended(0xac10), Ok("D=D<<".to_string()));
+        assert_eq!(extended(0xb008), Ok("M=M>>".to_string()));
+        assert_eq!(extended(0xa827), Ok("A=A<<;JMP".to_string()));
+        assert_eq!(extended(0xec10), Ok("D=A".to_string()));
+        assert_eq!(extended(0xaf80), Err(InvalidComp(0xaf80)));
+        // 拡張しなければ 101 の命令も下位 13 ビットだけで読む
+        assert_eq!(decode(Word::from(0xac10u16)), Ok("D=A".to_string()));
+
+        let program = [0xac10u16, 0xa410]
+            .iter()
+            .map(|i| Word::from(*i))
+            .collect::<Vec<_>>();
+        assert_eq!(listing(&program, false, true).text, "D=D<<\nD=D>>\n");
+        assert_eq!(listing(&program, false, false).invalid, vec![1]);
+    }
+}
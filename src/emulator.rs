@@ -0,0 +1,457 @@
+#![allow(dead_code)]
+
+use crate::disasm::is_shift;
+use crate::keyboard::{Keyboard, KBD};
+use crate::logic::bit::O;
+use crate::logic::Word;
+use crate::rom::ROM_SIZE;
+use crate::snapshot::{Reader, Snapshot, SnapshotError, Writer};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+pub const RAM_SIZE: usize = KBD + 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmulatorError {
+    TooLarge(usize),
+    Address { pc: u16, address: u16 },
+}
+
+impl Display for EmulatorError {
+    fn fmt(&self, dest: &mut Formatter) -> fmt::Result {
+        match self {
+            EmulatorError::TooLarge(len) => write!(
+                dest,
+                "program has {} instructions, ROM32K holds {}.",
+                len, ROM_SIZE
+            ),
+            EmulatorError::Address { pc, address } => write!(
+                dest,
+                "pc {}: M refers to address {}, which is out of memory.",
+                pc, address
+            ),
+        }
+    }
+}
+
+// Hack コンピュータの振る舞いだけを真似る (ゲートは使わない)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Computer {
+    rom: Vec<Word>,
+    ram: Vec<Word>,
+    a: u16,
+    d: u16,
+    pc: u16,
+    cycle: u64,
+    keyboard: Keyboard,
+    // 101 で始まる命令をシフト命令として実行する
+    extended: bool,
+}
+
+impl Computer {
+    pub fn new(program: Vec<Word>) -> Result<Self, EmulatorError> {
+        if program.len() > ROM_SIZE {
+            return Err(EmulatorError::TooLarge(program.len()));
+        }
+        Ok(Computer {
+            rom: program,
+            ram: vec![Word::new([O; 16]); RAM_SIZE],
+            a: 0,
+            d: 0,
+            pc: 0,
+            cycle: 0,
+            keyboard: Keyboard::new(),
+            extended: false,
+        })
+    }
+
+    pub fn with_keyboard(mut self, keyboard: Keyboard) -> Self {
+        self.keyboard = keyboard;
+        self
+    }
+
+    pub fn with_extended(mut self, extended: bool) -> Self {
+        self.extended = extended;
+        self
+    }
+
+    pub fn is_extended(&self) -> bool {
+        self.extended
+    }
+
+    pub fn a(&self) -> Word {
+        Word::from(self.a)
+    }
+
+    pub fn d(&self) -> Word {
+        Word::from(self.d)
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn cycle(&self) -> u64 {
+        self.cycle
+    }
+
+    pub fn rom(&self) -> &[Word] {
+        &self.rom
+    }
+
+    pub fn ram(&self) -> &[Word] {
+        &self.ram
+    }
+
+    pub fn keyboard(&self) -> &Keyboard {
+        &self.keyboard
+    }
+
+    pub fn peek(&self, address: usize) -> Word {
+        self.ram[address]
+    }
+
+    pub fn poke(&mut self, address: usize, value: Word) {
+        self.ram[address] = value;
+    }
+
+    pub fn set_a(&mut self, value: Word) {
+        self.a = u16::from(value);
+    }
+
+    pub fn set_d(&mut self, value: Word) {
+        self.d = u16::from(value);
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    pub fn reset(&mut self) {
+        self.pc = 0;
+    }
+
+    // 終わりの無限ループ (@n, 0;JMP で n に戻る) か ROM の外にいる
+    pub fn is_halted(&self) -> bool {
+        let pc = self.pc as usize;
+        if pc >= self.rom.len() {
+            return true;
+        }
+        let here = u16::from(self.rom[pc]);
+        pc + 1 < self.rom.len() && here == self.pc && u16::from(self.rom[pc + 1]) == 0xea87
+    }
+
+    pub fn step(&mut self) -> Result<(), EmulatorError> {
+        self.keyboard.tick(self.cycle);
+        self.ram[KBD] = self.keyboard.output();
+        let instruction = self
+            .rom
+            .get(self.pc as usize)
+            .map_or(0, |word| u16::from(*word));
+        if instruction & 0x8000 == 0 {
+            self.a = instruction;
+            self.pc = self.pc.wrapping_add(1);
+        } else {
+            let m = if instruction & 0x1000 != 0 || instruction & 0x0008 != 0 {
+                Some(self.address()?)
+            } else {
+                None
+            };
+            let y = match (instruction & 0x1000 != 0, m) {
+                (true, Some(address)) => u16::from(self.ram[address]),
+                _ => self.a,
+            };
+            let out = if self.extended && is_shift(instruction) {
+                shift(self.d, y, ((instruction >> 6) & 0x3f) as u8)
+            } else {
+                alu(self.d, y, ((instruction >> 6) & 0x3f) as u8)
+            };
+            if instruction & 0x0008 != 0 {
+                if let Some(address) = m {
+                    if address != KBD {
+                        self.ram[address] = Word::from(out);
+                    }
+                }
+            }
+            let jump = instruction & 0x7;
+            let target = self.a;
+            if instruction & 0x0020 != 0 {
+                self.a = out;
+            }
+            if instruction & 0x0010 != 0 {
+                self.d = out;
+            }
+            let negative = out & 0x8000 != 0;
+            let zero = out == 0;
+            let taken = (jump & 4 != 0 && negative)
+                || (jump & 2 != 0 && zero)
+                || (jump & 1 != 0 && !negative && !zero);
+            self.pc = if taken {
+                target
+            } else {
+                self.pc.wrapping_add(1)
+            };
+        }
+        self.cycle += 1;
+        Ok(())
+    }
+
+    // 止まるか max_cycles に達するまで実行し、実行したサイクル数を返す
+    pub fn run(&mut self, max_cycles: u64) -> Result<u64, EmulatorError> {
+        let start = self.cycle;
+        while self.cycle - start < max_cycles && !self.is_halted() {
+            self.step()?;
+        }
+        Ok(self.cycle - start)
+    }
+
+    fn address(&self) -> Result<usize, EmulatorError> {
+        if (self.a as usize) < RAM_SIZE {
+            Ok(self.a as usize)
+        } else {
+            Err(EmulatorError::Address {
+                pc: self.pc,
+                address: self.a,
+            })
+        }
+    }
+}
+
+// ROM と RAM は全部、キーボードは台本と再生位置ごと保存する。最後の 1 バイトは命令セット
+impl Snapshot for Computer {
+    const KIND: &'static str = "Computer";
+    fn save(&self, w: &mut Writer) {
+        w.size(self.rom.len());
+        for word in self.rom.iter().chain(self.ram.iter()) {
+            w.value(word);
+        }
+        w.u16(self.a);
+        w.u16(self.d);
+        w.u16(self.pc);
+        w.u64(self.cycle);
+        w.value(&self.keyboard);
+        w.u8(if self.extended { 1 } else { 0 });
+    }
+    fn load(r: &mut Reader) -> Result<Self, SnapshotError> {
+        let size = r.size()?;
+        if size > ROM_SIZE {
+            return Err(SnapshotError::Invalid(
+                EmulatorError::TooLarge(size).to_string(),
+            ));
+        }
+        let rom = (0..size)
+            .map(|_| r.value())
+            .collect::<Result<Vec<Word>, _>>()?;
+        let ram = (0..RAM_SIZE)
+            .map(|_| r.value())
+            .collect::<Result<Vec<Word>, _>>()?;
+        Ok(Computer {
+            rom,
+            ram,
+            a: r.u16()?,
+            d: r.u16()?,
+            pc: r.u16()?,
+            cycle: r.u64()?,
+            keyboard: r.value()?,
+            extended: match r.u8()? {
+                0 => false,
+                1 => true,
+                n => {
+                    return Err(SnapshotError::Invalid(format!(
+                        "{} is not an instruction set.",
+                        n
+                    )))
+                }
+            },
+        })
+    }
+}
+
+// control の最上位が I なら左、次が I なら x を、O なら y をずらす。右は算術シフト
+pub fn shift(x: u16, y: u16, control: u8) -> u16 {
+    let value = if control & 0x10 != 0 { x } else { y };
+    if control & 0x20 != 0 {
+        value << 1
+    } else {
+        ((value as i16) >> 1) as u16
+    }
+}
+
+// control は zx nx zy ny f no の順 (zx が最上位)
+pub fn alu(x: u16, y: u16, control: u8) -> u16 {
+    let bit = |i: u8| control & (1 << (5 - i)) != 0;
+    let x = if bit(0) { 0 } else { x };
+    let x = if bit(1) { !x } else { x };
+    let y = if bit(2) { 0 } else { y };
+    let y = if bit(3) { !y } else { y };
+    let out = if bit(4) { x.wrapping_add(y) } else { x & y };
+    if bit(5) {
+        !out
+    } else {
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{alu, shift, Computer, EmulatorError};
+    use crate::arithmetic::ALU;
+    use crate::assembler::assemble_with;
+    use crate::keyboard::Keyboard;
+    use crate::logic::Word;
+    use crate::rom::parse_hack;
+    use crate::snapshot::{from_bytes, to_bytes};
+
+    // RAM[2] = max(RAM[0], RAM[1])
+    const MAX: &str = "0000000000000000\n1111110000010000\n0000000000000001\n\
+                       1111010011010000\n0000000000001010\n1110001100000001\n\
+                       0000000000000001\n1111110000010000\n0000000000001100\n\
+                       1110101010000111\n0000000000000000\n1111110000010000\n\
+                       0000000000000010\n1110001100001000\n0000000000001110\n\
+                       1110101010000111\n";
+
+    #[test]
+    fn for_alu() {
+        for (x, y) in [(0u16, 0u16), (17, 3), (65535, 1), (12345, 40000)].iter() {
+            for control in 0..64u8 {
+                let c = |i: u8| Word::from(((control >> (5 - i)) & 1) as u16)[15];
+                let (out, _, _) = ALU(
+                    Word::from(*x),
+                    Word::from(*y),
+                    c(0),
+                    c(1),
+                    c(2),
+                    c(3),
+                    c(4),
+                    c(5),
+                );
+                assert_eq!(alu(*x, *y, control), u16::from(out));
+            }
+        }
+    }
+
+    #[test]
+    fn for_add() {
+        let program = parse_hack(
+            "0000000000000010\n1110110000010000\n0000000000000011\n\
+             1110000010010000\n0000000000000000\n1110001100001000\n",
+        )
+        .unwrap();
+        let mut computer = Computer::new(program).unwrap();
+        assert_eq!(computer.run(100).unwrap(), 6);
+        assert!(computer.is_halted());
+        assert_eq!(computer.peek(0), Word::from(5u16));
+        assert_eq!(computer.d(), Word::from(5u16));
+        assert_eq!(computer.a(), Word::from(0u16));
+    }
+
+    #[test]
+    fn for_max() {
+        for (x, y) in [(3u16, 7u16), (9, 2), (65535, 5)].iter() {
+            let mut computer = Computer::new(parse_hack(MAX).unwrap()).unwrap();
+            computer.poke(0, Word::from(*x));
+            computer.poke(1, Word::from(*y));
+            computer.run(1000).unwrap();
+            assert!(computer.is_halted());
+            let expected = std::cmp::max(*x as i16, *y as i16) as u16;
+            assert_eq!(computer.peek(2), Word::from(expected));
+        }
+    }
+
+    #[test]
+    fn for_keyboard() {
+        // RAM[0] = KBD を繰り返す
+        let program = parse_hack(
+            "0110000000000000\n1111110000010000\n0000000000000000\n\
+             1110001100001000\n0000000000000000\n1110101010000111\n",
+        )
+        .unwrap();
+        let keyboard = Keyboard::from_script("10 left\n20 release\n").unwrap();
+        let mut computer = Computer::new(program).unwrap().with_keyboard(keyboard);
+        computer.run(17).unwrap();
+        assert_eq!(computer.peek(0), Word::from(130u16));
+        computer.run(12).unwrap();
+        assert_eq!(computer.peek(0), Word::from(0u16));
+        assert_eq!(computer.cycle(), 29);
+    }
+
+    #[test]
+    fn for_snapshot() {
+        // キー入力の途中で保存し、読み戻した方と最後まで同じに動くか
+        let program = parse_hack(
+            "0110000000000000\n1111110000010000\n0000000000000000\n\
+             1110001100001000\n0000000000000000\n1110101010000111\n",
+        )
+        .unwrap();
+        let keyboard = Keyboard::from_script("10 left\n20 release\n30 a\n").unwrap();
+        let mut computer = Computer::new(program).unwrap().with_keyboard(keyboard);
+        computer.run(17).unwrap();
+        let bytes = to_bytes(&computer);
+        let mut resumed: Computer = from_bytes(&bytes).unwrap();
+        assert_eq!(resumed, computer);
+        computer.run(20).unwrap();
+        resumed.run(20).unwrap();
+        assert_eq!(resumed, computer);
+        assert_eq!(resumed.peek(0), Word::from(97u16));
+
+        // 壊れたキーボードの状態は読み込まない
+        let mut broken = bytes.clone();
+        let len = broken.len();
+        broken[len - 2] = 0xff;
+        assert_eq!(
+            from_bytes::<Computer>(&broken).unwrap_err().to_string(),
+            "255 is not a Hack key code."
+        );
+        let mut broken = bytes.clone();
+        broken[len - 1] = 2;
+        assert_eq!(
+            from_bytes::<Computer>(&broken).unwrap_err().to_string(),
+            "2 is not an instruction set."
+        );
+    }
+
+    #[test]
+    fn for_errors() {
+        let program = parse_hack("0111111111111111\n1110111111001000\n").unwrap();
+        let mut computer = Computer::new(program).unwrap();
+        assert_eq!(
+            computer.run(10),
+            Err(EmulatorError::Address {
+                pc: 1,
+                address: 32767
+            })
+        );
+        assert_eq!(
+            Computer::new(vec![Word::from(0u16); 32769]),
+            Err(EmulatorError::TooLarge(32769))
+        );
+    }
+
+    #[test]
+    fn for_extended() {
+        assert_eq!(shift(0x4001, 0, 0b110000), 0x8002);
+        assert_eq!(shift(0, 0x8002, 0b000000), 0xc001);
+        assert_eq!(shift(0, 0x4002, 0b000000), 0x2001);
+
+        // RAM[1] = RAM[0] * 4、RAM[2] = RAM[0] / 2
+        let src = "@R0\nD=M\nD=D<<\nD=D<<\n@R1\nM=D\n@R0\nD=M>>\n@R2\nM=D\n";
+        let program = assemble_with("Shift.asm", src, true).unwrap().code;
+        let mut computer = Computer::new(program.clone()).unwrap().with_extended(true);
+        computer.poke(0, Word::from(-7i16));
+        computer.run(10).unwrap();
+        assert_eq!(computer.peek(1), Word::from(-28i16));
+        assert_eq!(computer.peek(2), Word::from(-4i16));
+
+        // 命令セットもスナップショットに残る
+        let resumed: Computer = from_bytes(&to_bytes(&computer)).unwrap();
+        assert!(resumed.is_extended());
+        assert_eq!(resumed, computer);
+
+        // 拡張しなければ下位 13 ビットだけの普通の C 命令になる (D<< は D=A、M>> は D&M)
+        let mut strict = Computer::new(program).unwrap();
+        strict.poke(0, Word::from(6u16));
+        strict.run(10).unwrap();
+        assert_eq!(strict.peek(1), Word::from(0u16));
+        assert_eq!(strict.peek(2), Word::from(0u16));
+    }
+}
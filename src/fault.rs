@@ -0,0 +1,262 @@
+#![allow(dead_code)]
+
+use crate::logic::bit::{self, I, O};
+use crate::netlist::{Driver, Fault, Net, Netlist};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+// 定数以外の全ネットの stuck-at-0 と stuck-at-1
+pub fn faults(netlist: &Netlist) -> Vec<Fault> {
+    netlist
+        .nets()
+        .filter(|net| !matches!(netlist.driver(*net), Driver::Const(_)))
+        .flat_map(|net| vec![Fault { net, value: O }, Fault { net, value: I }])
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coverage {
+    pub total: usize,
+    // どのテストでも出力が変わらなかった故障と、その場所
+    pub undetected: Vec<(Fault, String)>,
+}
+
+impl Coverage {
+    pub fn detected(&self) -> usize {
+        self.total - self.undetected.len()
+    }
+}
+
+impl Display for Coverage {
+    fn fmt(&self, dest: &mut Formatter) -> fmt::Result {
+        writeln!(
+            dest,
+            "{} of {} faults detected ({:.1}%)",
+            self.detected(),
+            self.total,
+            100.0 * self.detected() as f64 / self.total.max(1) as f64
+        )?;
+        for (fault, place) in self.undetected.iter() {
+            let value = if fault.value == I { 1 } else { 0 };
+            writeln!(dest, "  undetected: {} stuck-at-{}", place, value)?;
+        }
+        Ok(())
+    }
+}
+
+// 各故障について、vectors のどれかで正常な出力と違えば検出できたとする
+pub fn coverage(netlist: &Netlist, vectors: &[Vec<bit>]) -> Coverage {
+    let expected = vectors.iter().map(|v| netlist.eval(v)).collect::<Vec<_>>();
+    let all = faults(netlist);
+    let undetected = all
+        .iter()
+        .filter(|fault| {
+            vectors
+                .iter()
+                .zip(expected.iter())
+                .all(|(v, e)| netlist.eval_with_fault(v, **fault) == *e)
+        })
+        .map(|fault| (*fault, describe(netlist, fault.net)))
+        .collect();
+    Coverage {
+        total: all.len(),
+        undetected,
+    }
+}
+
+// 入力ポート、いちばん外側のチップの出力ポート、チップの中の Nand の順に名前を探す
+pub fn describe(netlist: &Netlist, net: Net) -> String {
+    for (name, nets) in netlist.inputs().iter() {
+        if let Some(i) = nets.iter().position(|n| *n == net) {
+            return port(name, i, nets.len());
+        }
+    }
+    let owner = netlist
+        .instances()
+        .iter()
+        .enumerate()
+        .filter_map(|(index, instance)| {
+            instance.outputs.iter().find_map(|p| {
+                p.nets
+                    .iter()
+                    .position(|n| *n == net)
+                    .map(|i| (index, port(p.name, i, p.nets.len())))
+            })
+        })
+        .min_by_key(|(index, _)| netlist.instances()[*index].depth);
+    if let Some((index, name)) = owner {
+        return format!("{}.{}", path(netlist, index), name);
+    }
+    match netlist.driver(net) {
+        Driver::Gate(g) => {
+            let owner = netlist.gates()[g].owner;
+            let k = netlist.gates()[..g]
+                .iter()
+                .filter(|gate| gate.owner == owner)
+                .count();
+            match owner {
+                Some(index) => format!("{}/Nand[{}]", path(netlist, index), k),
+                None => format!("Nand[{}]", k),
+            }
+        }
+        _ => format!("n{}", net.id()),
+    }
+}
+
+fn port(name: &str, index: usize, width: usize) -> String {
+    if width == 1 {
+        name.to_string()
+    } else {
+        format!("{}[{}]", name, index)
+    }
+}
+
+// 親から順に Chip[k]。k は同じ親の下の同じチップの中での順番
+fn path(netlist: &Netlist, index: usize) -> String {
+    let instances = netlist.instances();
+    let mut parts = vec![];
+    let mut current = Some(index);
+    while let Some(i) = current {
+        let instance = &instances[i];
+        let k = instances[..i]
+            .iter()
+            .filter(|other| other.parent == instance.parent && other.chip == instance.chip)
+            .count();
+        parts.push(if instance.parent.is_none() {
+            instance.chip.to_string()
+        } else {
+            format!("{}[{}]", instance.chip, k)
+        });
+        current = instance.parent;
+    }
+    parts.reverse();
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{coverage, describe};
+    use crate::arithmetic::ADD16_VECTORS;
+    use crate::logic::bit::{I, O};
+    use crate::logic::Word;
+    use crate::netlist::{Fault, Net, Netlist};
+    use crate::{arithmetic, logic};
+
+    fn bits(value: u16) -> Vec<crate::logic::bit> {
+        Word::from(value).to_slice().to_vec()
+    }
+
+    fn add16() -> Netlist {
+        let mut n = Netlist::new();
+        let a = n.input16("a");
+        let b = n.input16("b");
+        let out = n.capture(|| arithmetic::Add16(a, b));
+        n.output16("out", out);
+        n
+    }
+
+    // describe の名前からネットを引く
+    fn net(n: &Netlist, place: &str) -> Net {
+        n.nets().find(|net| describe(n, *net) == place).unwrap()
+    }
+
+    #[test]
+    fn for_describe() {
+        let n = add16();
+        assert_eq!(describe(&n, n.inputs()[1].1[3]), "b[3]");
+        assert_eq!(describe(&n, n.outputs()[0].1[0]), "Add16.out[0]");
+        let first = n
+            .instances()
+            .iter()
+            .find(|instance| instance.chip == "FullAdder")
+            .unwrap();
+        let carry = first.outputs[0].nets[0];
+        assert_eq!(describe(&n, carry), "Add16/FullAdder[0].carry");
+        let inner = n.gates()[0].out;
+        assert_eq!(describe(&n, inner), "Add16/HalfAdder[0]/And[0]/Nand[0]");
+    }
+
+    #[test]
+    fn for_add16_vectors() {
+        let n = add16();
+        // arithmetic の for_add16 が使っている組
+        let vectors = ADD16_VECTORS
+            .iter()
+            .map(|(a, b, _)| [bits(*a), bits(*b)].concat())
+            .collect::<Vec<_>>();
+        let weak = coverage(&n, &vectors);
+        assert_eq!(weak.total, 2 * (n.net_count() - 2));
+        assert_eq!(weak.detected(), 609);
+
+        // 最下位の桁から FullAdder[0] への桁上がりは、これらの組では一度も 1 にならない
+        let first = net(&n, "Add16/HalfAdder[0].carry");
+        let stuck = Fault {
+            net: first,
+            value: O,
+        };
+        assert!(weak.undetected.iter().any(|(fault, _)| *fault == stuck));
+        let report = weak.to_string();
+        assert!(report.starts_with("609 of 1022 faults detected (59.6%)\n"));
+        assert!(report.contains("  undetected: Add16/HalfAdder[0].carry stuck-at-0\n"));
+
+        // 各桁で桁上がりを起こす組を足すと取りこぼしが減る。最上位の桁上がりは捨てられるので残る
+        let mut strong = vectors.clone();
+        for (a, b) in [
+            (0u16, 0u16),
+            (0xffff, 1),
+            (0x5555, 0x5555),
+            (0xaaaa, 0xaaaa),
+        ]
+        .iter()
+        {
+            strong.push([bits(*a), bits(*b)].concat());
+            strong.push([bits(*b), bits(*a)].concat());
+        }
+        let better = coverage(&n, &strong);
+        assert!(better.undetected.len() < weak.undetected.len());
+        assert!(!better.undetected.iter().any(|(fault, _)| *fault == stuck));
+        let last = net(&n, "Add16/FullAdder[14].carry");
+        assert!(better.undetected.contains(&(
+            Fault {
+                net: last,
+                value: I
+            },
+            "Add16/FullAdder[14].carry".to_string()
+        )));
+    }
+
+    #[test]
+    fn for_exhaustive_xor() {
+        let mut n = Netlist::new();
+        let a = n.input("a");
+        let b = n.input("b");
+        let out = n.capture(|| logic::Xor(a, b));
+        n.output("out", out);
+        let all = [[O, O], [O, I], [I, O], [I, I]]
+            .iter()
+            .map(|v| v.to_vec())
+            .collect::<Vec<_>>();
+        // And は同じ Nand を二つ並べて Not の代わりにしているので、片方の stuck-at-1 は
+        // どの入力でも出力に現れない
+        let places = coverage(&n, &all)
+            .undetected
+            .into_iter()
+            .map(|(fault, place)| {
+                assert_eq!(fault.value, I);
+                place
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            places,
+            vec![
+                "Xor/And[0]/Nand[0]",
+                "Xor/And[0]/Nand[1]",
+                "Xor/And[1]/Nand[0]",
+                "Xor/And[1]/Nand[1]"
+            ]
+        );
+        let one = coverage(&n, &all[..1]);
+        assert!(one.detected() > 0);
+        assert!(one.detected() < one.total);
+    }
+}
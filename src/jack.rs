@@ -0,0 +1,780 @@
+#![allow(dead_code)]
+
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+const KEYWORDS: [&str; 21] = [
+    "class",
+    "constructor",
+    "function",
+    "method",
+    "field",
+    "static",
+    "var",
+    "int",
+    "char",
+    "boolean",
+    "void",
+    "true",
+    "false",
+    "null",
+    "this",
+    "let",
+    "do",
+    "if",
+    "else",
+    "while",
+    "return",
+];
+
+const SYMBOLS: &str = "{}()[].,;+-*/&|<>=~";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JackError {
+    pub file: String,
+    pub span: Span,
+    pub message: String,
+}
+
+impl Display for JackError {
+    fn fmt(&self, dest: &mut Formatter) -> fmt::Result {
+        write!(
+            dest,
+            "{}:{}:{}: {}",
+            self.file, self.span.line, self.span.column, self.message
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    Keyword(&'static str),
+    Symbol(char),
+    Int(u16),
+    Str(String),
+    Ident(String),
+}
+
+impl Display for Token {
+    fn fmt(&self, dest: &mut Formatter) -> fmt::Result {
+        match self {
+            Token::Keyword(word) => write!(dest, "`{}`", word),
+            Token::Symbol(c) => write!(dest, "`{}`", c),
+            Token::Int(n) => write!(dest, "`{}`", n),
+            Token::Str(s) => write!(dest, "\"{}\"", s),
+            Token::Ident(name) => write!(dest, "`{}`", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+    Int,
+    Char,
+    Boolean,
+    Class(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VarKind {
+    Static,
+    Field,
+    Argument,
+    Local,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Var {
+    pub kind: VarKind,
+    pub ty: Type,
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubroutineKind {
+    Constructor,
+    Function,
+    Method,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subroutine {
+    pub kind: SubroutineKind,
+    // None は void
+    pub ret: Option<Type>,
+    pub name: String,
+    pub span: Span,
+    pub params: Vec<Var>,
+    pub locals: Vec<Var>,
+    pub body: Vec<Statement>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Class {
+    pub file: String,
+    pub name: String,
+    pub span: Span,
+    pub vars: Vec<Var>,
+    pub subroutines: Vec<Subroutine>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Statement {
+    Let {
+        name: String,
+        span: Span,
+        index: Option<Expr>,
+        value: Expr,
+    },
+    If {
+        condition: Expr,
+        then: Vec<Statement>,
+        otherwise: Vec<Statement>,
+    },
+    While {
+        condition: Expr,
+        body: Vec<Statement>,
+    },
+    Do(Call),
+    Return {
+        span: Span,
+        value: Option<Expr>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExprKind {
+    Int(u16),
+    Str(String),
+    True,
+    False,
+    Null,
+    This,
+    Var(String),
+    Index(String, Box<Expr>),
+    Call(Call),
+    // - か ~
+    Unary(char, Box<Expr>),
+    // Jack の二項演算には優先順位が無く、左から順に結合する
+    Binary(char, Box<Expr>, Box<Expr>),
+}
+
+// receiver は Foo.bar() の Foo、x.bar() の x。bar() なら None
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Call {
+    pub receiver: Option<String>,
+    pub name: String,
+    pub args: Vec<Expr>,
+    pub span: Span,
+}
+
+pub fn tokenize(file: &str, src: &str) -> Result<Vec<(Token, Span)>, JackError> {
+    let chars = src.chars().collect::<Vec<_>>();
+    let mut tokens = vec![];
+    let (mut i, mut line, mut column) = (0, 1, 1);
+    let error = |span: Span, message: String| JackError {
+        file: file.to_string(),
+        span,
+        message,
+    };
+    while i < chars.len() {
+        let span = Span { line, column };
+        let c = chars[i];
+        let rest = &chars[i..];
+        // 読み進めた文字数
+        let length;
+        if c == '\n' {
+            i += 1;
+            line += 1;
+            column = 1;
+            continue;
+        } else if c.is_whitespace() {
+            length = 1;
+        } else if rest.starts_with(&['/', '/']) {
+            length = rest.iter().position(|c| *c == '\n').unwrap_or(rest.len());
+        } else if rest.starts_with(&['/', '*']) {
+            let end = (3..rest.len()).find(|j| rest[*j - 1] == '*' && rest[*j] == '/');
+            let end = match end {
+                Some(end) => end + 1,
+                None => return Err(error(span, "unterminated comment.".to_string())),
+            };
+            for c in rest[..end].iter() {
+                if *c == '\n' {
+                    line += 1;
+                    column = 0;
+                }
+                column += 1;
+            }
+            i += end;
+            continue;
+        } else if c == '"' {
+            let end = match rest[1..].iter().position(|c| *c == '"' || *c == '\n') {
+                Some(end) if rest[end + 1] == '"' => end + 1,
+                _ => return Err(error(span, "unterminated string.".to_string())),
+            };
+            tokens.push((Token::Str(rest[1..end].iter().collect()), span));
+            length = end + 1;
+        } else if SYMBOLS.contains(c) {
+            tokens.push((Token::Symbol(c), span));
+            length = 1;
+        } else if c.is_ascii_digit() {
+            length = rest
+                .iter()
+                .position(|c| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let digits = rest[..length].iter().collect::<String>();
+            match digits.parse::<u16>() {
+                Ok(n) if n <= 32767 => tokens.push((Token::Int(n), span)),
+                _ => {
+                    return Err(error(
+                        span,
+                        format!("integer {} is larger than 32767.", digits),
+                    ))
+                }
+            }
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            length = rest
+                .iter()
+                .position(|c| !c.is_ascii_alphanumeric() && *c != '_')
+                .unwrap_or(rest.len());
+            let word = rest[..length].iter().collect::<String>();
+            match KEYWORDS.iter().find(|k| **k == word) {
+                Some(keyword) => tokens.push((Token::Keyword(keyword), span)),
+                None => tokens.push((Token::Ident(word), span)),
+            }
+        } else {
+            return Err(error(span, format!("unexpected character `{}`.", c)));
+        }
+        i += length;
+        column += length;
+    }
+    Ok(tokens)
+}
+
+pub fn parse(file: &str, src: &str) -> Result<Class, JackError> {
+    let tokens = tokenize(file, src)?;
+    let mut parser = Parser {
+        file,
+        tokens,
+        position: 0,
+    };
+    let class = parser.class()?;
+    if let Some((token, span)) = parser.tokens.get(parser.position) {
+        return Err(parser.error(*span, format!("unexpected {} after the class.", token)));
+    }
+    Ok(class)
+}
+
+struct Parser<'a> {
+    file: &'a str,
+    tokens: Vec<(Token, Span)>,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, span: Span, message: String) -> JackError {
+        JackError {
+            file: self.file.to_string(),
+            span,
+            message,
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    fn span(&self) -> Span {
+        match self.tokens.get(self.position) {
+            Some((_, span)) => *span,
+            None => self
+                .tokens
+                .last()
+                .map_or(Span { line: 1, column: 1 }, |(_, s)| *s),
+        }
+    }
+
+    fn next(&mut self, expected: &str) -> Result<(Token, Span), JackError> {
+        match self.tokens.get(self.position) {
+            Some((token, span)) => {
+                self.position += 1;
+                Ok((token.clone(), *span))
+            }
+            None => Err(self.error(
+                self.span(),
+                format!("expected {}, found the end of the file.", expected),
+            )),
+        }
+    }
+
+    fn unexpected<T>(&self, expected: &str) -> Result<T, JackError> {
+        match self.tokens.get(self.position) {
+            Some((token, span)) => {
+                Err(self.error(*span, format!("expected {}, found {}.", expected, token)))
+            }
+            None => Err(self.error(
+                self.span(),
+                format!("expected {}, found the end of the file.", expected),
+            )),
+        }
+    }
+
+    fn is_symbol(&self, c: char) -> bool {
+        self.peek() == Some(&Token::Symbol(c))
+    }
+
+    fn is_keyword(&self, word: &str) -> bool {
+        matches!(self.peek(), Some(Token::Keyword(k)) if *k == word)
+    }
+
+    fn symbol(&mut self, c: char) -> Result<Span, JackError> {
+        if self.is_symbol(c) {
+            Ok(self.next("")?.1)
+        } else {
+            self.unexpected(&format!("`{}`", c))
+        }
+    }
+
+    fn keyword(&mut self, word: &str) -> Result<Span, JackError> {
+        if self.is_keyword(word) {
+            Ok(self.next("")?.1)
+        } else {
+            self.unexpected(&format!("`{}`", word))
+        }
+    }
+
+    fn identifier(&mut self) -> Result<(String, Span), JackError> {
+        match self.peek() {
+            Some(Token::Ident(_)) => match self.next("")? {
+                (Token::Ident(name), span) => Ok((name, span)),
+                _ => unreachable!(),
+            },
+            _ => self.unexpected("an identifier"),
+        }
+    }
+
+    fn ty(&mut self) -> Result<Type, JackError> {
+        let ty = match self.peek() {
+            Some(Token::Keyword("int")) => Type::Int,
+            Some(Token::Keyword("char")) => Type::Char,
+            Some(Token::Keyword("boolean")) => Type::Boolean,
+            Some(Token::Ident(name)) => Type::Class(name.clone()),
+            _ => return self.unexpected("a type"),
+        };
+        self.position += 1;
+        Ok(ty)
+    }
+
+    // type name (, name)* ;
+    fn var_list(&mut self, kind: VarKind, vars: &mut Vec<Var>) -> Result<(), JackError> {
+        let ty = self.ty()?;
+        loop {
+            let (name, span) = self.identifier()?;
+            vars.push(Var {
+                kind,
+                ty: ty.clone(),
+                name,
+                span,
+            });
+            if !self.is_symbol(',') {
+                break;
+            }
+            self.symbol(',')?;
+        }
+        self.symbol(';')?;
+        Ok(())
+    }
+
+    fn class(&mut self) -> Result<Class, JackError> {
+        let span = self.keyword("class")?;
+        let (name, _) = self.identifier()?;
+        self.symbol('{')?;
+        let mut vars = vec![];
+        while self.is_keyword("static") || self.is_keyword("field") {
+            let kind = if self.is_keyword("static") {
+                VarKind::Static
+            } else {
+                VarKind::Field
+            };
+            self.position += 1;
+            self.var_list(kind, &mut vars)?;
+        }
+        let mut subroutines = vec![];
+        while !self.is_symbol('}') {
+            subroutines.push(self.subroutine()?);
+        }
+        self.symbol('}')?;
+        Ok(Class {
+            file: self.file.to_string(),
+            name,
+            span,
+            vars,
+            subroutines,
+        })
+    }
+
+    fn subroutine(&mut self) -> Result<Subroutine, JackError> {
+        let kind = match self.peek() {
+            Some(Token::Keyword("constructor")) => SubroutineKind::Constructor,
+            Some(Token::Keyword("function")) => SubroutineKind::Function,
+            Some(Token::Keyword("method")) => SubroutineKind::Method,
+            _ => return self.unexpected("a subroutine declaration"),
+        };
+        self.position += 1;
+        let ret = if self.is_keyword("void") {
+            self.position += 1;
+            None
+        } else {
+            Some(self.ty()?)
+        };
+        let (name, span) = self.identifier()?;
+        self.symbol('(')?;
+        let mut params = vec![];
+        while !self.is_symbol(')') {
+            if !params.is_empty() {
+                self.symbol(',')?;
+            }
+            let ty = self.ty()?;
+            let (name, span) = self.identifier()?;
+            params.push(Var {
+                kind: VarKind::Argument,
+                ty,
+                name,
+                span,
+            });
+        }
+        self.symbol(')')?;
+        self.symbol('{')?;
+        let mut locals = vec![];
+        while self.is_keyword("var") {
+            self.position += 1;
+            self.var_list(VarKind::Local, &mut locals)?;
+        }
+        let body = self.statements()?;
+        self.symbol('}')?;
+        Ok(Subroutine {
+            kind,
+            ret,
+            name,
+            span,
+            params,
+            locals,
+            body,
+        })
+    }
+
+    // 閉じ括弧 } の手前まで
+    fn statements(&mut self) -> Result<Vec<Statement>, JackError> {
+        let mut statements = vec![];
+        while !self.is_symbol('}') {
+            statements.push(self.statement()?);
+        }
+        Ok(statements)
+    }
+
+    fn block(&mut self) -> Result<Vec<Statement>, JackError> {
+        self.symbol('{')?;
+        let statements = self.statements()?;
+        self.symbol('}')?;
+        Ok(statements)
+    }
+
+    fn statement(&mut self) -> Result<Statement, JackError> {
+        let keyword = match self.peek() {
+            Some(Token::Keyword(keyword)) => *keyword,
+            _ => return self.unexpected("a statement"),
+        };
+        match keyword {
+            "let" => {
+                self.position += 1;
+                let (name, span) = self.identifier()?;
+                let index = if self.is_symbol('[') {
+                    self.symbol('[')?;
+                    let index = self.expression()?;
+                    self.symbol(']')?;
+                    Some(index)
+                } else {
+                    None
+                };
+                self.symbol('=')?;
+                let value = self.expression()?;
+                self.symbol(';')?;
+                Ok(Statement::Let {
+                    name,
+                    span,
+                    index,
+                    value,
+                })
+            }
+            "if" => {
+                self.position += 1;
+                self.symbol('(')?;
+                let condition = self.expression()?;
+                self.symbol(')')?;
+                let then = self.block()?;
+                let otherwise = if self.is_keyword("else") {
+                    self.position += 1;
+                    self.block()?
+                } else {
+                    vec![]
+                };
+                Ok(Statement::If {
+                    condition,
+                    then,
+                    otherwise,
+                })
+            }
+            "while" => {
+                self.position += 1;
+                self.symbol('(')?;
+                let condition = self.expression()?;
+                self.symbol(')')?;
+                let body = self.block()?;
+                Ok(Statement::While { condition, body })
+            }
+            "do" => {
+                self.position += 1;
+                let (name, span) = self.identifier()?;
+                let call = self.call(name, span)?;
+                self.symbol(';')?;
+                Ok(Statement::Do(call))
+            }
+            "return" => {
+                let span = self.keyword("return")?;
+                let value = if self.is_symbol(';') {
+                    None
+                } else {
+                    Some(self.expression()?)
+                };
+                self.symbol(';')?;
+                Ok(Statement::Return { span, value })
+            }
+            _ => self.unexpected("a statement"),
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expr, JackError> {
+        let mut left = self.term()?;
+        while let Some(Token::Symbol(op)) = self.peek() {
+            let op = *op;
+            if !"+-*/&|<>=".contains(op) {
+                break;
+            }
+            self.position += 1;
+            let right = self.term()?;
+            let span = left.span;
+            left = Expr {
+                kind: ExprKind::Binary(op, Box::new(left), Box::new(right)),
+                span,
+            };
+        }
+        Ok(left)
+    }
+
+    fn term(&mut self) -> Result<Expr, JackError> {
+        let (token, span) = self.next("an expression")?;
+        let kind = match token {
+            Token::Int(n) => ExprKind::Int(n),
+            Token::Str(s) => ExprKind::Str(s),
+            Token::Keyword("true") => ExprKind::True,
+            Token::Keyword("false") => ExprKind::False,
+            Token::Keyword("null") => ExprKind::Null,
+            Token::Keyword("this") => ExprKind::This,
+            Token::Symbol('(') => {
+                let inner = self.expression()?;
+                self.symbol(')')?;
+                return Ok(inner);
+            }
+            Token::Symbol(op) if op == '-' || op == '~' => {
+                ExprKind::Unary(op, Box::new(self.term()?))
+            }
+            Token::Ident(name) => {
+                if self.is_symbol('[') {
+                    self.symbol('[')?;
+                    let index = self.expression()?;
+                    self.symbol(']')?;
+                    ExprKind::Index(name, Box::new(index))
+                } else if self.is_symbol('(') || self.is_symbol('.') {
+                    ExprKind::Call(self.call(name, span)?)
+                } else {
+                    ExprKind::Var(name)
+                }
+            }
+            _ => {
+                self.position -= 1;
+                return self.unexpected("an expression");
+            }
+        };
+        Ok(Expr { kind, span })
+    }
+
+    // 最初の名前は読んだあと
+    fn call(&mut self, first: String, span: Span) -> Result<Call, JackError> {
+        let (receiver, name) = if self.is_symbol('.') {
+            self.symbol('.')?;
+            (Some(first), self.identifier()?.0)
+        } else {
+            (None, first)
+        };
+        self.symbol('(')?;
+        let mut args = vec![];
+        while !self.is_symbol(')') {
+            if !args.is_empty() {
+                self.symbol(',')?;
+            }
+            args.push(self.expression()?);
+        }
+        self.symbol(')')?;
+        Ok(Call {
+            receiver,
+            name,
+            args,
+            span,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, tokenize, ExprKind, Span, Statement, SubroutineKind, Token, Type};
+
+    const MAIN: &str = "/** 平方を表示する */
+class Main {
+    static int count;
+
+    function void main() {
+        var Array a;
+        var int i, sum;
+        let a = Array.new(3); // 配列
+        let i = 0;
+        while (i < 3) {
+            let a[i] = i * i;
+            let sum = sum + a[i];
+            let i = i + 1;
+        }
+        if (~(sum = 5)) {
+            do Output.printString(\"sum: \");
+        } else {
+            do Output.printInt(-sum);
+        }
+        return;
+    }
+}
+";
+
+    #[test]
+    fn for_tokenize() {
+        let tokens = tokenize("Main.jack", "let x = \"a b\"; /* c\n */ do f(12);").unwrap();
+        let kinds = tokens.iter().map(|(t, _)| t.clone()).collect::<Vec<_>>();
+        assert_eq!(kinds[0], Token::Keyword("let"));
+        assert_eq!(kinds[3], Token::Str("a b".to_string()));
+        assert_eq!(kinds[8], Token::Int(12));
+        assert_eq!(tokens[5].1, Span { line: 2, column: 5 });
+
+        let error = |src| tokenize("Main.jack", src).unwrap_err().to_string();
+        assert_eq!(
+            error("let x = 40000;"),
+            "Main.jack:1:9: integer 40000 is larger than 32767."
+        );
+        assert_eq!(
+            error("\n  \"abc\n\""),
+            "Main.jack:2:3: unterminated string."
+        );
+        assert_eq!(error("/* x"), "Main.jack:1:1: unterminated comment.");
+        assert_eq!(
+            error("let x = #;"),
+            "Main.jack:1:9: unexpected character `#`."
+        );
+    }
+
+    #[test]
+    fn for_parse() {
+        let class = parse("Main.jack", MAIN).unwrap();
+        assert_eq!(class.name, "Main");
+        assert_eq!(class.vars[0].ty, Type::Int);
+        let main = &class.subroutines[0];
+        assert_eq!(main.kind, SubroutineKind::Function);
+        assert_eq!(main.ret, None);
+        assert_eq!(main.locals.len(), 3);
+        assert_eq!(main.locals[2].name, "sum");
+        assert_eq!(
+            main.locals[2].span,
+            Span {
+                line: 7,
+                column: 20
+            }
+        );
+        assert_eq!(main.body.len(), 5);
+        match &main.body[2] {
+            Statement::While { body, .. } => match &body[0] {
+                Statement::Let { index, value, .. } => {
+                    assert!(index.is_some());
+                    assert!(matches!(value.kind, ExprKind::Binary('*', _, _)));
+                }
+                _ => panic!("expected let"),
+            },
+            _ => panic!("expected while"),
+        }
+        match &main.body[3] {
+            Statement::If {
+                condition,
+                otherwise,
+                ..
+            } => {
+                assert!(matches!(condition.kind, ExprKind::Unary('~', _)));
+                assert_eq!(otherwise.len(), 1);
+            }
+            _ => panic!("expected if"),
+        }
+
+        // 優先順位は無く左から
+        let class = parse(
+            "A.jack",
+            "class A { function int f() { return 1 + 2 * 3; } }",
+        )
+        .unwrap();
+        match &class.subroutines[0].body[0] {
+            Statement::Return { value: Some(e), .. } => match &e.kind {
+                ExprKind::Binary('*', left, _) => {
+                    assert!(matches!(left.kind, ExprKind::Binary('+', _, _)))
+                }
+                _ => panic!("expected *"),
+            },
+            _ => panic!("expected return"),
+        }
+    }
+
+    #[test]
+    fn for_parse_errors() {
+        let error = |src| parse("A.jack", src).unwrap_err().to_string();
+        assert_eq!(
+            error("class A { function void f() { let x 1; } }"),
+            "A.jack:1:37: expected `=`, found `1`."
+        );
+        assert_eq!(
+            error("class A { function void f() {"),
+            "A.jack:1:29: expected a statement, found the end of the file."
+        );
+        assert_eq!(
+            error("class A { method int f() { return; } } }"),
+            "A.jack:1:40: unexpected `}` after the class."
+        );
+        assert_eq!(
+            error("class A { function void f() { loop; } }"),
+            "A.jack:1:31: expected a statement, found `loop`."
+        );
+    }
+}
@@ -0,0 +1,480 @@
+#![allow(dead_code)]
+
+use crate::jack::{
+    Call, Class, Expr, ExprKind, JackError, Span, Statement, Subroutine, SubroutineKind, Type, Var,
+    VarKind,
+};
+use std::collections::HashMap;
+
+use SubroutineKind::{Constructor, Function, Method};
+
+// Jack OS のクラス、サブルーチン、種類、引数の数 (method は this を除く)
+const OS: [(&str, &str, SubroutineKind, usize); 46] = [
+    ("Math", "init", Function, 0),
+    ("Math", "abs", Function, 1),
+    ("Math", "multiply", Function, 2),
+    ("Math", "divide", Function, 2),
+    ("Math", "min", Function, 2),
+    ("Math", "max", Function, 2),
+    ("Math", "sqrt", Function, 1),
+    ("String", "new", Constructor, 1),
+    ("String", "dispose", Method, 0),
+    ("String", "length", Method, 0),
+    ("String", "charAt", Method, 1),
+    ("String", "setCharAt", Method, 2),
+    ("String", "appendChar", Method, 1),
+    ("String", "eraseLastChar", Method, 0),
+    ("String", "intValue", Method, 0),
+    ("String", "setInt", Method, 1),
+    ("String", "backSpace", Function, 0),
+    ("String", "doubleQuote", Function, 0),
+    ("String", "newLine", Function, 0),
+    ("Array", "new", Function, 1),
+    ("Array", "dispose", Method, 0),
+    ("Output", "init", Function, 0),
+    ("Output", "moveCursor", Function, 2),
+    ("Output", "printChar", Function, 1),
+    ("Output", "printString", Function, 1),
+    ("Output", "printInt", Function, 1),
+    ("Output", "println", Function, 0),
+    ("Output", "backSpace", Function, 0),
+    ("Screen", "init", Function, 0),
+    ("Screen", "clearScreen", Function, 0),
+    ("Screen", "setColor", Function, 1),
+    ("Screen", "drawPixel", Function, 2),
+    ("Screen", "drawLine", Function, 4),
+    ("Screen", "drawRectangle", Function, 4),
+    ("Screen", "drawCircle", Function, 3),
+    ("Keyboard", "keyPressed", Function, 0),
+    ("Keyboard", "readChar", Function, 0),
+    ("Keyboard", "readLine", Function, 1),
+    ("Keyboard", "readInt", Function, 1),
+    ("Memory", "peek", Function, 1),
+    ("Memory", "poke", Function, 2),
+    ("Memory", "alloc", Function, 1),
+    ("Memory", "deAlloc", Function, 1),
+    ("Sys", "halt", Function, 0),
+    ("Sys", "error", Function, 1),
+    ("Sys", "wait", Function, 1),
+];
+
+// クラス名 -> サブルーチン名 -> (種類, 引数の数)
+type Signatures = HashMap<String, HashMap<String, (SubroutineKind, usize)>>;
+
+// コード生成の前に呼ぶ。見つけた誤りを全部返す
+pub fn check(classes: &[Class]) -> Vec<JackError> {
+    let mut signatures: Signatures = HashMap::new();
+    for (class, name, kind, args) in OS.iter() {
+        signatures
+            .entry(class.to_string())
+            .or_default()
+            .insert(name.to_string(), (*kind, *args));
+    }
+    let mut errors = vec![];
+    for class in classes.iter() {
+        if signatures.contains_key(&class.name) && !is_os(&class.name) {
+            errors.push(error(
+                class,
+                class.span,
+                format!("class `{}` is defined twice.", class.name),
+            ));
+        }
+        // プログラムの中で OS のクラスを書き直してもよい
+        let table = signatures.entry(class.name.clone()).or_default();
+        if is_os(&class.name) {
+            table.clear();
+        }
+        for subroutine in class.subroutines.iter() {
+            let signature = (subroutine.kind, subroutine.params.len());
+            if table.insert(subroutine.name.clone(), signature).is_some() {
+                errors.push(error(
+                    class,
+                    subroutine.span,
+                    format!("subroutine `{}` is defined twice.", subroutine.name),
+                ));
+            }
+        }
+    }
+
+    for class in classes.iter() {
+        let mut checker = Checker {
+            class,
+            signatures: &signatures,
+            subroutine: None,
+            scope: vec![],
+            errors: vec![],
+        };
+        checker.class();
+        errors.append(&mut checker.errors);
+    }
+    errors
+}
+
+fn is_os(class: &str) -> bool {
+    OS.iter().any(|(name, ..)| *name == class)
+}
+
+fn error(class: &Class, span: Span, message: String) -> JackError {
+    JackError {
+        file: class.file.clone(),
+        span,
+        message,
+    }
+}
+
+struct Checker<'a> {
+    class: &'a Class,
+    signatures: &'a Signatures,
+    subroutine: Option<&'a Subroutine>,
+    // 内側の宣言が後ろ
+    scope: Vec<&'a Var>,
+    errors: Vec<JackError>,
+}
+
+impl<'a> Checker<'a> {
+    fn report(&mut self, span: Span, message: String) {
+        self.errors.push(error(self.class, span, message));
+    }
+
+    fn declare(&mut self, var: &'a Var) {
+        if self.scope.iter().any(|v| v.name == var.name) {
+            self.report(var.span, format!("`{}` is already declared.", var.name));
+        }
+        if let Type::Class(name) = &var.ty {
+            if !self.signatures.contains_key(name) {
+                self.report(var.span, format!("unknown class `{}`.", name));
+            }
+        }
+        self.scope.push(var);
+    }
+
+    fn class(&mut self) {
+        for var in self.class.vars.iter() {
+            self.declare(var);
+        }
+        let class_vars = self.scope.len();
+        for subroutine in self.class.subroutines.iter() {
+            self.subroutine = Some(subroutine);
+            self.scope.truncate(class_vars);
+            if let Some(Type::Class(name)) = &subroutine.ret {
+                if !self.signatures.contains_key(name) {
+                    self.report(subroutine.span, format!("unknown class `{}`.", name));
+                }
+            }
+            for var in subroutine.params.iter().chain(subroutine.locals.iter()) {
+                self.declare(var);
+            }
+            self.statements(&subroutine.body);
+            if !returns(&subroutine.body) {
+                self.report(
+                    subroutine.span,
+                    format!("`{}` can reach its end without `return`.", subroutine.name),
+                );
+            }
+        }
+    }
+
+    fn is_function(&self) -> bool {
+        self.subroutine.map(|s| s.kind) == Some(Function)
+    }
+
+    fn lookup(&mut self, name: &str, span: Span) -> Option<&'a Var> {
+        let var = self.scope.iter().rev().find(|v| v.name == name).copied();
+        if let Some(var) = var {
+            if var.kind == VarKind::Field && self.is_function() {
+                self.report(
+                    span,
+                    format!("field `{}` cannot be used in a function.", name),
+                );
+            }
+        }
+        var
+    }
+
+    fn statements(&mut self, statements: &'a [Statement]) {
+        for statement in statements.iter() {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &'a Statement) {
+        match statement {
+            Statement::Let {
+                name,
+                span,
+                index,
+                value,
+            } => {
+                if self.lookup(name, *span).is_none() {
+                    self.report(
+                        *span,
+                        format!("assignment to undeclared variable `{}`.", name),
+                    );
+                }
+                if let Some(index) = index {
+                    self.expression(index);
+                }
+                self.expression(value);
+            }
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                self.expression(condition);
+                self.statements(then);
+                self.statements(otherwise);
+            }
+            Statement::While { condition, body } => {
+                self.expression(condition);
+                self.statements(body);
+            }
+            Statement::Do(call) => self.call(call),
+            Statement::Return { span, value } => {
+                let subroutine = match self.subroutine {
+                    Some(subroutine) => subroutine,
+                    None => return,
+                };
+                match (&subroutine.ret, value) {
+                    (None, Some(_)) => self.report(
+                        *span,
+                        format!("void subroutine `{}` returns a value.", subroutine.name),
+                    ),
+                    (Some(_), None) => {
+                        self.report(*span, format!("`{}` must return a value.", subroutine.name))
+                    }
+                    _ => (),
+                }
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+        }
+    }
+
+    fn expression(&mut self, expr: &'a Expr) {
+        match &expr.kind {
+            ExprKind::This if self.is_function() => self.report(
+                expr.span,
+                "`this` cannot be used in a function.".to_string(),
+            ),
+            ExprKind::Var(name) | ExprKind::Index(name, _) => {
+                if self.lookup(name, expr.span).is_none() {
+                    self.report(expr.span, format!("undeclared variable `{}`.", name));
+                }
+                if let ExprKind::Index(_, index) = &expr.kind {
+                    self.expression(index);
+                }
+            }
+            ExprKind::Call(call) => self.call(call),
+            ExprKind::Unary(_, inner) => self.expression(inner),
+            ExprKind::Binary(_, left, right) => {
+                self.expression(left);
+                self.expression(right);
+            }
+            _ => (),
+        }
+    }
+
+    fn call(&mut self, call: &'a Call) {
+        for arg in call.args.iter() {
+            self.expression(arg);
+        }
+        // (呼び出すクラス, オブジェクトを通した呼び出しか)
+        let (class, on_object) = match &call.receiver {
+            None => {
+                if self.is_function() {
+                    if let Some((Method, _)) = self.signature(&self.class.name, &call.name) {
+                        self.report(
+                            call.span,
+                            format!(
+                                "method `{}` cannot be called from a function without an object.",
+                                call.name
+                            ),
+                        );
+                        return;
+                    }
+                }
+                (self.class.name.clone(), true)
+            }
+            Some(receiver) => match self.lookup(receiver, call.span) {
+                Some(var) => match &var.ty {
+                    Type::Class(class) => (class.clone(), true),
+                    _ => {
+                        self.report(
+                            call.span,
+                            format!("`{}` is not an object and has no methods.", receiver),
+                        );
+                        return;
+                    }
+                },
+                None if self.signatures.contains_key(receiver) => (receiver.clone(), false),
+                None => {
+                    self.report(
+                        call.span,
+                        format!("undeclared variable or class `{}`.", receiver),
+                    );
+                    return;
+                }
+            },
+        };
+
+        let full = format!("{}.{}", class, call.name);
+        let (kind, args) = match self.signature(&class, &call.name) {
+            Some(signature) => signature,
+            None => {
+                // 宣言の無いクラスの中身は調べられない
+                if self.signatures.contains_key(&class) {
+                    self.report(call.span, format!("unknown subroutine `{}`.", full));
+                }
+                return;
+            }
+        };
+        match (kind, on_object) {
+            (Method, false) => self.report(
+                call.span,
+                format!("method `{}` is called as a function.", full),
+            ),
+            (Function, true) | (Constructor, true) => self.report(
+                call.span,
+                format!("`{}` is not a method; call it as `{}`.", full, full),
+            ),
+            _ => (),
+        }
+        if args != call.args.len() {
+            self.report(
+                call.span,
+                format!(
+                    "`{}` expects {} arguments, got {}.",
+                    full,
+                    args,
+                    call.args.len()
+                ),
+            );
+        }
+    }
+
+    fn signature(&self, class: &str, name: &str) -> Option<(SubroutineKind, usize)> {
+        self.signatures.get(class)?.get(name).copied()
+    }
+}
+
+// どの道を通っても return に着くか
+fn returns(statements: &[Statement]) -> bool {
+    statements.iter().any(|statement| match statement {
+        Statement::Return { .. } => true,
+        Statement::If {
+            then, otherwise, ..
+        } => returns(then) && returns(otherwise),
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check;
+    use crate::jack::parse;
+
+    fn errors(sources: &[(&str, &str)]) -> Vec<String> {
+        let classes = sources
+            .iter()
+            .map(|(file, src)| parse(file, src).unwrap())
+            .collect::<Vec<_>>();
+        check(&classes).iter().map(|e| e.to_string()).collect()
+    }
+
+    const POINT: &str = "class Point {
+    field int x, y;
+    static int count;
+
+    constructor Point new(int ax, int ay) {
+        let x = ax;
+        let y = ay;
+        let count = count + 1;
+        return this;
+    }
+
+    method int getX() { return x; }
+
+    function int count() { return count; }
+
+    method int distance(Point other) {
+        var int dx;
+        let dx = x - other.getX();
+        if (dx < 0) { return -dx; } else { return dx; }
+    }
+}
+";
+
+    #[test]
+    fn for_valid_program() {
+        let main = "class Main {
+    function void main() {
+        var Point p;
+        var String s;
+        let p = Point.new(3, 4);
+        let s = \"x\";
+        do Output.printInt(p.distance(Point.new(1, Point.count())));
+        do s.appendChar(65);
+        return;
+    }
+}
+";
+        assert_eq!(
+            errors(&[("Point.jack", POINT), ("Main.jack", main)]),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn for_errors() {
+        let main = "class Main {
+    field int size;
+
+    function int main() {
+        var Point p;
+        var int n;
+        let p = Point.new(1);
+        let m = 3;
+        let size = n;
+        do Point.getX();
+        do p.count();
+        do n.run();
+        do helper(this);
+        do Output.printInt(q);
+        do Screen.drawLine(1, 2);
+        do Foo.bar();
+        if (n) { return 1; }
+    }
+
+    method void helper(Main other) {
+        return 2;
+    }
+
+    method Thing make() {
+        return;
+    }
+}
+";
+        assert_eq!(
+            errors(&[("Point.jack", POINT), ("Main.jack", main)]),
+            vec![
+                "Main.jack:7:17: `Point.new` expects 2 arguments, got 1.",
+                "Main.jack:8:13: assignment to undeclared variable `m`.",
+                "Main.jack:9:13: field `size` cannot be used in a function.",
+                "Main.jack:10:12: method `Point.getX` is called as a function.",
+                "Main.jack:11:12: `Point.count` is not a method; call it as `Point.count`.",
+                "Main.jack:12:12: `n` is not an object and has no methods.",
+                "Main.jack:13:19: `this` cannot be used in a function.",
+                "Main.jack:13:12: method `helper` cannot be called from a function without an object.",
+                "Main.jack:14:28: undeclared variable `q`.",
+                "Main.jack:15:12: `Screen.drawLine` expects 4 arguments, got 2.",
+                "Main.jack:16:12: undeclared variable or class `Foo`.",
+                "Main.jack:4:18: `main` can reach its end without `return`.",
+                "Main.jack:21:9: void subroutine `helper` returns a value.",
+                "Main.jack:24:18: unknown class `Thing`.",
+                "Main.jack:25:9: `make` must return a value.",
+            ]
+        );
+    }
+}
@@ -0,0 +1,546 @@
+#![allow(dead_code)]
+
+use crate::jack::{Call, Class, Expr, ExprKind, Statement, SubroutineKind, Type, Var, VarKind};
+
+// どの最適化も初めは無効
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Options {
+    fold: bool,
+    prune: bool,
+    double: bool,
+}
+
+impl Options {
+    pub fn new() -> Self {
+        Options::default()
+    }
+
+    pub fn all() -> Self {
+        Options::new().fold(true).prune(true).double(true)
+    }
+
+    // 定数だけの式をコンパイル時に計算する
+    pub fn fold(mut self, fold: bool) -> Self {
+        self.fold = fold;
+        self
+    }
+
+    // 条件が定数の if と while の、通らない側を消す
+    pub fn prune(mut self, prune: bool) -> Self {
+        self.prune = prune;
+        self
+    }
+
+    // 2 の累乗との掛け算を Math.multiply ではなく足し算の繰り返しにする
+    pub fn double(mut self, double: bool) -> Self {
+        self.double = double;
+        self
+    }
+}
+
+// jack_check::check を通ったクラスを VM コードにする
+pub fn compile(class: &Class, options: &Options) -> String {
+    let mut compiler = Compiler {
+        class,
+        options: *options,
+        lines: vec![],
+        scope: vec![],
+        counter: 0,
+    };
+    compiler.class();
+    let mut vm = compiler.lines.join("\n");
+    vm.push('\n');
+    vm
+}
+
+// 空行とコメントを除いた命令の数
+pub fn count(vm: &str) -> usize {
+    vm.lines()
+        .map(|line| line.split("//").next().unwrap_or("").trim())
+        .filter(|line| !line.is_empty())
+        .count()
+}
+
+// 定数だけの式なら 16 ビットの値を返す。0 での割り算は実行時に任せる
+pub fn constant(expr: &Expr) -> Option<i16> {
+    let truth = |b: bool| if b { -1 } else { 0 };
+    match &expr.kind {
+        ExprKind::Int(n) => Some(*n as i16),
+        ExprKind::True => Some(-1),
+        ExprKind::False | ExprKind::Null => Some(0),
+        ExprKind::Unary('-', inner) => constant(inner).map(|v| v.wrapping_neg()),
+        ExprKind::Unary(_, inner) => constant(inner).map(|v| !v),
+        ExprKind::Binary(op, left, right) => {
+            let (x, y) = (constant(left)?, constant(right)?);
+            match op {
+                '+' => Some(x.wrapping_add(y)),
+                '-' => Some(x.wrapping_sub(y)),
+                '*' => Some(x.wrapping_mul(y)),
+                '/' if y != 0 => Some(x.wrapping_div(y)),
+                '&' => Some(x & y),
+                '|' => Some(x | y),
+                '<' => Some(truth(x < y)),
+                '>' => Some(truth(x > y)),
+                '=' => Some(truth(x == y)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+struct Compiler<'a> {
+    class: &'a Class,
+    options: Options,
+    lines: Vec<String>,
+    // 変数と、その区画の中の番号
+    scope: Vec<(&'a Var, usize)>,
+    counter: usize,
+}
+
+impl<'a> Compiler<'a> {
+    fn emit(&mut self, line: String) {
+        self.lines.push(line);
+    }
+
+    fn label(&mut self, name: &str) -> String {
+        self.counter += 1;
+        format!("{}{}", name, self.counter)
+    }
+
+    fn declare(&mut self, var: &'a Var, offset: usize) {
+        let index = self
+            .scope
+            .iter()
+            .filter(|(v, _)| v.kind == var.kind)
+            .count();
+        self.scope.push((var, index + offset));
+    }
+
+    fn lookup(&self, name: &str) -> Option<(&'a Var, usize)> {
+        self.scope
+            .iter()
+            .rev()
+            .find(|(v, _)| v.name == name)
+            .copied()
+    }
+
+    fn segment(kind: VarKind) -> &'static str {
+        match kind {
+            VarKind::Static => "static",
+            VarKind::Field => "this",
+            VarKind::Argument => "argument",
+            VarKind::Local => "local",
+        }
+    }
+
+    fn push_var(&mut self, name: &str) {
+        let (var, index) = self.lookup(name).expect("checked by jack_check");
+        self.emit(format!("push {} {}", Compiler::segment(var.kind), index));
+    }
+
+    fn class(&mut self) {
+        for var in self.class.vars.iter() {
+            self.declare(var, 0);
+        }
+        let fields = self
+            .class
+            .vars
+            .iter()
+            .filter(|v| v.kind == VarKind::Field)
+            .count();
+        let class_vars = self.scope.len();
+        for subroutine in self.class.subroutines.iter() {
+            self.scope.truncate(class_vars);
+            // method の argument 0 は this
+            let offset = if subroutine.kind == SubroutineKind::Method {
+                1
+            } else {
+                0
+            };
+            for var in subroutine.params.iter() {
+                self.declare(var, offset);
+            }
+            for var in subroutine.locals.iter() {
+                self.declare(var, 0);
+            }
+            self.emit(format!(
+                "function {}.{} {}",
+                self.class.name,
+                subroutine.name,
+                subroutine.locals.len()
+            ));
+            match subroutine.kind {
+                SubroutineKind::Constructor => {
+                    self.emit(format!("push constant {}", fields));
+                    self.emit("call Memory.alloc 1".to_string());
+                    self.emit("pop pointer 0".to_string());
+                }
+                SubroutineKind::Method => {
+                    self.emit("push argument 0".to_string());
+                    self.emit("pop pointer 0".to_string());
+                }
+                SubroutineKind::Function => (),
+            }
+            self.statements(&subroutine.body);
+        }
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements.iter() {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Let {
+                name, index, value, ..
+            } => match index {
+                None => {
+                    self.expression(value);
+                    let (var, index) = self.lookup(name).expect("checked by jack_check");
+                    self.emit(format!("pop {} {}", Compiler::segment(var.kind), index));
+                }
+                Some(index) => {
+                    self.push_var(name);
+                    self.expression(index);
+                    self.emit("add".to_string());
+                    self.expression(value);
+                    self.emit("pop temp 0".to_string());
+                    self.emit("pop pointer 1".to_string());
+                    self.emit("push temp 0".to_string());
+                    self.emit("pop that 0".to_string());
+                }
+            },
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                if let (true, Some(value)) = (self.options.prune, constant(condition)) {
+                    self.statements(if value != 0 { then } else { otherwise });
+                    return;
+                }
+                let (other, end) = (self.label("IF_FALSE"), self.label("IF_END"));
+                self.expression(condition);
+                self.emit("not".to_string());
+                self.emit(format!("if-goto {}", other));
+                self.statements(then);
+                if otherwise.is_empty() {
+                    self.emit(format!("label {}", other));
+                } else {
+                    self.emit(format!("goto {}", end));
+                    self.emit(format!("label {}", other));
+                    self.statements(otherwise);
+                    self.emit(format!("label {}", end));
+                }
+            }
+            Statement::While { condition, body } => {
+                if let (true, Some(0)) = (self.options.prune, constant(condition)) {
+                    return;
+                }
+                let (top, end) = (self.label("WHILE_EXP"), self.label("WHILE_END"));
+                self.emit(format!("label {}", top));
+                self.expression(condition);
+                self.emit("not".to_string());
+                self.emit(format!("if-goto {}", end));
+                self.statements(body);
+                self.emit(format!("goto {}", top));
+                self.emit(format!("label {}", end));
+            }
+            Statement::Do(call) => {
+                self.call(call);
+                self.emit("pop temp 0".to_string());
+            }
+            Statement::Return { value, .. } => {
+                match value {
+                    Some(value) => self.expression(value),
+                    None => self.emit("push constant 0".to_string()),
+                }
+                self.emit("return".to_string());
+            }
+        }
+    }
+
+    // 負の数は !v が 0..32767 に入ることを使う
+    fn push_constant(&mut self, value: i16) {
+        if value >= 0 {
+            self.emit(format!("push constant {}", value));
+        } else {
+            self.emit(format!("push constant {}", !value));
+            self.emit("not".to_string());
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        if self.options.fold {
+            if let Some(value) = constant(expr) {
+                self.push_constant(value);
+                return;
+            }
+        }
+        match &expr.kind {
+            ExprKind::Int(n) => self.emit(format!("push constant {}", n)),
+            ExprKind::Str(s) => {
+                self.emit(format!("push constant {}", s.chars().count()));
+                self.emit("call String.new 1".to_string());
+                for c in s.chars() {
+                    self.emit(format!("push constant {}", c as u32));
+                    self.emit("call String.appendChar 2".to_string());
+                }
+            }
+            ExprKind::True => {
+                self.emit("push constant 0".to_string());
+                self.emit("not".to_string());
+            }
+            ExprKind::False | ExprKind::Null => self.emit("push constant 0".to_string()),
+            ExprKind::This => self.emit("push pointer 0".to_string()),
+            ExprKind::Var(name) => self.push_var(name),
+            ExprKind::Index(name, index) => {
+                self.push_var(name);
+                self.expression(index);
+                self.emit("add".to_string());
+                self.emit("pop pointer 1".to_string());
+                self.emit("push that 0".to_string());
+            }
+            ExprKind::Call(call) => self.call(call),
+            ExprKind::Unary(op, inner) => {
+                self.expression(inner);
+                self.emit(if *op == '-' { "neg" } else { "not" }.to_string());
+            }
+            ExprKind::Binary('*', left, right) if self.options.double => {
+                match (power_of_two(left), power_of_two(right)) {
+                    (_, Some(k)) => self.doubled(left, k),
+                    (Some(k), _) => self.doubled(right, k),
+                    _ => self.binary('*', left, right),
+                }
+            }
+            ExprKind::Binary(op, left, right) => self.binary(*op, left, right),
+        }
+    }
+
+    fn binary(&mut self, op: char, left: &Expr, right: &Expr) {
+        self.expression(left);
+        self.expression(right);
+        let command = match op {
+            '+' => "add",
+            '-' => "sub",
+            '*' => "call Math.multiply 2",
+            '/' => "call Math.divide 2",
+            '&' => "and",
+            '|' => "or",
+            '<' => "lt",
+            '>' => "gt",
+            _ => "eq",
+        };
+        self.emit(command.to_string());
+    }
+
+    // x * 2^k を k 回の x + x にする。temp 0 に置いて二回積む
+    fn doubled(&mut self, expr: &Expr, k: u32) {
+        self.expression(expr);
+        for _ in 0..k {
+            self.emit("pop temp 0".to_string());
+            self.emit("push temp 0".to_string());
+            self.emit("push temp 0".to_string());
+            self.emit("add".to_string());
+        }
+    }
+
+    fn call(&mut self, call: &Call) {
+        let (class, this) = match &call.receiver {
+            None => {
+                self.emit("push pointer 0".to_string());
+                (self.class.name.clone(), 1)
+            }
+            Some(receiver) => match self.lookup(receiver) {
+                Some((var, _)) => {
+                    self.push_var(receiver);
+                    match &var.ty {
+                        Type::Class(class) => (class.clone(), 1),
+                        _ => unreachable!("checked by jack_check"),
+                    }
+                }
+                None => (receiver.clone(), 0),
+            },
+        };
+        for arg in call.args.iter() {
+            self.expression(arg);
+        }
+        self.emit(format!(
+            "call {}.{} {}",
+            class,
+            call.name,
+            call.args.len() + this
+        ));
+    }
+}
+
+// 2^k (k は 1 から 14) なら k
+fn power_of_two(expr: &Expr) -> Option<u32> {
+    match constant(expr) {
+        Some(v) if v > 1 && (v as u16).is_power_of_two() => Some((v as u16).trailing_zeros()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compile, count, Options};
+    use crate::jack::parse;
+    use crate::jack_check::check;
+    use crate::vm::{Native, Program, Vm};
+
+    const MAIN: &str = "class Main {
+    static int result, total, flag;
+    static Counter counter;
+
+    function void main() {
+        var Array a;
+        var int i;
+        let a = Array.new(5);
+        let i = 0;
+        while (i < 5) {
+            let a[i] = (i * 8) + (3 * 4) - (-2);
+            let i = i + 1;
+        }
+        let total = Main.sum(a, 5);
+        if ((2 + 2) = 4) {
+            let flag = ~(1 < 0);
+        } else {
+            let flag = 99;
+        }
+        while (false) {
+            let flag = 0;
+        }
+        let counter = Counter.new(10);
+        do counter.add(7);
+        do counter.add(-3 * 4);
+        let result = counter.get() * 16 / 4;
+        return;
+    }
+
+    function int sum(Array a, int n) {
+        var int i, s;
+        while (i < n) {
+            let s = s + a[i];
+            let i = i + 1;
+        }
+        return s;
+    }
+}
+";
+
+    const COUNTER: &str = "class Counter {
+    field int value;
+
+    constructor Counter new(int start) {
+        let value = start;
+        return this;
+    }
+
+    method void add(int n) {
+        let value = value + n;
+        return;
+    }
+
+    method int get() {
+        return value;
+    }
+}
+";
+
+    const SYS: &str = "class Sys {
+    function void init() {
+        do Main.main();
+        while (true) {}
+        return;
+    }
+}
+";
+
+    // Math と Memory.alloc、Array.new だけを Rust で用意する
+    struct Os {
+        heap: usize,
+    }
+
+    impl Native for Os {
+        fn call(&mut self, name: &str, args: &[i16], _: &mut [i16]) -> Option<Result<i16, String>> {
+            let value = match name {
+                "Math.multiply" => args[0].wrapping_mul(args[1]),
+                "Math.divide" => args[0] / args[1],
+                "Memory.alloc" | "Array.new" => {
+                    self.heap += args[0] as usize;
+                    (self.heap - args[0] as usize) as i16
+                }
+                _ => return None,
+            };
+            Some(Ok(value))
+        }
+    }
+
+    // Main の static と、生成した VM コード全体
+    fn run(options: &Options) -> (Vec<i16>, String) {
+        let classes = [("Main", MAIN), ("Counter", COUNTER), ("Sys", SYS)]
+            .iter()
+            .map(|(name, src)| parse(&format!("{}.jack", name), src).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(check(&classes), vec![]);
+        let vm = classes
+            .iter()
+            .map(|class| (class.name.clone(), compile(class, options)))
+            .collect::<Vec<_>>();
+        let code = vm.iter().map(|(_, src)| src.as_str()).collect::<String>();
+        let files = vm
+            .iter()
+            .map(|(name, src)| (name.as_str(), src.as_str()))
+            .collect::<Vec<_>>();
+        let mut machine =
+            Vm::new(Program::parse(&files).unwrap()).with_native(Box::new(Os { heap: 2048 }));
+        machine.run(100_000).unwrap();
+        let statics = (0..3)
+            .map(|i| machine.peek(machine.static_address("Main", i).unwrap()))
+            .collect();
+        (statics, code)
+    }
+
+    #[test]
+    fn for_options() {
+        let (expected, plain) = run(&Options::new());
+        // (10 + 7 - 12) * 16 / 4、(0 + 8 + 16 + 24 + 32) + 5 * 14、~(1 < 0)
+        assert_eq!(expected, vec![20, 150, -1]);
+        let (statics, folded) = run(&Options::new().fold(true));
+        assert_eq!(statics, expected);
+        assert!(count(&folded) < count(&plain));
+        let (statics, pruned) = run(&Options::new().fold(true).prune(true));
+        assert_eq!(statics, expected);
+        assert!(count(&pruned) < count(&folded));
+        assert!(!pruned.contains("push constant 99"));
+        // 命令は増えるが、Math.multiply は i * 8 と counter.get() * 16 の分だけ減る
+        let multiplies = |vm: &str| vm.matches("call Math.multiply").count();
+        let (statics, doubled) = run(&Options::all());
+        assert_eq!(statics, expected);
+        assert_eq!(multiplies(&doubled), multiplies(&pruned) - 2);
+    }
+
+    #[test]
+    fn for_generated_code() {
+        let class = parse(
+            "A.jack",
+            "class A { function int f(int x) { if (1 < 2) { return x * 4; } return -3 + 1; } }",
+        )
+        .unwrap();
+        assert_eq!(
+            compile(&class, &Options::new()),
+            "function A.f 0\npush constant 1\npush constant 2\nlt\nnot\nif-goto IF_FALSE1\n\
+             push argument 0\npush constant 4\ncall Math.multiply 2\nreturn\nlabel IF_FALSE1\n\
+             push constant 3\nneg\npush constant 1\nadd\nreturn\n"
+        );
+        assert_eq!(
+            compile(&class, &Options::all()),
+            "function A.f 0\npush argument 0\npop temp 0\npush temp 0\npush temp 0\nadd\n\
+             pop temp 0\npush temp 0\npush temp 0\nadd\nreturn\npush constant 1\nnot\nreturn\n"
+        );
+    }
+}
@@ -0,0 +1,322 @@
+#![allow(dead_code)]
+
+use crate::logic::Word;
+use crate::snapshot::{Reader, Snapshot, SnapshotError, Writer};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+pub const KBD: usize = 24576;
+
+// Hack の特殊キー (表示可能な ASCII 文字はそのままのコード)
+pub const NEWLINE: u16 = 128;
+pub const BACKSPACE: u16 = 129;
+pub const LEFT: u16 = 130;
+pub const UP: u16 = 131;
+pub const RIGHT: u16 = 132;
+pub const DOWN: u16 = 133;
+pub const HOME: u16 = 134;
+pub const END: u16 = 135;
+pub const PAGE_UP: u16 = 136;
+pub const PAGE_DOWN: u16 = 137;
+pub const INSERT: u16 = 138;
+pub const DELETE: u16 = 139;
+pub const ESC: u16 = 140;
+pub const F1: u16 = 141;
+pub const F12: u16 = 152;
+
+const NAMES: [(&str, u16); 15] = [
+    ("release", 0),
+    ("space", 32),
+    ("newline", NEWLINE),
+    ("enter", NEWLINE),
+    ("backspace", BACKSPACE),
+    ("left", LEFT),
+    ("up", UP),
+    ("right", RIGHT),
+    ("down", DOWN),
+    ("home", HOME),
+    ("end", END),
+    ("pageup", PAGE_UP),
+    ("pagedown", PAGE_DOWN),
+    ("insert", INSERT),
+    ("delete", DELETE),
+];
+
+// cycle 番目のサイクルから code が押されている (0 は離す)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub cycle: u64,
+    pub code: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for ScriptError {
+    fn fmt(&self, dest: &mut Formatter) -> fmt::Result {
+        write!(dest, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keyboard {
+    script: Vec<KeyEvent>,
+    next: usize,
+    cycle: u64,
+    code: u16,
+}
+
+impl Keyboard {
+    pub fn new() -> Self {
+        Keyboard::from_events(vec![])
+    }
+
+    pub fn from_events(mut script: Vec<KeyEvent>) -> Self {
+        for event in script.iter() {
+            if !is_key_code(event.code) {
+                panic!(
+                    "`Keyboard::from_events` fail: {} is not a Hack key code.",
+                    event.code
+                );
+            }
+        }
+        // 同じサイクルのイベントは書かれた順に適用する
+        script.sort_by_key(|event| event.cycle);
+        Keyboard {
+            script,
+            next: 0,
+            cycle: 0,
+            code: 0,
+        }
+    }
+
+    pub fn from_script(src: &str) -> Result<Self, ScriptError> {
+        Ok(Keyboard::from_events(parse_script(src)?))
+    }
+
+    // cycle までのイベントを適用する。時間を戻したときは最初から再生し直す
+    pub fn tick(&mut self, cycle: u64) {
+        if cycle < self.cycle {
+            self.next = 0;
+            self.code = 0;
+        }
+        while self.next < self.script.len() && self.script[self.next].cycle <= cycle {
+            self.code = self.script[self.next].code;
+            self.next += 1;
+        }
+        self.cycle = cycle;
+    }
+
+    pub fn press(&mut self, code: u16) {
+        if !is_key_code(code) {
+            panic!("`Keyboard::press` fail: {} is not a Hack key code.", code);
+        }
+        self.code = code;
+    }
+
+    pub fn release(&mut self) {
+        self.code = 0;
+    }
+
+    pub fn code(&self) -> u16 {
+        self.code
+    }
+
+    pub fn output(&self) -> Word {
+        Word::from(self.code)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next == self.script.len()
+    }
+}
+
+impl Snapshot for Keyboard {
+    const KIND: &'static str = "Keyboard";
+    fn save(&self, w: &mut Writer) {
+        w.size(self.script.len());
+        for event in self.script.iter() {
+            w.u64(event.cycle);
+            w.u16(event.code);
+        }
+        w.size(self.next);
+        w.u64(self.cycle);
+        w.u16(self.code);
+    }
+    fn load(r: &mut Reader) -> Result<Self, SnapshotError> {
+        let key = |code: u16| {
+            if is_key_code(code) {
+                Ok(code)
+            } else {
+                Err(SnapshotError::Invalid(format!(
+                    "{} is not a Hack key code.",
+                    code
+                )))
+            }
+        };
+        let mut script = vec![];
+        for _ in 0..r.size()? {
+            let cycle = r.u64()?;
+            let code = key(r.u16()?)?;
+            script.push(KeyEvent { cycle, code });
+        }
+        let next = r.u32()? as usize;
+        if next > script.len() {
+            return Err(SnapshotError::Invalid(format!(
+                "keyboard is at event {} of {}.",
+                next,
+                script.len()
+            )));
+        }
+        Ok(Keyboard {
+            script,
+            next,
+            cycle: r.u64()?,
+            code: key(r.u16()?)?,
+        })
+    }
+}
+
+// 一行に「サイクル キー」。キーは数字、一文字、または left などの名前。# 以降はコメント
+pub fn parse_script(src: &str) -> Result<Vec<KeyEvent>, ScriptError> {
+    let mut events = vec![];
+    for (index, line) in src.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |message: String| ScriptError {
+            line: index + 1,
+            message,
+        };
+        let mut fields = line.splitn(2, char::is_whitespace);
+        let cycle = fields.next().unwrap_or("");
+        let key = fields.next().unwrap_or("").trim();
+        let cycle = cycle
+            .parse::<u64>()
+            .map_err(|_| error(format!("`{}` is not a cycle number.", cycle)))?;
+        if key.is_empty() {
+            return Err(error("missing key.".to_string()));
+        }
+        let code = parse_key(key).ok_or_else(|| error(format!("unknown key `{}`.", key)))?;
+        events.push(KeyEvent { cycle, code });
+    }
+    Ok(events)
+}
+
+pub fn parse_key(key: &str) -> Option<u16> {
+    if let Ok(code) = key.parse::<u16>() {
+        return if is_key_code(code) { Some(code) } else { None };
+    }
+    let lower = key.to_lowercase();
+    if let Some((_, code)) = NAMES.iter().find(|(name, _)| *name == lower) {
+        return Some(*code);
+    }
+    if lower == "esc" || lower == "escape" {
+        return Some(ESC);
+    }
+    if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse::<u16>().ok()) {
+        if (1..=12).contains(&n) {
+            return Some(F1 + n - 1);
+        }
+    }
+    let mut chars = key.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if (' '..='~').contains(&c) => Some(c as u16),
+        _ => None,
+    }
+}
+
+pub fn is_key_code(code: u16) -> bool {
+    code == 0 || (32..=126).contains(&code) || (NEWLINE..=F12).contains(&code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_key, parse_script, KeyEvent, Keyboard, LEFT, NEWLINE};
+    use crate::logic::Word;
+
+    #[test]
+    fn for_parse_key() {
+        assert_eq!(parse_key("130"), Some(LEFT));
+        assert_eq!(parse_key("Left"), Some(LEFT));
+        assert_eq!(parse_key("enter"), Some(NEWLINE));
+        assert_eq!(parse_key("a"), Some(97));
+        assert_eq!(parse_key("A"), Some(65));
+        assert_eq!(parse_key("space"), Some(32));
+        assert_eq!(parse_key("esc"), Some(140));
+        assert_eq!(parse_key("F12"), Some(152));
+        assert_eq!(parse_key("release"), Some(0));
+        assert_eq!(parse_key("127"), None);
+        assert_eq!(parse_key("f13"), None);
+        assert_eq!(parse_key("ab"), None);
+    }
+
+    #[test]
+    fn for_parse_script() {
+        let events = parse_script("# demo\n10 left\n\n25 0  # release\n40 q\n").unwrap();
+        assert_eq!(
+            events,
+            vec![
+                KeyEvent {
+                    cycle: 10,
+                    code: 130
+                },
+                KeyEvent { cycle: 25, code: 0 },
+                KeyEvent {
+                    cycle: 40,
+                    code: 113
+                },
+            ]
+        );
+        let e = parse_script("10 left\nx right\n").unwrap_err();
+        assert_eq!(e.line, 2);
+        assert_eq!(format!("{}", e), "line 2: `x` is not a cycle number.");
+        let e = parse_script("10 left\n\n20 hyper\n").unwrap_err();
+        assert_eq!(format!("{}", e), "line 3: unknown key `hyper`.");
+        let e = parse_script("10\n").unwrap_err();
+        assert_eq!(format!("{}", e), "line 1: missing key.");
+    }
+
+    #[test]
+    fn for_keyboard_replay() {
+        let mut keyboard = Keyboard::from_script("10 left\n25 release\n40 q\n40 w\n").unwrap();
+        let mut codes = vec![];
+        for cycle in 0..50 {
+            keyboard.tick(cycle);
+            codes.push(keyboard.code());
+        }
+        assert_eq!(codes[9], 0);
+        assert_eq!(codes[10], 130);
+        assert_eq!(codes[24], 130);
+        assert_eq!(codes[25], 0);
+        assert_eq!(codes[40], 119);
+        assert_eq!(keyboard.output(), Word::from(119u16));
+        assert!(keyboard.is_finished());
+
+        // 巻き戻しても同じ結果になる
+        keyboard.tick(12);
+        assert_eq!(keyboard.code(), 130);
+        keyboard.tick(30);
+        assert_eq!(keyboard.code(), 0);
+    }
+
+    #[test]
+    fn for_keyboard_manual() {
+        let mut keyboard = Keyboard::new();
+        assert_eq!(keyboard.output(), Word::from(0u16));
+        keyboard.press(NEWLINE);
+        assert_eq!(keyboard.output(), Word::from(128u16));
+        keyboard.release();
+        assert_eq!(keyboard.code(), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn for_keyboard_bad_code() {
+        Keyboard::new().press(200);
+    }
+}
@@ -0,0 +1,198 @@
+#![allow(dead_code)]
+
+use crate::logic::bit::{self, I, O};
+use crate::logic::{Signal, Word};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+// 64 本の独立した入力を一度に流す。i 番目のビットが i 番目の入力。
+// logic と arithmetic のチップは Signal について総称的なので、そのまま Lanes を流せる
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Lanes(pub u64);
+
+impl Signal for Lanes {
+    const LOW: Self = Lanes(0);
+    const HIGH: Self = Lanes(!0);
+    fn nand(self, other: Self) -> Self {
+        Lanes(!(self.0 & other.0))
+    }
+}
+
+impl Lanes {
+    pub const WIDTH: usize = 64;
+
+    // 全レーンに同じ値
+    pub fn splat(value: bit) -> Self {
+        match value {
+            O => Lanes::LOW,
+            I => Lanes::HIGH,
+        }
+    }
+
+    // 足りないレーンは O
+    pub fn pack(bits: &[bit]) -> Self {
+        if bits.len() > Lanes::WIDTH {
+            panic!("`Lanes::pack` fail: {} bits is over 64.", bits.len());
+        }
+        Lanes(
+            bits.iter()
+                .enumerate()
+                .fold(0, |acc, (i, b)| if *b == I { acc | 1 << i } else { acc }),
+        )
+    }
+
+    pub fn lane(&self, index: usize) -> bit {
+        if (self.0 >> index) & 1 == 1 {
+            I
+        } else {
+            O
+        }
+    }
+}
+
+impl Display for Lanes {
+    fn fmt(&self, dest: &mut Formatter) -> fmt::Result {
+        write!(dest, "{:064b}", self.0)
+    }
+}
+
+// Word と同じく 0 番目が最上位ビット
+pub type Lanes16 = [Lanes; 16];
+
+pub fn pack16(words: &[Word]) -> Lanes16 {
+    map16(|i| Lanes::pack(&words.iter().map(|w| w[i]).collect::<Vec<_>>()))
+}
+
+pub fn unpack16(a: &Lanes16, lane: usize) -> Word {
+    Word::new(map16(|i| a[i].lane(lane)))
+}
+
+fn map16<S, F>(f: F) -> [S; 16]
+where
+    S: Copy,
+    F: FnMut(usize) -> S,
+{
+    let mut f = f;
+    let first = f(0);
+    let mut out = [first; 16];
+    for (i, value) in out.iter_mut().enumerate().skip(1) {
+        *value = f(i);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::bit::{I, O};
+    use crate::truth_table::to_bits;
+    use crate::{arithmetic, logic};
+
+    // n 本の入力の 2^n 通りを 1 レーンずつ並べる (n は 6 まで)
+    fn exhaustive(inputs: usize) -> Vec<Lanes> {
+        (0..inputs)
+            .map(|k| {
+                let bits = (0..1usize << inputs)
+                    .map(|v| to_bits(v, inputs)[k])
+                    .collect::<Vec<_>>();
+                Lanes::pack(&bits)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn for_pack() {
+        let lanes = Lanes::pack(&[I, O, I]);
+        assert_eq!(lanes, Lanes(0b101));
+        assert_eq!((lanes.lane(0), lanes.lane(1), lanes.lane(63)), (I, O, O));
+        assert_eq!(Lanes::splat(I), Lanes(u64::MAX));
+        let words = [
+            Word::from(1u16),
+            Word::from(0x8000u16),
+            Word::from(0x1234u16),
+        ];
+        let packed = pack16(&words);
+        assert_eq!(packed[15], Lanes(0b001));
+        assert_eq!(packed[0], Lanes(0b010));
+        assert_eq!(unpack16(&packed, 2), words[2]);
+    }
+
+    #[test]
+    fn for_fulladder() {
+        let i = exhaustive(3);
+        let [carry, sum] = arithmetic::FullAdder(i[0], i[1], i[2]);
+        for lane in 0..8 {
+            let b = to_bits(lane, 3);
+            assert_eq!(
+                [carry.lane(lane), sum.lane(lane)],
+                arithmetic::FullAdder(b[0], b[1], b[2])
+            );
+        }
+    }
+
+    #[test]
+    fn for_mux8way16() {
+        // 8 本の入力語と sel を、64 レーンずつ 16 回ずらして流す
+        let mut seed = 0x9e37u16;
+        for _ in 0..16 {
+            let vectors = (0..Lanes::WIDTH)
+                .map(|_| {
+                    let mut words = [Word::from(0u16); 8];
+                    for word in words.iter_mut() {
+                        seed = seed.wrapping_mul(25173).wrapping_add(13849);
+                        *word = Word::from(seed);
+                    }
+                    words
+                })
+                .collect::<Vec<_>>();
+            let sel = exhaustive(6);
+            let w = (0..8)
+                .map(|k| pack16(&vectors.iter().map(|v| v[k]).collect::<Vec<_>>()))
+                .collect::<Vec<_>>();
+            let out = logic::Mux8Way16(
+                w[0],
+                w[1],
+                w[2],
+                w[3],
+                w[4],
+                w[5],
+                w[6],
+                w[7],
+                [sel[3], sel[4], sel[5]],
+            );
+            for (lane, v) in vectors.iter().enumerate() {
+                let s = [sel[3].lane(lane), sel[4].lane(lane), sel[5].lane(lane)];
+                let expected = logic::Mux8Way16(v[0], v[1], v[2], v[3], v[4], v[5], v[6], v[7], s);
+                assert_eq!(unpack16(&out, lane), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn for_alu() {
+        // 制御ビット 64 通りを 1 回で
+        let c = exhaustive(6);
+        for (x, y) in [(17u16, 3u16), (0, 65535), (40000, 123), (32767, 32767)].iter() {
+            let xs = map16(|i| Lanes::splat(Word::from(*x)[i]));
+            let ys = map16(|i| Lanes::splat(Word::from(*y)[i]));
+            let (out, zr, ng) = arithmetic::ALU(xs, ys, c[0], c[1], c[2], c[3], c[4], c[5]);
+            for lane in 0..Lanes::WIDTH {
+                let b = to_bits(lane, 6);
+                let expected = arithmetic::ALU(
+                    Word::from(*x),
+                    Word::from(*y),
+                    b[0],
+                    b[1],
+                    b[2],
+                    b[3],
+                    b[4],
+                    b[5],
+                );
+                assert_eq!(
+                    (unpack16(&out, lane), zr.lane(lane), ng.lane(lane)),
+                    expected
+                );
+            }
+        }
+    }
+}
@@ -1,13 +1,21 @@
-#![allow(dead_code, non_snake_case, non_camel_case_types)]
+#![allow(
+    dead_code,
+    non_snake_case,
+    non_camel_case_types,
+    clippy::too_many_arguments,
+    clippy::wrong_self_convention
+)]
 
 use crate::logic::bit::{I, O};
+use crate::snapshot::{Reader, Snapshot, SnapshotError, Writer};
 use num_traits::{FromPrimitive, PrimInt};
-use std::convert::{From, Into};
+use std::convert::From;
 use std::fmt;
 use std::fmt::{Display, Formatter};
 use std::ops::{Index, IndexMut};
+use std::str::FromStr;
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum bit {
     O,
     I,
@@ -52,6 +60,64 @@ impl Word {
             self[9], self[10], self[11], self[12], self[13], self[14], self[15],
         ]
     }
+
+    pub fn signed(&self) -> i16 {
+        u16::from(*self) as i16
+    }
+
+    pub fn unsigned(&self) -> u16 {
+        u16::from(*self)
+    }
+
+    // .tst と .cmp の %B0101、%D-1、%X7FFF に加え、0x7fff と 10 進数を読む。
+    // 10 進数は -32768..=65535
+    pub fn parse_literal(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+        let error = || format!("`{}` is not a 16 bit literal.", s);
+        let (radix, digits) = match s.get(..2) {
+            Some("%B") | Some("%b") => (2, &s[2..]),
+            Some("%X") | Some("%x") | Some("0x") | Some("0X") => (16, &s[2..]),
+            Some("%D") | Some("%d") => (10, &s[2..]),
+            _ => (10, s),
+        };
+        let max_digits = match radix {
+            2 => 16,
+            16 => 4,
+            _ => 6,
+        };
+        if digits.is_empty() || digits.len() > max_digits || digits.starts_with('+') {
+            return Err(error());
+        }
+        if radix == 10 {
+            return match digits.parse::<i32>() {
+                Ok(n) if (-32768..=65535).contains(&n) => Ok(Word::from(n as u16)),
+                _ => Err(error()),
+            };
+        }
+        match u16::from_str_radix(digits, radix) {
+            Ok(n) => Ok(Word::from(n)),
+            Err(_) => Err(error()),
+        }
+    }
+}
+
+// 書式は u16 と同じ。{:016b} や {:04X} で .cmp と同じ桁数になる
+impl fmt::Binary for Word {
+    fn fmt(&self, dest: &mut Formatter) -> fmt::Result {
+        fmt::Binary::fmt(&self.unsigned(), dest)
+    }
+}
+
+impl fmt::LowerHex for Word {
+    fn fmt(&self, dest: &mut Formatter) -> fmt::Result {
+        fmt::LowerHex::fmt(&self.unsigned(), dest)
+    }
+}
+
+impl fmt::UpperHex for Word {
+    fn fmt(&self, dest: &mut Formatter) -> fmt::Result {
+        fmt::UpperHex::fmt(&self.unsigned(), dest)
+    }
 }
 
 impl Display for Word {
@@ -69,7 +135,7 @@ impl Index<usize> for Word {
     type Output = bit;
     fn index(&self, index: usize) -> &Self::Output {
         if index > 15 {
-            panic!(format!("index fail: {} is out of range.", index));
+            panic!("index fail: {} is out of range.", index);
         }
         &self.0[index]
     }
@@ -78,101 +144,215 @@ impl Index<usize> for Word {
 impl IndexMut<usize> for Word {
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
         if index > 15 {
-            panic!(format!("index_mut fail: {}, out of range.", index));
+            panic!("index_mut fail: {}, out of range.", index);
         }
         self.0.index_mut(index)
     }
 }
 
-impl From<String> for Word {
-    fn from(mut s: String) -> Self {
-        s = s.split_terminator(' ').collect();
+impl FromStr for Word {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s: String = s.split_terminator(' ').collect();
         let mut instruction = Word::new([O; 16]);
         let mut i = 0usize;
         for bytes in s.bytes() {
+            if i == 16 {
+                return Err("`Word::from_string` fail: need less than 16.".to_string());
+            }
             instruction[i] = match bytes {
                 48 => O,
                 49 => I,
-                _ => panic!("`WOrd::from_string` fail: cannot find 0 or 1."),
+                _ => return Err("`Word::from_string` fail: cannot find 0 or 1.".to_string()),
             };
-            i = if i == 16 {
-                panic!("`Word::from_string` fail: need less than 16.")
-            } else {
-                i + 1
-            }
+            i += 1
         }
         if i == 16 {
-            instruction
+            Ok(instruction)
         } else {
-            panic!("`Word::from_string` fail: need more than 15")
+            Err("`Word::from_string` fail: need more than 15".to_string())
         }
     }
 }
 
+impl From<String> for Word {
+    fn from(s: String) -> Self {
+        Word::from(s.as_str())
+    }
+}
+
 impl From<&str> for Word {
     fn from(s: &str) -> Self {
-        let s: String = s.split_terminator(' ').collect();
-        let mut instruction = Word::new([O; 16]);
-        let mut i = 0usize;
-        for bytes in s.bytes() {
-            instruction[i] = match bytes {
-                48 => O,
-                49 => I,
-                _ => panic!("`Word::from_string` fail: cannot find 0 or 1."),
-            };
-            i = if i == 16 {
-                panic!("`Word::from_string` fail: need less than 16.")
-            } else {
-                i + 1
-            }
+        match s.parse() {
+            Ok(word) => word,
+            Err(message) => panic!("{}", message),
         }
-        if i == 16 {
-            instruction
-        } else {
-            panic!("`Word::from_string` fail: need more than 15")
+    }
+}
+
+// 0 番目が最上位ビット
+impl From<u16> for Word {
+    fn from(value: u16) -> Self {
+        let mut word = Word::new([O; 16]);
+        for i in 0..16 {
+            word[i] = if (value >> (15 - i)) & 1 == 1 { I } else { O };
+        }
+        word
+    }
+}
+
+impl From<i16> for Word {
+    fn from(value: i16) -> Self {
+        Word::from(value as u16)
+    }
+}
+
+impl From<Word> for i16 {
+    fn from(word: Word) -> Self {
+        word.signed()
+    }
+}
+
+impl From<Word> for u16 {
+    fn from(word: Word) -> Self {
+        word.0
+            .iter()
+            .fold(0, |acc, b| (acc << 1) | if *b == I { 1 } else { 0 })
+    }
+}
+
+impl Snapshot for bit {
+    const KIND: &'static str = "bit";
+    fn save(&self, w: &mut Writer) {
+        w.u8(if *self == I { 1 } else { 0 });
+    }
+    fn load(r: &mut Reader) -> Result<Self, SnapshotError> {
+        match r.u8()? {
+            0 => Ok(O),
+            1 => Ok(I),
+            n => Err(SnapshotError::Invalid(format!("{} is not a bit.", n))),
+        }
+    }
+}
+
+impl Snapshot for Word {
+    const KIND: &'static str = "Word";
+    fn save(&self, w: &mut Writer) {
+        w.u16(u16::from(*self));
+    }
+    fn load(r: &mut Reader) -> Result<Self, SnapshotError> {
+        Ok(Word::from(r.u16()?))
+    }
+}
+
+// Nand さえあれば、どのゲートも作れる。bit のほか lanes::Lanes や netlist::Net でも同じチップを動かせる
+pub trait Signal: Copy + PartialEq + fmt::Debug {
+    const LOW: Self;
+    const HIGH: Self;
+    fn nand(self, other: Self) -> Self;
+    // チップの入口と出口。Net だけが部品の区切りとして記録し、ほかは何もしない
+    fn begin(_chip: &str, _inputs: &[(&'static str, &[Self])]) {}
+    fn end(_outputs: &[(&'static str, &[Self])]) {}
+}
+
+impl Signal for bit {
+    const LOW: Self = O;
+    const HIGH: Self = I;
+    fn nand(self, other: Self) -> Self {
+        match self {
+            O => match other {
+                O => I,
+                I => I,
+            },
+            I => match other {
+                O => I,
+                I => O,
+            },
         }
     }
 }
 
-pub fn Nand(a: bit, b: bit) -> bit {
-    match a {
-        O => match b {
-            O => I,
-            I => I,
-        },
-        I => match b {
-            O => I,
-            I => O,
-        },
+// 16 本の Signal。Word は bit の、[S; 16] は任意の Signal の場合。0 番目が最上位ビット
+pub trait Signal16:
+    Copy + PartialEq + fmt::Debug + Index<usize, Output = <Self as Signal16>::Bit> + IndexMut<usize>
+{
+    type Bit: Signal;
+    fn new(bits: [Self::Bit; 16]) -> Self;
+    fn bits(&self) -> [Self::Bit; 16];
+    fn splat(value: Self::Bit) -> Self {
+        Self::new([value; 16])
+    }
+}
+
+impl Signal16 for Word {
+    type Bit = bit;
+    fn new(bits: [bit; 16]) -> Self {
+        Word(bits)
+    }
+    fn bits(&self) -> [bit; 16] {
+        self.0
+    }
+}
+
+impl<S: Signal> Signal16 for [S; 16] {
+    type Bit = S;
+    fn new(bits: [S; 16]) -> Self {
+        bits
+    }
+    fn bits(&self) -> [S; 16] {
+        *self
     }
 }
 
-pub fn Not(a: bit) -> bit {
-    Nand(a, a)
+pub fn Nand<S: Signal>(a: S, b: S) -> S {
+    a.nand(b)
+}
+
+pub fn Not<S: Signal>(a: S) -> S {
+    S::begin("Not", &[("in", &[a])]);
+    let out = Nand(a, a);
+    S::end(&[("out", &[out])]);
+    out
 }
 
-pub fn And(a: bit, b: bit) -> bit {
-    Nand(Nand(a, b), Nand(a, b))
+pub fn And<S: Signal>(a: S, b: S) -> S {
+    S::begin("And", &[("a", &[a]), ("b", &[b])]);
+    let out = Nand(Nand(a, b), Nand(a, b));
+    S::end(&[("out", &[out])]);
+    out
 }
 
-pub fn Or(a: bit, b: bit) -> bit {
-    Nand(Nand(a, a), Nand(b, b))
+pub fn Or<S: Signal>(a: S, b: S) -> S {
+    S::begin("Or", &[("a", &[a]), ("b", &[b])]);
+    let out = Nand(Nand(a, a), Nand(b, b));
+    S::end(&[("out", &[out])]);
+    out
 }
 
-pub fn Xor(a: bit, b: bit) -> bit {
-    Or(And(a, Not(b)), And(b, Not(a)))
+pub fn Xor<S: Signal>(a: S, b: S) -> S {
+    S::begin("Xor", &[("a", &[a]), ("b", &[b])]);
+    let out = Or(And(a, Not(b)), And(b, Not(a)));
+    S::end(&[("out", &[out])]);
+    out
 }
 
-pub fn Mux(a: bit, b: bit, sel: bit) -> bit {
-    Or(And(a, Not(sel)), And(b, sel))
+pub fn Mux<S: Signal>(a: S, b: S, sel: S) -> S {
+    S::begin("Mux", &[("a", &[a]), ("b", &[b]), ("sel", &[sel])]);
+    let out = Or(And(a, Not(sel)), And(b, sel));
+    S::end(&[("out", &[out])]);
+    out
 }
 
-pub fn DMux(inc: bit, sel: bit) -> [bit; 2] {
-    [And(inc, Not(sel)), And(inc, sel)]
+pub fn DMux<S: Signal>(inc: S, sel: S) -> [S; 2] {
+    S::begin("DMux", &[("in", &[inc]), ("sel", &[sel])]);
+    let out = [And(inc, Not(sel)), And(inc, sel)];
+    S::end(&[("a", &[out[0]]), ("b", &[out[1]])]);
+    out
 }
 
-pub fn Not16(a: Word) -> Word {
-    Word::new([
+pub fn Not16<W: Signal16>(a: W) -> W {
+    W::Bit::begin("Not16", &[("in", &a.bits())]);
+    let out = W::new([
         Not(a[0]),
         Not(a[1]),
         Not(a[2]),
@@ -189,11 +369,14 @@ pub fn Not16(a: Word) -> Word {
         Not(a[13]),
         Not(a[14]),
         Not(a[15]),
-    ])
+    ]);
+    W::Bit::end(&[("out", &out.bits())]);
+    out
 }
 
-pub fn And16(a: Word, b: Word) -> Word {
-    Word::new([
+pub fn And16<W: Signal16>(a: W, b: W) -> W {
+    W::Bit::begin("And16", &[("a", &a.bits()), ("b", &b.bits())]);
+    let out = W::new([
         And(a[0], b[0]),
         And(a[1], b[1]),
         And(a[2], b[2]),
@@ -210,11 +393,14 @@ pub fn And16(a: Word, b: Word) -> Word {
         And(a[13], b[13]),
         And(a[14], b[14]),
         And(a[15], b[15]),
-    ])
+    ]);
+    W::Bit::end(&[("out", &out.bits())]);
+    out
 }
 
-pub fn Or16(a: Word, b: Word) -> Word {
-    Word::new([
+pub fn Or16<W: Signal16>(a: W, b: W) -> W {
+    W::Bit::begin("Or16", &[("a", &a.bits()), ("b", &b.bits())]);
+    let out = W::new([
         Or(a[0], b[0]),
         Or(a[1], b[1]),
         Or(a[2], b[2]),
@@ -231,11 +417,17 @@ pub fn Or16(a: Word, b: Word) -> Word {
         Or(a[13], b[13]),
         Or(a[14], b[14]),
         Or(a[15], b[15]),
-    ])
+    ]);
+    W::Bit::end(&[("out", &out.bits())]);
+    out
 }
 
-pub fn Mux16(a: Word, b: Word, sel: bit) -> Word {
-    Word::new([
+pub fn Mux16<W: Signal16>(a: W, b: W, sel: W::Bit) -> W {
+    W::Bit::begin(
+        "Mux16",
+        &[("a", &a.bits()), ("b", &b.bits()), ("sel", &[sel])],
+    );
+    let out = W::new([
         Mux(a[0], b[0], sel),
         Mux(a[1], b[1], sel),
         Mux(a[2], b[2], sel),
@@ -252,21 +444,36 @@ pub fn Mux16(a: Word, b: Word, sel: bit) -> Word {
         Mux(a[13], b[13], sel),
         Mux(a[14], b[14], sel),
         Mux(a[15], b[15], sel),
-    ])
+    ]);
+    W::Bit::end(&[("out", &out.bits())]);
+    out
 }
 
-pub fn Or8Way(a: [bit; 8]) -> bit {
-    Or(
+pub fn Or8Way<S: Signal>(a: [S; 8]) -> S {
+    S::begin("Or8Way", &[("in", &a)]);
+    let out = Or(
         Or(Or(a[0], a[1]), Or(a[2], a[3])),
         Or(Or(a[4], a[5]), Or(a[6], a[7])),
-    )
+    );
+    S::end(&[("out", &[out])]);
+    out
 }
 
-pub fn Mux4Way16(a: Word, b: Word, c: Word, d: Word, sel: [bit; 2]) -> Word {
-    let mux2 = |a: bit, b: bit, c: bit, d: bit, s0: bit, s1: bit| -> bit {
+pub fn Mux4Way16<W: Signal16>(a: W, b: W, c: W, d: W, sel: [W::Bit; 2]) -> W {
+    W::Bit::begin(
+        "Mux4Way16",
+        &[
+            ("a", &a.bits()),
+            ("b", &b.bits()),
+            ("c", &c.bits()),
+            ("d", &d.bits()),
+            ("sel", &sel),
+        ],
+    );
+    let mux2 = |a: W::Bit, b: W::Bit, c: W::Bit, d: W::Bit, s0: W::Bit, s1: W::Bit| -> W::Bit {
         Mux(Mux(a, b, s1), Mux(c, d, s1), s0)
     };
-    Word::new([
+    let out = W::new([
         mux2(a[0], b[0], c[0], d[0], sel[0], sel[1]),
         mux2(a[1], b[1], c[1], d[1], sel[0], sel[1]),
         mux2(a[2], b[2], c[2], d[2], sel[0], sel[1]),
@@ -283,32 +490,38 @@ pub fn Mux4Way16(a: Word, b: Word, c: Word, d: Word, sel: [bit; 2]) -> Word {
         mux2(a[13], b[13], c[13], d[13], sel[0], sel[1]),
         mux2(a[14], b[14], c[14], d[14], sel[0], sel[1]),
         mux2(a[15], b[15], c[15], d[15], sel[0], sel[1]),
-    ])
-}
-
-pub fn Mux8Way16(
-    a: Word,
-    b: Word,
-    c: Word,
-    d: Word,
-    e: Word,
-    f: Word,
-    g: Word,
-    h: Word,
-    s: [bit; 3],
-) -> Word {
-    let mux3 = |a: bit,
-                b: bit,
-                c: bit,
-                d: bit,
-                e: bit,
-                f: bit,
-                g: bit,
-                h: bit,
-                s0: bit,
-                s1: bit,
-                s2: bit|
-     -> bit {
+    ]);
+    W::Bit::end(&[("out", &out.bits())]);
+    out
+}
+
+pub fn Mux8Way16<W: Signal16>(a: W, b: W, c: W, d: W, e: W, f: W, g: W, h: W, s: [W::Bit; 3]) -> W {
+    W::Bit::begin(
+        "Mux8Way16",
+        &[
+            ("a", &a.bits()),
+            ("b", &b.bits()),
+            ("c", &c.bits()),
+            ("d", &d.bits()),
+            ("e", &e.bits()),
+            ("f", &f.bits()),
+            ("g", &g.bits()),
+            ("h", &h.bits()),
+            ("sel", &s),
+        ],
+    );
+    let mux3 = |a: W::Bit,
+                b: W::Bit,
+                c: W::Bit,
+                d: W::Bit,
+                e: W::Bit,
+                f: W::Bit,
+                g: W::Bit,
+                h: W::Bit,
+                s0: W::Bit,
+                s1: W::Bit,
+                s2: W::Bit|
+     -> W::Bit {
         Mux(
             Mux(Mux(a, b, s2), Mux(c, d, s2), s1),
             Mux(Mux(e, f, s2), Mux(g, h, s2), s1),
@@ -316,7 +529,7 @@ pub fn Mux8Way16(
         )
     };
 
-    Word::new([
+    let out = W::new([
         mux3(
             a[0], b[0], c[0], d[0], e[0], f[0], g[0], h[0], s[0], s[1], s[2],
         ),
@@ -365,20 +578,31 @@ pub fn Mux8Way16(
         mux3(
             a[15], b[15], c[15], d[15], e[15], f[15], g[15], h[15], s[0], s[1], s[2],
         ),
-    ])
+    ]);
+    W::Bit::end(&[("out", &out.bits())]);
+    out
 }
 
-pub fn DMux4Way(inc: bit, sel: [bit; 2]) -> [bit; 4] {
-    [
+pub fn DMux4Way<S: Signal>(inc: S, sel: [S; 2]) -> [S; 4] {
+    S::begin("DMux4Way", &[("in", &[inc]), ("sel", &sel)]);
+    let out = [
         And(Not(sel[0]), And(Not(sel[1]), inc)),
         And(Not(sel[0]), And(sel[1], inc)),
         And(sel[0], And(Not(sel[1]), inc)),
         And(sel[0], And(sel[1], inc)),
-    ]
+    ];
+    S::end(&[
+        ("a", &out[0..1]),
+        ("b", &out[1..2]),
+        ("c", &out[2..3]),
+        ("d", &out[3..4]),
+    ]);
+    out
 }
 
-pub fn DMux8Way(inc: bit, sel: [bit; 3]) -> [bit; 8] {
-    [
+pub fn DMux8Way<S: Signal>(inc: S, sel: [S; 3]) -> [S; 8] {
+    S::begin("DMux8Way", &[("in", &[inc]), ("sel", &sel)]);
+    let out = [
         And(And(Not(sel[0]), Not(sel[1])), And(Not(sel[2]), inc)),
         And(And(Not(sel[0]), Not(sel[1])), And(sel[2], inc)),
         And(And(Not(sel[0]), sel[1]), And(Not(sel[2]), inc)),
@@ -387,74 +611,200 @@ pub fn DMux8Way(inc: bit, sel: [bit; 3]) -> [bit; 8] {
         And(And(sel[0], Not(sel[1])), And(sel[2], inc)),
         And(And(sel[0], sel[1]), And(Not(sel[2]), inc)),
         And(And(sel[0], sel[1]), And(sel[2], inc)),
-    ]
+    ];
+    S::end(&[
+        ("a", &out[0..1]),
+        ("b", &out[1..2]),
+        ("c", &out[2..3]),
+        ("d", &out[3..4]),
+        ("e", &out[4..5]),
+        ("f", &out[5..6]),
+        ("g", &out[6..7]),
+        ("h", &out[7..8]),
+    ]);
+    out
+}
+
+// sel の本数で幅が決まる。inputs は 2 の sel.len() 乗個で、sel[0] が最上位。
+// 部品の名前は MuxNWay16_8 のように幅ごとに変える (同じ名前だと Verilog のモジュールが自分自身を含んでしまう)。
+// sel がなければ配線だけなので部品にしない (幅 0 のポートは Verilog で書けない)
+pub fn MuxNWay16<W: Signal16>(inputs: &[W], sel: &[W::Bit]) -> W {
+    assert_eq!(inputs.len(), 1 << sel.len(), "MuxNWay16 needs 2^sel inputs");
+    let (s0, rest) = match sel.split_first() {
+        None => return inputs[0],
+        Some(split) => split,
+    };
+    let flat = inputs.iter().flat_map(|w| w.bits()).collect::<Vec<_>>();
+    W::Bit::begin(
+        &format!("MuxNWay16_{}", inputs.len()),
+        &[("in", &flat), ("sel", sel)],
+    );
+    let half = inputs.len() / 2;
+    let out = Mux16(
+        MuxNWay16(&inputs[..half], rest),
+        MuxNWay16(&inputs[half..], rest),
+        *s0,
+    );
+    W::Bit::end(&[("out", &out.bits())]);
+    out
+}
+
+pub fn DMuxNWay<S: Signal>(inc: S, sel: &[S]) -> Vec<S> {
+    let (s0, rest) = match sel.split_first() {
+        None => return vec![inc],
+        Some(split) => split,
+    };
+    S::begin(
+        &format!("DMuxNWay_{}", 1 << sel.len()),
+        &[("in", &[inc]), ("sel", sel)],
+    );
+    let [a, b] = DMux(inc, *s0);
+    let out = [DMuxNWay(a, rest), DMuxNWay(b, rest)].concat();
+    S::end(&[("out", &out)]);
+    out
 }
 
 #[cfg(test)]
 mod tests {
-    use super::bit::{I, O};
+    use super::bit::{self, I, O};
     use super::Word;
     use super::{
-        And, And16, DMux, DMux4Way, DMux8Way, Mux, Mux16, Mux4Way16, Mux8Way16, Nand, Not, Not16,
-        Or, Or16, Or8Way, Xor,
+        And, And16, DMux, DMux4Way, DMux8Way, DMuxNWay, Mux, Mux16, Mux4Way16, Mux8Way16,
+        MuxNWay16, Nand, Not, Not16, Or, Or16, Or8Way, Xor,
     };
+    use crate::truth_table::TruthTable;
+
+    // TruthTable が並べた行を、手で書いた真理値表 (入力 | 出力 を一行ずつ) と比べる
+    fn check(inputs: &[&str], outputs: &[&str], chip: fn(&[bit]) -> Vec<bit>, expected: &str) {
+        let bits = |cells: &str| {
+            cells
+                .split_whitespace()
+                .map(|c| if c == "1" { I } else { O })
+                .collect::<Vec<_>>()
+        };
+        let expected = expected
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let mut cells = line.split('|');
+                (bits(cells.next().unwrap()), bits(cells.next().unwrap()))
+            })
+            .collect::<Vec<_>>();
+        let table = TruthTable::new(inputs, outputs, chip);
+        let actual = table
+            .rows()
+            .iter()
+            .map(|row| (row.inputs.clone(), row.outputs.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(actual, expected);
+    }
 
     #[test]
     fn for_nand() {
-        assert_eq!(Nand(O, O), I);
-        assert_eq!(Nand(O, I), I);
-        assert_eq!(Nand(I, O), I);
-        assert_eq!(Nand(I, I), O)
+        check(
+            &["a", "b"],
+            &["out"],
+            |i| vec![Nand(i[0], i[1])],
+            "
+            0 0 | 1
+            0 1 | 1
+            1 0 | 1
+            1 1 | 0
+            ",
+        );
     }
 
     #[test]
     fn for_not() {
-        assert_eq!(Not(O), I);
-        assert_eq!(Not(I), O);
+        check(
+            &["in"],
+            &["out"],
+            |i| vec![Not(i[0])],
+            "
+            0 | 1
+            1 | 0
+            ",
+        );
     }
 
     #[test]
     fn for_and() {
-        assert_eq!(And(O, O), O);
-        assert_eq!(And(O, I), O);
-        assert_eq!(And(I, O), O);
-        assert_eq!(And(I, I), I);
+        check(
+            &["a", "b"],
+            &["out"],
+            |i| vec![And(i[0], i[1])],
+            "
+            0 0 | 0
+            0 1 | 0
+            1 0 | 0
+            1 1 | 1
+            ",
+        );
     }
 
     #[test]
     fn for_or() {
-        assert_eq!(Or(O, O), O);
-        assert_eq!(Or(O, I), I);
-        assert_eq!(Or(I, O), I);
-        assert_eq!(Or(I, I), I);
+        check(
+            &["a", "b"],
+            &["out"],
+            |i| vec![Or(i[0], i[1])],
+            "
+            0 0 | 0
+            0 1 | 1
+            1 0 | 1
+            1 1 | 1
+            ",
+        );
     }
 
     #[test]
     fn for_xor() {
-        assert_eq!(Xor(O, O), O);
-        assert_eq!(Xor(O, I), I);
-        assert_eq!(Xor(I, O), I);
-        assert_eq!(Xor(I, I), O);
+        check(
+            &["a", "b"],
+            &["out"],
+            |i| vec![Xor(i[0], i[1])],
+            "
+            0 0 | 0
+            0 1 | 1
+            1 0 | 1
+            1 1 | 0
+            ",
+        );
     }
 
     #[test]
     fn for_mux() {
-        assert_eq!(Mux(O, O, O), O);
-        assert_eq!(Mux(O, I, O), O);
-        assert_eq!(Mux(I, O, O), I);
-        assert_eq!(Mux(I, I, O), I);
-        assert_eq!(Mux(O, O, I), O);
-        assert_eq!(Mux(O, I, I), I);
-        assert_eq!(Mux(I, O, I), O);
-        assert_eq!(Mux(I, I, I), I);
+        check(
+            &["a", "b", "sel"],
+            &["out"],
+            |i| vec![Mux(i[0], i[1], i[2])],
+            "
+            0 0 0 | 0
+            0 0 1 | 0
+            0 1 0 | 0
+            0 1 1 | 1
+            1 0 0 | 1
+            1 0 1 | 0
+            1 1 0 | 1
+            1 1 1 | 1
+            ",
+        );
     }
 
     #[test]
     fn for_dmux() {
-        assert_eq!(DMux(O, O), [O, O]);
-        assert_eq!(DMux(O, I), [O, O]);
-        assert_eq!(DMux(I, O), [I, O]);
-        assert_eq!(DMux(I, I), [O, I]);
+        check(
+            &["in", "sel"],
+            &["a", "b"],
+            |i| DMux(i[0], i[1]).to_vec(),
+            "
+            0 0 | 0 0
+            0 1 | 0 0
+            1 0 | 1 0
+            1 1 | 0 1
+            ",
+        );
     }
 
     #[test]
@@ -463,6 +813,99 @@ mod tests {
         assert_eq!(format!("{}", O), "O".to_string());
     }
 
+    #[test]
+    fn for_word_from_str() {
+        assert_eq!(
+            Word::from("0000 0000 0000 0111"),
+            Word([O, O, O, O, O, O, O, O, O, O, O, O, O, I, I, I])
+        );
+        assert_eq!(
+            "1111000011110000".parse::<Word>(),
+            Ok(Word([I, I, I, I, O, O, O, O, I, I, I, I, O, O, O, O]))
+        );
+        assert!("000000000000000".parse::<Word>().is_err());
+        assert!("00000000000000000".parse::<Word>().is_err());
+        assert!("000000000000000x".parse::<Word>().is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn for_word_from_bad_str() {
+        let _ = Word::from("0101");
+    }
+
+    #[test]
+    fn for_word_u16() {
+        assert_eq!(
+            Word::from(5u16),
+            Word([O, O, O, O, O, O, O, O, O, O, O, O, O, I, O, I])
+        );
+        assert_eq!(
+            Word::from(0x8000u16),
+            Word([I, O, O, O, O, O, O, O, O, O, O, O, O, O, O, O])
+        );
+        for value in [0u16, 1, 255, 12345, 32767, 32768, 65535].iter() {
+            assert_eq!(u16::from(Word::from(*value)), *value);
+        }
+    }
+
+    #[test]
+    fn for_word_format() {
+        let word = Word::from(-2i16);
+        assert_eq!(format!("{:b}", word), "1111111111111110");
+        assert_eq!(format!("{:016b}", Word::from(5u16)), "0000000000000101");
+        assert_eq!(format!("{:x}", word), "fffe");
+        assert_eq!(format!("{:#06X}", Word::from(0x1au16)), "0x001A");
+        assert_eq!((word.signed(), word.unsigned()), (-2, 65534));
+        assert_eq!(i16::from(Word::from(32768u16)), -32768);
+        assert_eq!(format!("{}", Word::from(1u16)).len(), 51);
+    }
+
+    #[test]
+    fn for_word_parse_literal() {
+        for (literal, value) in [
+            ("%B0000000000000101", 5u16),
+            ("%B101", 5),
+            ("%D-1", 65535),
+            ("%D32767", 32767),
+            ("%X7FFF", 32767),
+            ("%xff", 255),
+            ("0x8000", 32768),
+            ("-32768", 32768),
+            ("65535", 65535),
+            (" 12 ", 12),
+        ]
+        .iter()
+        {
+            assert_eq!(
+                Word::parse_literal(literal),
+                Ok(Word::from(*value)),
+                "{}",
+                literal
+            );
+        }
+        for literal in [
+            "",
+            "%B",
+            "%B2",
+            "%B10000000000000000",
+            "%X10000",
+            "0x",
+            "%D65536",
+            "-32769",
+            "+1",
+            "%X+1",
+            "twelve",
+        ]
+        .iter()
+        {
+            assert_eq!(
+                Word::parse_literal(literal),
+                Err(format!("`{}` is not a 16 bit literal.", literal.trim()))
+            );
+        }
+    }
+
     #[test]
     fn for_not16() {
         assert_eq!(
@@ -772,33 +1215,115 @@ mod tests {
 
     #[test]
     fn for_dmux4way() {
-        assert_eq!(DMux4Way(O, [O, O]), [O, O, O, O]);
-        assert_eq!(DMux4Way(O, [O, I]), [O, O, O, O]);
-        assert_eq!(DMux4Way(O, [I, O]), [O, O, O, O]);
-        assert_eq!(DMux4Way(O, [I, I]), [O, O, O, O]);
-        assert_eq!(DMux4Way(I, [O, O]), [I, O, O, O]);
-        assert_eq!(DMux4Way(I, [O, I]), [O, I, O, O]);
-        assert_eq!(DMux4Way(I, [I, O]), [O, O, I, O]);
-        assert_eq!(DMux4Way(I, [I, I]), [O, O, O, I]);
+        check(
+            &["in", "sel1", "sel0"],
+            &["a", "b", "c", "d"],
+            |i| DMux4Way(i[0], [i[1], i[2]]).to_vec(),
+            "
+            0 0 0 | 0 0 0 0
+            0 0 1 | 0 0 0 0
+            0 1 0 | 0 0 0 0
+            0 1 1 | 0 0 0 0
+            1 0 0 | 1 0 0 0
+            1 0 1 | 0 1 0 0
+            1 1 0 | 0 0 1 0
+            1 1 1 | 0 0 0 1
+            ",
+        );
     }
 
     #[test]
     fn for_dmux8way() {
-        assert_eq!(DMux8Way(O, [O, O, O]), [O, O, O, O, O, O, O, O]);
-        assert_eq!(DMux8Way(O, [O, O, I]), [O, O, O, O, O, O, O, O]);
-        assert_eq!(DMux8Way(O, [O, I, O]), [O, O, O, O, O, O, O, O]);
-        assert_eq!(DMux8Way(O, [O, I, I]), [O, O, O, O, O, O, O, O]);
-        assert_eq!(DMux8Way(O, [I, O, O]), [O, O, O, O, O, O, O, O]);
-        assert_eq!(DMux8Way(O, [I, O, I]), [O, O, O, O, O, O, O, O]);
-        assert_eq!(DMux8Way(O, [I, I, O]), [O, O, O, O, O, O, O, O]);
-        assert_eq!(DMux8Way(O, [I, I, I]), [O, O, O, O, O, O, O, O]);
-        assert_eq!(DMux8Way(I, [O, O, O]), [I, O, O, O, O, O, O, O]);
-        assert_eq!(DMux8Way(I, [O, O, I]), [O, I, O, O, O, O, O, O]);
-        assert_eq!(DMux8Way(I, [O, I, O]), [O, O, I, O, O, O, O, O]);
-        assert_eq!(DMux8Way(I, [O, I, I]), [O, O, O, I, O, O, O, O]);
-        assert_eq!(DMux8Way(I, [I, O, O]), [O, O, O, O, I, O, O, O]);
-        assert_eq!(DMux8Way(I, [I, O, I]), [O, O, O, O, O, I, O, O]);
-        assert_eq!(DMux8Way(I, [I, I, O]), [O, O, O, O, O, O, I, O]);
-        assert_eq!(DMux8Way(I, [I, I, I]), [O, O, O, O, O, O, O, I]);
+        check(
+            &["in", "sel2", "sel1", "sel0"],
+            &["a", "b", "c", "d", "e", "f", "g", "h"],
+            |i| DMux8Way(i[0], [i[1], i[2], i[3]]).to_vec(),
+            "
+            0 0 0 0 | 0 0 0 0 0 0 0 0
+            0 0 0 1 | 0 0 0 0 0 0 0 0
+            0 0 1 0 | 0 0 0 0 0 0 0 0
+            0 0 1 1 | 0 0 0 0 0 0 0 0
+            0 1 0 0 | 0 0 0 0 0 0 0 0
+            0 1 0 1 | 0 0 0 0 0 0 0 0
+            0 1 1 0 | 0 0 0 0 0 0 0 0
+            0 1 1 1 | 0 0 0 0 0 0 0 0
+            1 0 0 0 | 1 0 0 0 0 0 0 0
+            1 0 0 1 | 0 1 0 0 0 0 0 0
+            1 0 1 0 | 0 0 1 0 0 0 0 0
+            1 0 1 1 | 0 0 0 1 0 0 0 0
+            1 1 0 0 | 0 0 0 0 1 0 0 0
+            1 1 0 1 | 0 0 0 0 0 1 0 0
+            1 1 1 0 | 0 0 0 0 0 0 1 0
+            1 1 1 1 | 0 0 0 0 0 0 0 1
+            ",
+        );
+    }
+
+    // sel の値 s を上位から n 本のビットにする
+    fn sel(s: usize, n: usize) -> Vec<bit> {
+        (0..n)
+            .map(|i| if (s >> (n - 1 - i)) & 1 == 1 { I } else { O })
+            .collect()
+    }
+
+    #[test]
+    fn for_muxnway16() {
+        let words = (0..64u16)
+            .map(|i| Word::from(i.wrapping_mul(4111) ^ 0xa5a5))
+            .collect::<Vec<_>>();
+        let w = &words;
+        for s in 0..4 {
+            let b = sel(s, 2);
+            assert_eq!(
+                MuxNWay16(&w[..4], &b),
+                Mux4Way16(w[0], w[1], w[2], w[3], [b[0], b[1]])
+            );
+        }
+        for s in 0..8 {
+            let b = sel(s, 3);
+            assert_eq!(
+                MuxNWay16(&w[..8], &b),
+                Mux8Way16(
+                    w[0],
+                    w[1],
+                    w[2],
+                    w[3],
+                    w[4],
+                    w[5],
+                    w[6],
+                    w[7],
+                    [b[0], b[1], b[2]]
+                )
+            );
+        }
+        for s in 0..64 {
+            assert_eq!(MuxNWay16(w, &sel(s, 6)), w[s]);
+        }
+        assert_eq!(MuxNWay16(&w[..1], &[]), w[0]);
+        assert_eq!(MuxNWay16(&w[..2], &[I]), Mux16(w[0], w[1], I));
+    }
+
+    #[test]
+    fn for_dmuxnway() {
+        for inc in [O, I].iter() {
+            assert_eq!(DMuxNWay(*inc, &[]), vec![*inc]);
+            assert_eq!(DMuxNWay(*inc, &[I]), DMux(*inc, I).to_vec());
+            for s in 0..4 {
+                let b = sel(s, 2);
+                assert_eq!(DMuxNWay(*inc, &b), DMux4Way(*inc, [b[0], b[1]]).to_vec());
+            }
+            for s in 0..8 {
+                let b = sel(s, 3);
+                assert_eq!(
+                    DMuxNWay(*inc, &b),
+                    DMux8Way(*inc, [b[0], b[1], b[2]]).to_vec()
+                );
+            }
+        }
+        for s in 0..512 {
+            let out = DMuxNWay(I, &sel(s, 9));
+            assert_eq!(out.len(), 512);
+            assert!(out.iter().enumerate().all(|(i, b)| (*b == I) == (i == s)));
+        }
     }
 }
@@ -1,7 +1,35 @@
 mod arithmetic;
+mod assembler;
+mod cli;
+mod debugger;
+mod disasm;
+mod emulator;
+mod fault;
+mod jack;
+mod jack_check;
+mod jack_compiler;
+mod keyboard;
+mod lanes;
 mod logic;
+mod muldiv;
+mod netlist;
+mod rom;
+mod screen;
 mod sequential;
+mod simulator;
+mod snapshot;
+mod translator;
+mod truth_table;
+mod vcd;
+mod verilog;
+mod vm;
+
+use std::env;
+use std::io;
+use std::process;
 
 fn main() {
-    println!("Hello, world!");
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let code = cli::main(&args, &mut io::stdout(), &mut io::stderr());
+    process::exit(code);
 }
@@ -0,0 +1,329 @@
+#![allow(dead_code, non_snake_case)]
+
+use crate::arithmetic::{Add16, FullAdder, ShiftLeft16, ShiftRight16};
+use crate::logic::bit::{I, O};
+use crate::logic::{And16, Mux16, Not16, Or, Signal, Signal16, Word};
+use crate::netlist::{Bus, Netlist};
+use crate::sequential::{Clock, Register};
+
+// 積の下位 16 ビット。符号付きでも符号なしでも同じ値になる
+pub fn Mul16<W: Signal16>(a: W, b: W) -> W {
+    W::Bit::begin("Mul16", &[("a", &a.bits()), ("b", &b.bits())]);
+    let mut sum = And16(a, W::splat(b[15]));
+    let mut shifted = a;
+    for i in 1..16 {
+        shifted = ShiftLeft16(shifted);
+        sum = Add16(sum, And16(shifted, W::splat(b[15 - i])));
+    }
+    W::Bit::end(&[("out", &sum.bits())]);
+    sum
+}
+
+// 出力のポート名は入力と分ける (Verilog では同じ名前のポートを二つ持てない)
+pub fn MulStep<W: Signal16>(acc: W, a: W, b: W) -> [W; 3] {
+    W::Bit::begin(
+        "MulStep",
+        &[("acc", &acc.bits()), ("a", &a.bits()), ("b", &b.bits())],
+    );
+    let out = [
+        Mux16(acc, Add16(acc, a), b[15]),
+        ShiftLeft16(a),
+        ShiftRight16(b, W::Bit::LOW),
+    ];
+    W::Bit::end(&[
+        ("acc2", &out[0].bits()),
+        ("a2", &out[1].bits()),
+        ("b2", &out[2].bits()),
+    ]);
+    out
+}
+
+// r は余り、q は割られる数を上から送り出しつつ商を下から受け取る
+pub fn DivStep<W: Signal16>(r: W, q: W, d: W) -> [W; 2] {
+    W::Bit::begin(
+        "DivStep",
+        &[("r", &r.bits()), ("q", &q.bits()), ("d", &d.bits())],
+    );
+    let mut shifted = ShiftLeft16(r);
+    shifted[15] = q[0];
+    let not_d = Not16(d);
+    let mut trial = W::splat(W::Bit::LOW);
+    let mut carry = W::Bit::HIGH;
+    for i in (0..16).rev() {
+        let res = FullAdder(shifted[i], not_d[i], carry);
+        carry = res[0];
+        trial[i] = res[1];
+    }
+    let take = Or(r[0], carry);
+    let mut q2 = ShiftLeft16(q);
+    q2[15] = take;
+    let out = [Mux16(shifted, trial, take), q2];
+    W::Bit::end(&[("r2", &out[0].bits()), ("q2", &out[1].bits())]);
+    out
+}
+
+// 16 サイクルで終わる。サイクルの合間は clock を Tock にしておく
+pub struct Multiplier {
+    acc: Register,
+    a: Register,
+    b: Register,
+    clock: Clock,
+    cycles: usize,
+}
+
+impl Multiplier {
+    pub fn new(a: Word, b: Word) -> Self {
+        let mut m = Multiplier {
+            acc: Register::new(),
+            a: Register::new(),
+            b: Register::new(),
+            clock: Clock::new(),
+            cycles: 0,
+        };
+        m.acc.input(Word::new([O; 16]), I, &m.clock);
+        m.a.input(a, I, &m.clock);
+        m.b.input(b, I, &m.clock);
+        m.clock.next();
+        m
+    }
+
+    pub fn tick(&mut self) {
+        let [acc, a, b] = MulStep(
+            self.acc.output(&self.clock),
+            self.a.output(&self.clock),
+            self.b.output(&self.clock),
+        );
+        self.clock.next();
+        self.acc.input(acc, I, &self.clock);
+        self.a.input(a, I, &self.clock);
+        self.b.input(b, I, &self.clock);
+        self.clock.next();
+        self.cycles += 1;
+    }
+
+    pub fn run(&mut self) -> Word {
+        while self.cycles < 16 {
+            self.tick();
+        }
+        self.product()
+    }
+
+    pub fn product(&self) -> Word {
+        self.acc.output(&self.clock)
+    }
+
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+}
+
+// 符号なしの除算。0 で割ると商は 0xFFFF、余りは割られる数になる
+pub struct Divider {
+    r: Register,
+    q: Register,
+    d: Register,
+    clock: Clock,
+    cycles: usize,
+}
+
+impl Divider {
+    pub fn new(dividend: Word, divisor: Word) -> Self {
+        let mut div = Divider {
+            r: Register::new(),
+            q: Register::new(),
+            d: Register::new(),
+            clock: Clock::new(),
+            cycles: 0,
+        };
+        div.r.input(Word::new([O; 16]), I, &div.clock);
+        div.q.input(dividend, I, &div.clock);
+        div.d.input(divisor, I, &div.clock);
+        div.clock.next();
+        div
+    }
+
+    pub fn tick(&mut self) {
+        let [r, q] = DivStep(
+            self.r.output(&self.clock),
+            self.q.output(&self.clock),
+            self.d.output(&self.clock),
+        );
+        self.clock.next();
+        self.r.input(r, I, &self.clock);
+        self.q.input(q, I, &self.clock);
+        self.clock.next();
+        self.cycles += 1;
+    }
+
+    // (商, 余り)
+    pub fn run(&mut self) -> (Word, Word) {
+        while self.cycles < 16 {
+            self.tick();
+        }
+        (self.q.output(&self.clock), self.r.output(&self.clock))
+    }
+
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+}
+
+// 組み合わせ回路の Nand の数、状態を持つ DFF の数、結果までのサイクル数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cost {
+    pub nands: usize,
+    pub dffs: usize,
+    pub cycles: usize,
+}
+
+pub fn mul16_cost() -> Cost {
+    let mut n = Netlist::new();
+    let a = n.input16("a");
+    let b = n.input16("b");
+    n.capture(|| Mul16(a, b));
+    Cost {
+        nands: n.nand_count(),
+        dffs: 0,
+        cycles: 1,
+    }
+}
+
+pub fn multiplier_cost() -> Cost {
+    let mut n = Netlist::new();
+    let acc = n.input16("acc");
+    let a = n.input16("a");
+    let b = n.input16("b");
+    let [acc2, a2, b2] = n.capture(|| MulStep(acc, a, b));
+    load_logic(&mut n, &[(acc, acc2), (a, a2), (b, b2)]);
+    Cost {
+        nands: n.nand_count(),
+        dffs: 3 * 16,
+        cycles: 16,
+    }
+}
+
+pub fn divider_cost() -> Cost {
+    let mut n = Netlist::new();
+    let r = n.input16("r");
+    let q = n.input16("q");
+    let d = n.input16("d");
+    let [r2, q2] = n.capture(|| DivStep(r, q, d));
+    // d は最初に読み込んだまま保つが、Bit である以上 load の Mux は付いている
+    load_logic(&mut n, &[(r, r2), (q, q2), (d, d)]);
+    Cost {
+        nands: n.nand_count(),
+        dffs: 3 * 16,
+        cycles: 16,
+    }
+}
+
+// Register の各ビットは DFF の手前に、今の値と次の値を load で選ぶ Mux を持つ
+fn load_logic(n: &mut Netlist, registers: &[(Bus, Bus)]) {
+    let load = n.input("load");
+    n.capture(|| {
+        for (state, next) in registers.iter() {
+            Mux16(*state, *next, load);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::truth_table::to_bits;
+
+    const SAMPLES: [u16; 9] = [0, 1, 2, 3, 7, 255, 0x7fff, 0x8000, 0xffff];
+
+    fn pairs() -> Vec<(u16, u16)> {
+        let mut pairs = vec![];
+        for x in SAMPLES.iter().chain([12345u16, 40000].iter()) {
+            for y in SAMPLES.iter().chain([99u16, 300].iter()) {
+                pairs.push((*x, *y));
+            }
+        }
+        pairs
+    }
+
+    #[test]
+    fn for_mul16() {
+        for (x, y) in pairs() {
+            let expected = Word::from(x.wrapping_mul(y));
+            assert_eq!(Mul16(Word::from(x), Word::from(y)), expected);
+            let mut m = Multiplier::new(Word::from(x), Word::from(y));
+            assert_eq!(m.run(), expected, "{} * {}", x, y);
+            assert_eq!(m.cycles(), 16);
+            assert_eq!(
+                Word::from((x as i16).wrapping_mul(y as i16)),
+                expected,
+                "signed"
+            );
+        }
+    }
+
+    #[test]
+    fn for_divider() {
+        for (x, y) in pairs() {
+            let (q, r) = Divider::new(Word::from(x), Word::from(y)).run();
+            let expected = match (x.checked_div(y), x.checked_rem(y)) {
+                (Some(q), Some(r)) => (Word::from(q), Word::from(r)),
+                _ => (Word::from(0xffffu16), Word::from(x)),
+            };
+            assert_eq!((q, r), expected, "{} / {}", x, y);
+        }
+    }
+
+    #[test]
+    fn for_netlists() {
+        let mut n = Netlist::new();
+        let a = n.input16("a");
+        let b = n.input16("b");
+        let out = n.capture(|| Mul16(a, b));
+        n.output16("out", out);
+        let mut d = Netlist::new();
+        let r = d.input16("r");
+        let q = d.input16("q");
+        let dv = d.input16("d");
+        let [r2, q2] = d.capture(|| DivStep(r, q, dv));
+        d.output16("r", r2);
+        d.output16("q", q2);
+        let mut m = Netlist::new();
+        let acc = m.input16("acc");
+        let a = m.input16("a");
+        let b = m.input16("b");
+        let [acc2, a2, b2] = m.capture(|| MulStep(acc, a, b));
+        m.output16("acc", acc2);
+        m.output16("a", a2);
+        m.output16("b", b2);
+        for (x, y) in pairs() {
+            let input = [to_bits(x as usize, 16), to_bits(y as usize, 16)].concat();
+            assert_eq!(n.eval(&input), to_bits(x.wrapping_mul(y) as usize, 16));
+            let step = MulStep(Word::from(x ^ y), Word::from(x), Word::from(y));
+            let expected = [step[0].to_slice(), step[1].to_slice(), step[2].to_slice()].concat();
+            let acc = to_bits((x ^ y) as usize, 16);
+            assert_eq!(m.eval(&[acc, input.clone()].concat()), expected);
+            let step = DivStep(Word::from(y / 2), Word::from(x), Word::from(y));
+            let input = [to_bits((y / 2) as usize, 16), input].concat();
+            let expected = [step[0].to_slice(), step[1].to_slice()].concat();
+            assert_eq!(d.eval(&input), expected);
+        }
+    }
+
+    #[test]
+    fn for_cost() {
+        // And16 は 48、Add16 は 479、Mux16 は 160、FullAdder は 31 個の Nand
+        assert_eq!(
+            mul16_cost(),
+            Cost {
+                nands: 16 * 48 + 15 * 479,
+                dffs: 0,
+                cycles: 1
+            }
+        );
+        // レジスタ 3 本の load の Mux16 も数える
+        assert_eq!(multiplier_cost().nands, 479 + 160 + 3 + 3 * 160);
+        assert_eq!(divider_cost().nands, 16 + 16 * 31 + 3 + 160 + 3 * 160);
+        // 順序回路は 7 分の 1 ほどの Nand で済むかわりに 16 倍のサイクルがかかる
+        assert!(multiplier_cost().nands * 7 < mul16_cost().nands);
+        assert_eq!(multiplier_cost().cycles, 16 * mul16_cost().cycles);
+    }
+}
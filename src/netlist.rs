@@ -0,0 +1,1472 @@
+#![allow(dead_code, non_snake_case, clippy::too_many_arguments)]
+
+use crate::logic::bit::{self, I, O};
+use crate::logic::{self, Signal};
+use crate::{arithmetic, muldiv};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::convert::TryInto;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct Net(usize);
+
+impl Net {
+    pub fn id(&self) -> usize {
+        self.0
+    }
+}
+
+pub type Bus = [Net; 16];
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Driver {
+    Const(bit),
+    Input(usize),
+    Gate(usize),
+    Dff(usize),
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Gate {
+    pub a: Net,
+    pub b: Net,
+    pub out: Net,
+    pub owner: Option<usize>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Dff {
+    pub input: Net,
+    pub out: Net,
+    pub owner: Option<usize>,
+}
+
+// net を value に固定する故障
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Fault {
+    pub net: Net,
+    pub value: bit,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Port {
+    pub name: &'static str,
+    pub nets: Vec<Net>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Instance {
+    pub chip: String,
+    pub parent: Option<usize>,
+    pub depth: usize,
+    pub inputs: Vec<Port>,
+    pub outputs: Vec<Port>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Netlist {
+    drivers: Vec<Driver>,
+    gates: Vec<Gate>,
+    dffs: Vec<Dff>,
+    instances: Vec<Instance>,
+    inputs: Vec<(String, Vec<Net>)>,
+    outputs: Vec<(String, Vec<Net>)>,
+    stack: Vec<usize>,
+}
+
+impl Netlist {
+    pub fn new() -> Self {
+        Netlist {
+            drivers: vec![Driver::Const(O), Driver::Const(I)],
+            gates: vec![],
+            dffs: vec![],
+            instances: vec![],
+            inputs: vec![],
+            outputs: vec![],
+            stack: vec![],
+        }
+    }
+
+    pub fn constant(&self, value: bit) -> Net {
+        match value {
+            O => Net(0),
+            I => Net(1),
+        }
+    }
+
+    pub fn constant16(&self, value: [bit; 16]) -> Bus {
+        map16(|i| self.constant(value[i]))
+    }
+
+    pub fn input(&mut self, name: &str) -> Net {
+        self.input_bus(name, 1)[0]
+    }
+
+    pub fn input16(&mut self, name: &str) -> Bus {
+        to_bus(self.input_bus(name, 16))
+    }
+
+    pub fn input_bus(&mut self, name: &str, width: usize) -> Vec<Net> {
+        let port = self.inputs.len();
+        let nets = (0..width)
+            .map(|_| self.new_net(Driver::Input(port)))
+            .collect::<Vec<_>>();
+        self.inputs.push((name.to_string(), nets.clone()));
+        nets
+    }
+
+    pub fn output(&mut self, name: &str, net: Net) {
+        self.output_bus(name, &[net]);
+    }
+
+    pub fn output16(&mut self, name: &str, bus: Bus) {
+        self.output_bus(name, &bus);
+    }
+
+    pub fn output_bus(&mut self, name: &str, nets: &[Net]) {
+        self.outputs.push((name.to_string(), nets.to_vec()));
+    }
+
+    pub fn nand(&mut self, a: Net, b: Net) -> Net {
+        let out = self.new_net(Driver::Gate(self.gates.len()));
+        self.gates.push(Gate {
+            a,
+            b,
+            out,
+            owner: self.stack.last().copied(),
+        });
+        out
+    }
+
+    // 出力はクロックの立ち上がりで input の値になる。eval では常に O
+    pub fn dff(&mut self, input: Net) -> Net {
+        let out = self.new_net(Driver::Dff(self.dffs.len()));
+        self.dffs.push(Dff {
+            input,
+            out,
+            owner: self.stack.last().copied(),
+        });
+        out
+    }
+
+    // 帰還のある回路では DFF の出力を先に作り、入力はあとでつなぐ。持ち主のポートもつなぎ直す
+    pub fn connect(&mut self, out: Net, input: Net) {
+        let index = match self.driver(out) {
+            Driver::Dff(index) => index,
+            _ => panic!("`Netlist::connect` fail: {:?} is not a DFF output.", out),
+        };
+        let before = std::mem::replace(&mut self.dffs[index].input, input);
+        if let Some(owner) = self.dffs[index].owner {
+            for port in self.instances[owner].inputs.iter_mut() {
+                for net in port.nets.iter_mut().filter(|net| **net == before) {
+                    *net = input;
+                }
+            }
+        }
+    }
+
+    // チップの開始。以降に作られるゲートとチップはこのチップの子になる
+    pub fn begin(&mut self, chip: &str, inputs: &[(&'static str, &[Net])]) {
+        self.instances.push(Instance {
+            chip: chip.to_string(),
+            parent: self.stack.last().copied(),
+            depth: self.stack.len() + 1,
+            inputs: inputs
+                .iter()
+                .map(|(name, nets)| Port {
+                    name,
+                    nets: nets.to_vec(),
+                })
+                .collect(),
+            outputs: vec![],
+        });
+        self.stack.push(self.instances.len() - 1);
+    }
+
+    pub fn end(&mut self, outputs: &[(&'static str, &[Net])]) {
+        let index = match self.stack.pop() {
+            Some(index) => index,
+            None => panic!("`Netlist::end` fail: no chip has begun."),
+        };
+        self.instances[index].outputs = outputs
+            .iter()
+            .map(|(name, nets)| Port {
+                name,
+                nets: nets.to_vec(),
+            })
+            .collect();
+    }
+
+    pub fn net_count(&self) -> usize {
+        self.drivers.len()
+    }
+
+    pub fn nets(&self) -> impl Iterator<Item = Net> {
+        (0..self.drivers.len()).map(Net)
+    }
+
+    pub fn driver(&self, net: Net) -> Driver {
+        self.drivers[net.0]
+    }
+
+    pub fn gates(&self) -> &[Gate] {
+        &self.gates
+    }
+
+    pub fn dffs(&self) -> &[Dff] {
+        &self.dffs
+    }
+
+    pub fn instances(&self) -> &[Instance] {
+        &self.instances
+    }
+
+    pub fn inputs(&self) -> &[(String, Vec<Net>)] {
+        &self.inputs
+    }
+
+    pub fn outputs(&self) -> &[(String, Vec<Net>)] {
+        &self.outputs
+    }
+
+    pub fn nand_count(&self) -> usize {
+        self.gates.len()
+    }
+
+    pub fn count(&self, chip: &str) -> usize {
+        self.instances.iter().filter(|i| i.chip == chip).count()
+    }
+
+    // ゲートは作られた順に並んでいるので、前から一度評価するだけでよい
+    pub fn eval(&self, inputs: &[bit]) -> Vec<bit> {
+        self.eval_with(inputs, None)
+    }
+
+    pub fn eval_with_fault(&self, inputs: &[bit], fault: Fault) -> Vec<bit> {
+        self.eval_with(inputs, Some(fault))
+    }
+
+    fn eval_with(&self, inputs: &[bit], fault: Option<Fault>) -> Vec<bit> {
+        let width = self
+            .inputs
+            .iter()
+            .map(|(_, nets)| nets.len())
+            .sum::<usize>();
+        if inputs.len() != width {
+            panic!(
+                "`Netlist::eval` fail: need {} input bits, got {}.",
+                width,
+                inputs.len()
+            );
+        }
+        let mut values = vec![O; self.drivers.len()];
+        values[1] = I;
+        for (net, value) in self
+            .inputs
+            .iter()
+            .flat_map(|(_, nets)| nets.iter())
+            .zip(inputs.iter())
+        {
+            values[net.0] = *value;
+        }
+        if let Some(fault) = fault {
+            values[fault.net.0] = fault.value;
+        }
+        for gate in self.gates.iter() {
+            values[gate.out.0] = match fault {
+                Some(fault) if fault.net == gate.out => fault.value,
+                _ => crate::logic::Nand(values[gate.a.0], values[gate.b.0]),
+            };
+        }
+        self.outputs
+            .iter()
+            .flat_map(|(_, nets)| nets.iter())
+            .map(|net| values[net.0])
+            .collect()
+    }
+
+    pub fn to_dot(&self, options: &DotOptions) -> String {
+        Dot::new(self, options).render()
+    }
+
+    fn new_net(&mut self, driver: Driver) -> Net {
+        self.drivers.push(driver);
+        Net(self.drivers.len() - 1)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DotOptions {
+    max_depth: Option<usize>,
+    opaque: Vec<String>,
+}
+
+impl DotOptions {
+    pub fn new() -> Self {
+        DotOptions::default()
+    }
+
+    // depth 以下のチップだけを描き、depth のチップは箱として描く
+    pub fn depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    // 指定したチップは中身を展開しない
+    pub fn opaque(mut self, chip: &str) -> Self {
+        self.opaque.push(chip.to_string());
+        self
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+enum Node {
+    Const(bit),
+    Input(usize),
+    Output(usize),
+    Gate(usize),
+    Dff(usize),
+    Instance(usize),
+}
+
+struct Dot<'a> {
+    netlist: &'a Netlist,
+    // 折り畳まれた一番外側の祖先
+    folded: Vec<Option<usize>>,
+}
+
+impl<'a> Dot<'a> {
+    fn new(netlist: &'a Netlist, options: &DotOptions) -> Self {
+        let mut folded: Vec<Option<usize>> = vec![];
+        for (index, instance) in netlist.instances.iter().enumerate() {
+            let parent = instance.parent.and_then(|parent| folded[parent]);
+            let collapsed = options.max_depth.is_some_and(|d| instance.depth >= d)
+                || options.opaque.contains(&instance.chip);
+            folded.push(match parent {
+                Some(root) => Some(root),
+                None if collapsed => Some(index),
+                None => None,
+            });
+        }
+        Dot { netlist, folded }
+    }
+
+    fn render(&self) -> String {
+        let mut lines = vec![
+            "digraph netlist {".to_string(),
+            "  rankdir=LR;".to_string(),
+            "  node [fontname=\"Helvetica\"];".to_string(),
+        ];
+        let mut nodes = vec![];
+        let mut edges = vec![];
+        let mut seen = HashSet::new();
+        let mut add_edge = |src: Node, dst: Node, nodes: &mut Vec<Node>| {
+            if src != dst && seen.insert((src, dst)) {
+                for node in [src, dst].iter() {
+                    if !nodes.contains(node) {
+                        nodes.push(*node);
+                    }
+                }
+                edges.push((src, dst));
+            }
+        };
+        for (index, gate) in self.netlist.gates.iter().enumerate() {
+            let dst = self.gate_node(index, gate);
+            for net in [gate.a, gate.b].iter() {
+                add_edge(self.driver_node(*net), dst, &mut nodes);
+            }
+        }
+        for (index, dff) in self.netlist.dffs.iter().enumerate() {
+            let dst = self.dff_node(index, dff);
+            add_edge(self.driver_node(dff.input), dst, &mut nodes);
+        }
+        for (index, (_, nets)) in self.netlist.outputs.iter().enumerate() {
+            for net in nets.iter() {
+                add_edge(self.driver_node(*net), Node::Output(index), &mut nodes);
+            }
+        }
+
+        for node in nodes.iter() {
+            match node {
+                Node::Gate(index) => {
+                    if let Some(owner) = self.netlist.gates[*index].owner {
+                        if self.folded[owner].is_none() {
+                            continue;
+                        }
+                    }
+                }
+                Node::Dff(index) => {
+                    if let Some(owner) = self.netlist.dffs[*index].owner {
+                        if self.folded[owner].is_none() {
+                            continue;
+                        }
+                    }
+                }
+                Node::Instance(index) => {
+                    if let Some(parent) = self.netlist.instances[*index].parent {
+                        if self.folded[parent].is_none() {
+                            continue;
+                        }
+                    }
+                }
+                _ => (),
+            }
+            lines.push(format!("  {};", self.node_decl(*node)));
+        }
+        self.render_clusters(None, &nodes, 1, &mut lines);
+        for (src, dst) in edges.iter() {
+            lines.push(format!(
+                "  {} -> {};",
+                self.node_id(*src),
+                self.node_id(*dst)
+            ));
+        }
+        lines.push("}".to_string());
+        lines.join("\n") + "\n"
+    }
+
+    fn render_clusters(
+        &self,
+        parent: Option<usize>,
+        nodes: &[Node],
+        indent: usize,
+        lines: &mut Vec<String>,
+    ) {
+        for (index, instance) in self.netlist.instances.iter().enumerate() {
+            if instance.parent != parent || self.folded[index].is_some() {
+                continue;
+            }
+            let pad = "  ".repeat(indent);
+            lines.push(format!("{}subgraph cluster_{} {{", pad, index));
+            lines.push(format!("{}  label=\"{}\";", pad, instance.chip));
+            for node in nodes.iter() {
+                let owner = match node {
+                    Node::Gate(gate) => self.netlist.gates[*gate].owner,
+                    Node::Dff(dff) => self.netlist.dffs[*dff].owner,
+                    Node::Instance(child) => self.netlist.instances[*child].parent,
+                    _ => None,
+                };
+                if owner == Some(index) {
+                    lines.push(format!("{}  {};", pad, self.node_decl(*node)));
+                }
+            }
+            self.render_clusters(Some(index), nodes, indent + 1, lines);
+            lines.push(format!("{}}}", pad));
+        }
+    }
+
+    fn gate_node(&self, index: usize, gate: &Gate) -> Node {
+        match gate.owner.and_then(|owner| self.folded[owner]) {
+            Some(root) => Node::Instance(root),
+            None => Node::Gate(index),
+        }
+    }
+
+    fn dff_node(&self, index: usize, dff: &Dff) -> Node {
+        match dff.owner.and_then(|owner| self.folded[owner]) {
+            Some(root) => Node::Instance(root),
+            None => Node::Dff(index),
+        }
+    }
+
+    fn driver_node(&self, net: Net) -> Node {
+        match self.netlist.drivers[net.0] {
+            Driver::Const(value) => Node::Const(value),
+            Driver::Input(port) => Node::Input(port),
+            Driver::Gate(index) => self.gate_node(index, &self.netlist.gates[index]),
+            Driver::Dff(index) => self.dff_node(index, &self.netlist.dffs[index]),
+        }
+    }
+
+    fn node_id(&self, node: Node) -> String {
+        match node {
+            Node::Const(O) => "const0".to_string(),
+            Node::Const(I) => "const1".to_string(),
+            Node::Input(index) => format!("in{}", index),
+            Node::Output(index) => format!("out{}", index),
+            Node::Gate(index) => format!("g{}", index),
+            Node::Dff(index) => format!("d{}", index),
+            Node::Instance(index) => format!("i{}", index),
+        }
+    }
+
+    fn node_decl(&self, node: Node) -> String {
+        let port_label = |(name, nets): &(String, Vec<Net>)| {
+            if nets.len() == 1 {
+                name.clone()
+            } else {
+                format!("{}[{}]", name, nets.len())
+            }
+        };
+        let attrs = match node {
+            Node::Const(O) => "label=\"0\", shape=plaintext".to_string(),
+            Node::Const(I) => "label=\"1\", shape=plaintext".to_string(),
+            Node::Input(index) => format!(
+                "label=\"{}\", shape=circle",
+                port_label(&self.netlist.inputs[index])
+            ),
+            Node::Output(index) => format!(
+                "label=\"{}\", shape=doublecircle",
+                port_label(&self.netlist.outputs[index])
+            ),
+            Node::Gate(_) => "label=\"Nand\", shape=invhouse".to_string(),
+            Node::Dff(_) => "label=\"DFF\", shape=box3d".to_string(),
+            Node::Instance(index) => {
+                format!(
+                    "label=\"{}\", shape=box",
+                    self.netlist.instances[index].chip
+                )
+            }
+        };
+        format!("{} [{}]", self.node_id(node), attrs)
+    }
+}
+
+fn to_bus(nets: Vec<Net>) -> Bus {
+    match nets.try_into() {
+        Ok(bus) => bus,
+        Err(nets) => panic!("bus needs 16 nets, got {}.", nets.len()),
+    }
+}
+
+fn map16<F>(mut f: F) -> Bus
+where
+    F: FnMut(usize) -> Net,
+{
+    let mut bus = [Net(0); 16];
+    for (i, net) in bus.iter_mut().enumerate() {
+        *net = f(i);
+    }
+    bus
+}
+
+thread_local! {
+    // capture の間だけ、Net で動かしたチップの書き込み先になる
+    static CAPTURING: RefCell<Option<Netlist>> = const { RefCell::new(None) };
+}
+
+fn capturing<R, F: FnOnce(&mut Netlist) -> R>(f: F) -> R {
+    CAPTURING.with(|cell| match cell.borrow_mut().as_mut() {
+        Some(n) => f(n),
+        None => panic!("`Net` fail: chips on nets must run inside `Netlist::capture`."),
+    })
+}
+
+// 中断しても Netlist を元の場所へ戻す
+struct Restore<'a>(&'a mut Netlist);
+
+impl Drop for Restore<'_> {
+    fn drop(&mut self) {
+        if let Some(n) = CAPTURING.with(|cell| cell.borrow_mut().take()) {
+            *self.0 = n;
+        }
+    }
+}
+
+// Net で logic:: や arithmetic:: のチップを動かすと、Nand と部品の区切りが Netlist に記録される
+impl Signal for Net {
+    const LOW: Self = Net(0);
+    const HIGH: Self = Net(1);
+    fn nand(self, other: Self) -> Self {
+        capturing(|n| n.nand(self, other))
+    }
+    fn begin(chip: &str, inputs: &[(&'static str, &[Self])]) {
+        capturing(|n| n.begin(chip, inputs))
+    }
+    fn end(outputs: &[(&'static str, &[Self])]) {
+        capturing(|n| n.end(outputs))
+    }
+}
+
+impl Netlist {
+    // f の中で Net を渡したチップをこの Netlist に書き込む
+    pub fn capture<R, F: FnOnce() -> R>(&mut self, f: F) -> R {
+        CAPTURING.with(|cell| {
+            let mut cell = cell.borrow_mut();
+            if cell.is_some() {
+                panic!("`Netlist::capture` fail: already capturing.");
+            }
+            *cell = Some(std::mem::replace(self, Netlist::new()));
+        });
+        let _restore = Restore(self);
+        f()
+    }
+}
+
+// 状態を持つ部品は Nand だけでは書けないので、Net 用にここで作る。capture の中で呼ぶ
+pub fn DFF(inc: Net) -> Net {
+    Net::begin("DFF", &[("in", &[inc])]);
+    let out = capturing(|n| n.dff(inc));
+    Net::end(&[("out", &[out])]);
+    out
+}
+
+// sequential::Bit と同じく、今の値と in を load で選んで DFF に戻す
+pub fn Bit(inc: Net, load: Net) -> Net {
+    Net::begin("Bit", &[("in", &[inc]), ("load", &[load])]);
+    let out = DFF(Net::LOW);
+    let next = logic::Mux(out, inc, load);
+    capturing(|n| n.connect(out, next));
+    Net::end(&[("out", &[out])]);
+    out
+}
+
+pub fn Register(inc: Bus, load: Net) -> Bus {
+    Net::begin("Register", &[("in", &inc), ("load", &[load])]);
+    let out = map16(|i| Bit(inc[i], load));
+    Net::end(&[("out", &out)]);
+    out
+}
+
+// build と verilog::library が使うチップの一覧。
+// name が _ で終わるものは MuxNWay16_8 のように幅の付いた名前をまとめて受け持つ
+pub struct Chip {
+    pub name: &'static str,
+    // 入力ポートの幅。幅の決まらないチップでは library で使う幅
+    pub inputs: &'static [usize],
+    run: fn(&[Vec<Net>]),
+}
+
+impl Chip {
+    pub fn matches(&self, chip: &str) -> bool {
+        if self.name.ends_with('_') {
+            chip.starts_with(self.name)
+        } else {
+            chip == self.name
+        }
+    }
+}
+
+pub const CHIPS: [Chip; 37] = [
+    Chip {
+        name: "Not",
+        inputs: &[1],
+        run: |i| {
+            logic::Not(net_at(i, 0));
+        },
+    },
+    Chip {
+        name: "And",
+        inputs: &[1, 1],
+        run: |i| {
+            logic::And(net_at(i, 0), net_at(i, 1));
+        },
+    },
+    Chip {
+        name: "Or",
+        inputs: &[1, 1],
+        run: |i| {
+            logic::Or(net_at(i, 0), net_at(i, 1));
+        },
+    },
+    Chip {
+        name: "Xor",
+        inputs: &[1, 1],
+        run: |i| {
+            logic::Xor(net_at(i, 0), net_at(i, 1));
+        },
+    },
+    Chip {
+        name: "Mux",
+        inputs: &[1, 1, 1],
+        run: |i| {
+            logic::Mux(net_at(i, 0), net_at(i, 1), net_at(i, 2));
+        },
+    },
+    Chip {
+        name: "DMux",
+        inputs: &[1, 1],
+        run: |i| {
+            logic::DMux(net_at(i, 0), net_at(i, 1));
+        },
+    },
+    Chip {
+        name: "Not16",
+        inputs: &[16],
+        run: |i| {
+            logic::Not16(bus_at(i, 0));
+        },
+    },
+    Chip {
+        name: "And16",
+        inputs: &[16, 16],
+        run: |i| {
+            logic::And16(bus_at(i, 0), bus_at(i, 1));
+        },
+    },
+    Chip {
+        name: "Or16",
+        inputs: &[16, 16],
+        run: |i| {
+            logic::Or16(bus_at(i, 0), bus_at(i, 1));
+        },
+    },
+    Chip {
+        name: "Mux16",
+        inputs: &[16, 16, 1],
+        run: |i| {
+            logic::Mux16(bus_at(i, 0), bus_at(i, 1), net_at(i, 2));
+        },
+    },
+    Chip {
+        name: "Or8Way",
+        inputs: &[8],
+        run: |i| {
+            logic::Or8Way(to_array(&i[0]));
+        },
+    },
+    Chip {
+        name: "Mux4Way16",
+        inputs: &[16, 16, 16, 16, 2],
+        run: |i| {
+            logic::Mux4Way16(
+                bus_at(i, 0),
+                bus_at(i, 1),
+                bus_at(i, 2),
+                bus_at(i, 3),
+                to_array(&i[4]),
+            );
+        },
+    },
+    Chip {
+        name: "Mux8Way16",
+        inputs: &[16, 16, 16, 16, 16, 16, 16, 16, 3],
+        run: |i| {
+            logic::Mux8Way16(
+                bus_at(i, 0),
+                bus_at(i, 1),
+                bus_at(i, 2),
+                bus_at(i, 3),
+                bus_at(i, 4),
+                bus_at(i, 5),
+                bus_at(i, 6),
+                bus_at(i, 7),
+                to_array(&i[8]),
+            );
+        },
+    },
+    Chip {
+        name: "MuxNWay16_",
+        inputs: &[64, 2],
+        run: |i| {
+            logic::MuxNWay16(
+                &i[0]
+                    .chunks(16)
+                    .map(|c| to_bus(c.to_vec()))
+                    .collect::<Vec<_>>(),
+                &i[1],
+            );
+        },
+    },
+    Chip {
+        name: "DMux4Way",
+        inputs: &[1, 2],
+        run: |i| {
+            logic::DMux4Way(net_at(i, 0), to_array(&i[1]));
+        },
+    },
+    Chip {
+        name: "DMux8Way",
+        inputs: &[1, 3],
+        run: |i| {
+            logic::DMux8Way(net_at(i, 0), to_array(&i[1]));
+        },
+    },
+    Chip {
+        name: "DMuxNWay_",
+        inputs: &[1, 2],
+        run: |i| {
+            logic::DMuxNWay(net_at(i, 0), &i[1]);
+        },
+    },
+    Chip {
+        name: "HalfAdder",
+        inputs: &[1, 1],
+        run: |i| {
+            arithmetic::HalfAdder(net_at(i, 0), net_at(i, 1));
+        },
+    },
+    Chip {
+        name: "FullAdder",
+        inputs: &[1, 1, 1],
+        run: |i| {
+            arithmetic::FullAdder(net_at(i, 0), net_at(i, 1), net_at(i, 2));
+        },
+    },
+    Chip {
+        name: "Add16",
+        inputs: &[16, 16],
+        run: |i| {
+            arithmetic::Add16(bus_at(i, 0), bus_at(i, 1));
+        },
+    },
+    Chip {
+        name: "Add16WithFlags",
+        inputs: &[16, 16],
+        run: |i| {
+            arithmetic::Add16WithFlags(bus_at(i, 0), bus_at(i, 1));
+        },
+    },
+    Chip {
+        name: "Inc16",
+        inputs: &[16],
+        run: |i| {
+            arithmetic::Inc16(bus_at(i, 0));
+        },
+    },
+    Chip {
+        name: "ALU",
+        inputs: &[16, 16, 1, 1, 1, 1, 1, 1],
+        run: |i| {
+            arithmetic::ALU(
+                bus_at(i, 0),
+                bus_at(i, 1),
+                net_at(i, 2),
+                net_at(i, 3),
+                net_at(i, 4),
+                net_at(i, 5),
+                net_at(i, 6),
+                net_at(i, 7),
+            );
+        },
+    },
+    Chip {
+        name: "ALUWithFlags",
+        inputs: &[16, 16, 1, 1, 1, 1, 1, 1],
+        run: |i| {
+            arithmetic::ALUWithFlags(
+                bus_at(i, 0),
+                bus_at(i, 1),
+                net_at(i, 2),
+                net_at(i, 3),
+                net_at(i, 4),
+                net_at(i, 5),
+                net_at(i, 6),
+                net_at(i, 7),
+            );
+        },
+    },
+    Chip {
+        name: "IsZero16",
+        inputs: &[16],
+        run: |i| {
+            arithmetic::IsZero16(bus_at(i, 0));
+        },
+    },
+    Chip {
+        name: "ShiftLeft16",
+        inputs: &[16],
+        run: |i| {
+            arithmetic::ShiftLeft16(bus_at(i, 0));
+        },
+    },
+    Chip {
+        name: "ShiftRight16",
+        inputs: &[16, 1],
+        run: |i| {
+            arithmetic::ShiftRight16(bus_at(i, 0), net_at(i, 1));
+        },
+    },
+    Chip {
+        name: "RotateLeft16",
+        inputs: &[16],
+        run: |i| {
+            arithmetic::RotateLeft16(bus_at(i, 0));
+        },
+    },
+    Chip {
+        name: "RotateRight16",
+        inputs: &[16],
+        run: |i| {
+            arithmetic::RotateRight16(bus_at(i, 0));
+        },
+    },
+    Chip {
+        name: "BarrelShifter16",
+        inputs: &[16, 4, 1, 1],
+        run: |i| {
+            arithmetic::BarrelShifter16(bus_at(i, 0), to_array(&i[1]), net_at(i, 2), net_at(i, 3));
+        },
+    },
+    Chip {
+        name: "Compute",
+        inputs: &[16, 16, 16, 16, 1],
+        run: |i| {
+            arithmetic::Compute(
+                bus_at(i, 0),
+                bus_at(i, 1),
+                bus_at(i, 2),
+                bus_at(i, 3),
+                net_at(i, 4),
+            );
+        },
+    },
+    Chip {
+        name: "Mul16",
+        inputs: &[16, 16],
+        run: |i| {
+            muldiv::Mul16(bus_at(i, 0), bus_at(i, 1));
+        },
+    },
+    Chip {
+        name: "MulStep",
+        inputs: &[16, 16, 16],
+        run: |i| {
+            muldiv::MulStep(bus_at(i, 0), bus_at(i, 1), bus_at(i, 2));
+        },
+    },
+    Chip {
+        name: "DivStep",
+        inputs: &[16, 16, 16],
+        run: |i| {
+            muldiv::DivStep(bus_at(i, 0), bus_at(i, 1), bus_at(i, 2));
+        },
+    },
+    Chip {
+        name: "DFF",
+        inputs: &[1],
+        run: |i| {
+            DFF(net_at(i, 0));
+        },
+    },
+    Chip {
+        name: "Bit",
+        inputs: &[1, 1],
+        run: |i| {
+            Bit(net_at(i, 0), net_at(i, 1));
+        },
+    },
+    Chip {
+        name: "Register",
+        inputs: &[16, 1],
+        run: |i| {
+            Register(bus_at(i, 0), net_at(i, 1));
+        },
+    },
+];
+
+fn net_at(inputs: &[Vec<Net>], k: usize) -> Net {
+    inputs[k][0]
+}
+
+fn bus_at(inputs: &[Vec<Net>], k: usize) -> Bus {
+    to_bus(inputs[k].clone())
+}
+
+fn to_array<const N: usize>(nets: &[Net]) -> [Net; N] {
+    match nets.try_into() {
+        Ok(array) => array,
+        Err(_) => panic!("port needs {} nets, got {}.", N, nets.len()),
+    }
+}
+
+// チップ名から本物のチップを Net で呼ぶ。inputs は begin に渡すポートの順。知らないチップなら false
+pub fn build(n: &mut Netlist, chip: &str, inputs: &[Vec<Net>]) -> bool {
+    match CHIPS.iter().find(|def| def.matches(chip)) {
+        Some(def) => {
+            n.capture(|| (def.run)(inputs));
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build, DotOptions, Netlist};
+    use crate::arithmetic::{Add16, BarrelShifter16, FullAdder, ShiftLeft16, ShiftRight16, ALU};
+    use crate::logic::bit::{self, I, O};
+    use crate::logic::{DMux8Way, DMuxNWay, Mux8Way16, MuxNWay16, Word, Xor};
+    use crate::truth_table::{to_bits, TruthTable};
+    use crate::{arithmetic, logic, muldiv};
+
+    #[test]
+    fn for_netlist_xor() {
+        let mut n = Netlist::new();
+        let a = n.input("a");
+        let b = n.input("b");
+        let out = n.capture(|| Xor(a, b));
+        n.output("out", out);
+        assert_eq!(n.nand_count(), 11);
+        assert_eq!(n.count("Xor"), 1);
+        assert_eq!(n.count("And"), 2);
+        let table = TruthTable::new(&["a", "b"], &["out"], |i| n.eval(i));
+        assert_eq!(table.check(|i| vec![logic::Xor(i[0], i[1])]), Ok(()));
+    }
+
+    #[test]
+    fn for_netlist_fulladder() {
+        let mut n = Netlist::new();
+        let a = n.input("a");
+        let b = n.input("b");
+        let c = n.input("c");
+        let out = n.capture(|| FullAdder(a, b, c));
+        n.output("carry", out[0]);
+        n.output("sum", out[1]);
+        let table = TruthTable::new(&["a", "b", "c"], &["carry", "sum"], |i| n.eval(i));
+        assert_eq!(
+            table.check(|i| arithmetic::FullAdder(i[0], i[1], i[2]).to_vec()),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn for_netlist_add16() {
+        let mut n = Netlist::new();
+        let a = n.input16("a");
+        let b = n.input16("b");
+        let out = n.capture(|| Add16(a, b));
+        n.output16("out", out);
+        assert_eq!(n.count("FullAdder"), 15);
+        assert_eq!(n.count("HalfAdder"), 31);
+        for (x, y) in [(0, 0), (1, 65535), (12345, 54321), (32767, 1)].iter() {
+            let x = to_bits(*x, 16);
+            let y = to_bits(*y, 16);
+            let expected = arithmetic::Add16(to_word(&x), to_word(&y));
+            assert_eq!(n.eval(&[x, y].concat()), expected.to_slice().to_vec());
+        }
+    }
+
+    #[test]
+    fn for_netlist_mux8way16() {
+        let mut n = Netlist::new();
+        let ins = (0..8)
+            .map(|i| n.input16(&format!("in{}", i)))
+            .collect::<Vec<_>>();
+        let sel = n.input_bus("sel", 3);
+        let out = n.capture(|| {
+            Mux8Way16(
+                ins[0],
+                ins[1],
+                ins[2],
+                ins[3],
+                ins[4],
+                ins[5],
+                ins[6],
+                ins[7],
+                [sel[0], sel[1], sel[2]],
+            )
+        });
+        n.output16("out", out);
+        let words = (0..8)
+            .map(|i| to_bits(i * 4111 + 7, 16))
+            .collect::<Vec<_>>();
+        for s in 0..8 {
+            let input = [words.concat(), to_bits(s, 3)].concat();
+            assert_eq!(n.eval(&input), words[s]);
+        }
+    }
+
+    #[test]
+    fn for_netlist_nway() {
+        let mut n = Netlist::new();
+        let ins = (0..8)
+            .map(|i| n.input16(&format!("in{}", i)))
+            .collect::<Vec<_>>();
+        let sel = n.input_bus("sel", 3);
+        let out = n.capture(|| MuxNWay16(&ins, &sel));
+        n.output16("out", out);
+        // Mux16 を 7 個。固定幅の Mux8Way16 と同じ数の Nand になる
+        let mut fixed = Netlist::new();
+        let ins = (0..8)
+            .map(|i| fixed.input16(&format!("in{}", i)))
+            .collect::<Vec<_>>();
+        let s = fixed.input_bus("sel", 3);
+        let out = fixed.capture(|| {
+            Mux8Way16(
+                ins[0],
+                ins[1],
+                ins[2],
+                ins[3],
+                ins[4],
+                ins[5],
+                ins[6],
+                ins[7],
+                [s[0], s[1], s[2]],
+            )
+        });
+        fixed.output16("out", out);
+        assert_eq!(n.nand_count(), 7 * 160);
+        assert_eq!(n.nand_count(), fixed.nand_count());
+        let words = (0..8)
+            .map(|i| to_bits(i * 4111 + 7, 16))
+            .collect::<Vec<_>>();
+        for s in 0..8 {
+            let input = [words.concat(), to_bits(s, 3)].concat();
+            assert_eq!(n.eval(&input), words[s]);
+        }
+
+        let mut d = Netlist::new();
+        let inc = d.input("in");
+        let sel = d.input_bus("sel", 3);
+        let out = d.capture(|| DMuxNWay(inc, &sel));
+        d.output_bus("out", &out);
+        let mut fixed = Netlist::new();
+        let inc = fixed.input("in");
+        let s = fixed.input_bus("sel", 3);
+        let out = fixed.capture(|| DMux8Way(inc, [s[0], s[1], s[2]]));
+        fixed.output_bus("out", &out);
+        assert_eq!(d.nand_count(), 7 * 7);
+        for i in 0..16 {
+            let input = to_bits(i, 4);
+            assert_eq!(d.eval(&input), fixed.eval(&input));
+        }
+
+        // RAM512 の 9 本の sel。幅ごとのチップ名に上限はない
+        let mut d = Netlist::new();
+        let inc = d.input("in");
+        let sel = d.input_bus("sel", 9);
+        let out = d.capture(|| DMuxNWay(inc, &sel));
+        d.output_bus("out", &out);
+        assert_eq!(d.count("DMuxNWay_512"), 1);
+        assert_eq!(d.count("DMuxNWay_2"), 256);
+        for s in [0, 1, 255, 256, 300, 511].iter() {
+            let input = [vec![I], to_bits(*s, 9)].concat();
+            assert_eq!(d.eval(&input), logic::DMuxNWay(I, &input[1..]));
+        }
+    }
+
+    #[test]
+    fn for_netlist_alu() {
+        let mut n = Netlist::new();
+        let x = n.input16("x");
+        let y = n.input16("y");
+        let c = n.input_bus("c", 6);
+        let (out, zr, ng) = n.capture(|| ALU(x, y, c[0], c[1], c[2], c[3], c[4], c[5]));
+        n.output16("out", out);
+        n.output("zr", zr);
+        n.output("ng", ng);
+        let controls = [
+            [I, O, I, O, I, O],
+            [I, I, I, I, I, I],
+            [I, I, I, O, I, O],
+            [O, O, I, I, O, O],
+            [I, I, O, O, O, O],
+            [O, O, I, I, O, I],
+            [O, I, O, O, I, I],
+            [O, O, O, I, I, I],
+            [O, O, O, O, I, O],
+            [O, I, O, I, O, I],
+        ];
+        for (x, y) in [(17, 3), (0, 65535), (40000, 123)].iter() {
+            let x = to_bits(*x, 16);
+            let y = to_bits(*y, 16);
+            for c in controls.iter() {
+                let (out, zr, ng) =
+                    arithmetic::ALU(to_word(&x), to_word(&y), c[0], c[1], c[2], c[3], c[4], c[5]);
+                let mut expected = out.to_slice().to_vec();
+                expected.push(zr);
+                expected.push(ng);
+                assert_eq!(
+                    n.eval(&[x.clone(), y.clone(), c.to_vec()].concat()),
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn for_netlist_shifters() {
+        let mut n = Netlist::new();
+        let a = n.input16("in");
+        let amount = n.input_bus("amount", 4);
+        let right = n.input("right");
+        let arithmetic = n.input("arithmetic");
+        let (left, one, out) = n.capture(|| {
+            let k = [amount[0], amount[1], amount[2], amount[3]];
+            (
+                ShiftLeft16(a),
+                ShiftRight16(a, arithmetic),
+                BarrelShifter16(a, k, right, arithmetic),
+            )
+        });
+        n.output16("left", left);
+        n.output16("one", one);
+        n.output16("out", out);
+        // ShiftRight16 は And 一つ、BarrelShifter16 は Mux16 六つと And 二つ
+        assert_eq!(n.count("Mux16"), 6);
+        assert_eq!(n.nand_count(), 3 + 6 * 16 * 10 + 2 * 3);
+        for x in [0u16, 1, 0x8001, 0xffff, 0x1234, 0xbeef].iter() {
+            for k in 0..16 {
+                for (r, s) in [(O, O), (I, O), (I, I)].iter() {
+                    let input = [to_bits(*x as usize, 16), to_bits(k, 4), vec![*r, *s]].concat();
+                    let expected = match (r, s) {
+                        (O, _) => x << k,
+                        (_, O) => x >> k,
+                        _ => ((*x as i16) >> k) as u16,
+                    };
+                    let arithmetic = *s == I;
+                    let single = if arithmetic {
+                        ((*x as i16) >> 1) as u16
+                    } else {
+                        x >> 1
+                    };
+                    let outputs = n.eval(&input);
+                    assert_eq!(outputs[..16].to_vec(), to_bits((x << 1) as usize, 16));
+                    assert_eq!(outputs[16..32].to_vec(), to_bits(single as usize, 16));
+                    assert_eq!(outputs[32..].to_vec(), to_bits(expected as usize, 16));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn for_dot() {
+        let mut n = Netlist::new();
+        let a = n.input("a");
+        let b = n.input("b");
+        let c = n.input("c");
+        let out = n.capture(|| FullAdder(a, b, c));
+        n.output("carry", out[0]);
+        n.output("sum", out[1]);
+
+        assert_eq!(
+            n.to_dot(&DotOptions::new().depth(1)),
+            "digraph netlist {\n  \
+               rankdir=LR;\n  \
+               node [fontname=\"Helvetica\"];\n  \
+               in0 [label=\"a\", shape=circle];\n  \
+               i0 [label=\"FullAdder\", shape=box];\n  \
+               in1 [label=\"b\", shape=circle];\n  \
+               in2 [label=\"c\", shape=circle];\n  \
+               out0 [label=\"carry\", shape=doublecircle];\n  \
+               out1 [label=\"sum\", shape=doublecircle];\n  \
+               in0 -> i0;\n  \
+               in1 -> i0;\n  \
+               in2 -> i0;\n  \
+               i0 -> out0;\n  \
+               i0 -> out1;\n\
+             }\n"
+        );
+
+        let dot = n.to_dot(&DotOptions::new().depth(2));
+        assert!(dot.contains("  subgraph cluster_0 {\n    label=\"FullAdder\";\n"));
+        assert!(dot.contains("    i1 [label=\"HalfAdder\", shape=box];\n"));
+        assert!(dot.contains("    i17 [label=\"Or\", shape=box];\n"));
+        assert!(dot.contains("  i1 -> i9;\n"));
+        assert!(!dot.contains("Nand"));
+
+        let dot = n.to_dot(&DotOptions::new().opaque("HalfAdder"));
+        assert!(dot.contains("    subgraph cluster_17 {\n      label=\"Or\";\n"));
+        assert!(dot.contains("      g28 [label=\"Nand\", shape=invhouse];\n"));
+        assert!(dot.contains("  i9 -> g29;\n"));
+        assert!(!dot.contains("label=\"And\""));
+    }
+
+    type Chip = fn(&[Vec<bit>]) -> Vec<bit>;
+
+    // 入力が 12 ビット以下なら全部、それより多ければ擬似乱数の 64 組と全 O、全 I で比べる
+    fn same(name: &str, widths: &[usize], chip: Chip) {
+        let mut n = Netlist::new();
+        let inputs = widths
+            .iter()
+            .enumerate()
+            .map(|(i, w)| n.input_bus(&format!("in{}", i), *w))
+            .collect::<Vec<_>>();
+        assert!(build(&mut n, name, &inputs), "{} is not built", name);
+        let out = n.instances()[0]
+            .outputs
+            .iter()
+            .flat_map(|port| port.nets.clone())
+            .collect::<Vec<_>>();
+        n.output_bus("out", &out);
+        let total = widths.iter().sum::<usize>();
+        let vectors = if total <= 12 {
+            (0..1 << total)
+                .map(|v| to_bits(v, total))
+                .collect::<Vec<_>>()
+        } else {
+            let mut state = 0x2545_f491_4f6c_dd1du64;
+            let mut vectors = vec![vec![O; total], vec![I; total]];
+            for _ in 0..64 {
+                vectors.push(
+                    (0..total)
+                        .map(|_| {
+                            state ^= state << 13;
+                            state ^= state >> 7;
+                            state ^= state << 17;
+                            if state & 1 == 1 {
+                                I
+                            } else {
+                                O
+                            }
+                        })
+                        .collect(),
+                );
+            }
+            vectors
+        };
+        for v in vectors.iter() {
+            let mut split = vec![];
+            let mut rest = &v[..];
+            for w in widths.iter() {
+                split.push(rest[..*w].to_vec());
+                rest = &rest[*w..];
+            }
+            assert_eq!(n.eval(v), chip(&split), "{} {:?}", name, v);
+        }
+    }
+
+    fn word(w: Word) -> Vec<bit> {
+        w.to_slice().to_vec()
+    }
+
+    // build で Net から組んだ各チップが、同じチップを bit で動かしたときと同じ値を返すか。
+    // DFF は Nand で組む部品ではないので simulator の for_tick で確かめる
+    #[test]
+    fn for_build() {
+        same("Not", &[1], |i| vec![logic::Not(i[0][0])]);
+        same("And", &[1, 1], |i| vec![logic::And(i[0][0], i[1][0])]);
+        same("Or", &[1, 1], |i| vec![logic::Or(i[0][0], i[1][0])]);
+        same("Xor", &[1, 1], |i| vec![logic::Xor(i[0][0], i[1][0])]);
+        same("Mux", &[1, 1, 1], |i| {
+            vec![logic::Mux(i[0][0], i[1][0], i[2][0])]
+        });
+        same("DMux", &[1, 1], |i| logic::DMux(i[0][0], i[1][0]).to_vec());
+        same("Not16", &[16], |i| word(logic::Not16(to_word(&i[0]))));
+        same("And16", &[16, 16], |i| {
+            word(logic::And16(to_word(&i[0]), to_word(&i[1])))
+        });
+        same("Or16", &[16, 16], |i| {
+            word(logic::Or16(to_word(&i[0]), to_word(&i[1])))
+        });
+        same("Mux16", &[16, 16, 1], |i| {
+            word(logic::Mux16(to_word(&i[0]), to_word(&i[1]), i[2][0]))
+        });
+        same("Or8Way", &[8], |i| {
+            let a = &i[0];
+            vec![logic::Or8Way([
+                a[0], a[1], a[2], a[3], a[4], a[5], a[6], a[7],
+            ])]
+        });
+        same("Mux4Way16", &[16, 16, 16, 16, 2], |i| {
+            let w = |k: usize| to_word(&i[k]);
+            word(logic::Mux4Way16(w(0), w(1), w(2), w(3), [i[4][0], i[4][1]]))
+        });
+        same("Mux8Way16", &[16, 16, 16, 16, 16, 16, 16, 16, 3], |i| {
+            let w = |k: usize| to_word(&i[k]);
+            let s = [i[8][0], i[8][1], i[8][2]];
+            word(logic::Mux8Way16(
+                w(0),
+                w(1),
+                w(2),
+                w(3),
+                w(4),
+                w(5),
+                w(6),
+                w(7),
+                s,
+            ))
+        });
+        same("DMux4Way", &[1, 2], |i| {
+            logic::DMux4Way(i[0][0], [i[1][0], i[1][1]]).to_vec()
+        });
+        same("DMux8Way", &[1, 3], |i| {
+            logic::DMux8Way(i[0][0], [i[1][0], i[1][1], i[1][2]]).to_vec()
+        });
+        same("MuxNWay16_4", &[64, 2], |i| {
+            let ins = i[0].chunks(16).map(to_word).collect::<Vec<_>>();
+            word(logic::MuxNWay16(&ins, &i[1]))
+        });
+        same("DMuxNWay_16", &[1, 4], |i| logic::DMuxNWay(i[0][0], &i[1]));
+        same("HalfAdder", &[1, 1], |i| {
+            arithmetic::HalfAdder(i[0][0], i[1][0]).to_vec()
+        });
+        same("FullAdder", &[1, 1, 1], |i| {
+            arithmetic::FullAdder(i[0][0], i[1][0], i[2][0]).to_vec()
+        });
+        same("Add16", &[16, 16], |i| {
+            word(arithmetic::Add16(to_word(&i[0]), to_word(&i[1])))
+        });
+        same("Inc16", &[16], |i| word(arithmetic::Inc16(to_word(&i[0]))));
+        same("ALU", &[16, 16, 1, 1, 1, 1, 1, 1], |i| {
+            let c = |k: usize| i[k][0];
+            let (out, zr, ng) = arithmetic::ALU(
+                to_word(&i[0]),
+                to_word(&i[1]),
+                c(2),
+                c(3),
+                c(4),
+                c(5),
+                c(6),
+                c(7),
+            );
+            [word(out), vec![zr, ng]].concat()
+        });
+        same("ShiftLeft16", &[16], |i| {
+            word(arithmetic::ShiftLeft16(to_word(&i[0])))
+        });
+        same("ShiftRight16", &[16, 1], |i| {
+            word(arithmetic::ShiftRight16(to_word(&i[0]), i[1][0]))
+        });
+        same("BarrelShifter16", &[16, 4, 1, 1], |i| {
+            let k = [i[1][0], i[1][1], i[1][2], i[1][3]];
+            word(arithmetic::BarrelShifter16(
+                to_word(&i[0]),
+                k,
+                i[2][0],
+                i[3][0],
+            ))
+        });
+        same("Mul16", &[16, 16], |i| {
+            word(muldiv::Mul16(to_word(&i[0]), to_word(&i[1])))
+        });
+        same("MulStep", &[16, 16, 16], |i| {
+            let out = muldiv::MulStep(to_word(&i[0]), to_word(&i[1]), to_word(&i[2]));
+            out.iter().flat_map(|w| word(*w)).collect()
+        });
+        same("DivStep", &[16, 16, 16], |i| {
+            let out = muldiv::DivStep(to_word(&i[0]), to_word(&i[1]), to_word(&i[2]));
+            out.iter().flat_map(|w| word(*w)).collect()
+        });
+        same("Add16WithFlags", &[16, 16], |i| {
+            let (out, carry, overflow) = arithmetic::Add16WithFlags(to_word(&i[0]), to_word(&i[1]));
+            [word(out), vec![carry, overflow]].concat()
+        });
+        same("ALUWithFlags", &[16, 16, 1, 1, 1, 1, 1, 1], |i| {
+            let c = |k: usize| i[k][0];
+            let (out, zr, ng, carry, overflow) = arithmetic::ALUWithFlags(
+                to_word(&i[0]),
+                to_word(&i[1]),
+                c(2),
+                c(3),
+                c(4),
+                c(5),
+                c(6),
+                c(7),
+            );
+            [word(out), vec![zr, ng, carry, overflow]].concat()
+        });
+        same("IsZero16", &[16], |i| {
+            vec![arithmetic::IsZero16(to_word(&i[0]))]
+        });
+        same("RotateLeft16", &[16], |i| {
+            word(arithmetic::RotateLeft16(to_word(&i[0])))
+        });
+        same("RotateRight16", &[16], |i| {
+            word(arithmetic::RotateRight16(to_word(&i[0])))
+        });
+        same("Compute", &[16, 16, 16, 16, 1], |i| {
+            let w = |k: usize| to_word(&i[k]);
+            let (out, zr, ng) = arithmetic::Compute(w(0), w(1), w(2), w(3), i[4][0]);
+            [word(out), vec![zr, ng]].concat()
+        });
+    }
+
+    // capture の外では Net のチップは書き込み先がない
+    #[test]
+    #[should_panic]
+    fn for_capture_outside() {
+        let mut n = Netlist::new();
+        let a = n.input("a");
+        Xor(a, a);
+    }
+
+    fn to_word(bits: &[bit]) -> Word {
+        let mut word = Word::new([O; 16]);
+        for (i, b) in bits.iter().enumerate() {
+            word[i] = *b;
+        }
+        word
+    }
+}
@@ -0,0 +1,189 @@
+#![allow(dead_code)]
+
+use crate::logic::Word;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const ROM_SIZE: usize = 32768;
+
+#[derive(Debug)]
+pub enum RomError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+    TooLarge(usize),
+    OddLength(usize),
+}
+
+impl Display for RomError {
+    fn fmt(&self, dest: &mut Formatter) -> fmt::Result {
+        match self {
+            RomError::Io(e) => write!(dest, "{}", e),
+            RomError::Parse { line, message } => write!(dest, "line {}: {}", line, message),
+            RomError::TooLarge(len) => write!(
+                dest,
+                "program has {} instructions, ROM32K holds {}.",
+                len, ROM_SIZE
+            ),
+            RomError::OddLength(len) => {
+                write!(dest, "binary ROM has {} bytes, need an even number.", len)
+            }
+        }
+    }
+}
+
+impl From<io::Error> for RomError {
+    fn from(e: io::Error) -> Self {
+        RomError::Io(e)
+    }
+}
+
+// 一行に一命令。空行は読み飛ばす
+pub fn parse_hack(src: &str) -> Result<Vec<Word>, RomError> {
+    let mut program = vec![];
+    for (index, line) in src.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        match line.parse::<Word>() {
+            Ok(word) => program.push(word),
+            Err(message) => {
+                return Err(RomError::Parse {
+                    line: index + 1,
+                    message,
+                })
+            }
+        }
+    }
+    check_size(program)
+}
+
+pub fn to_hack(program: &[Word]) -> String {
+    program
+        .iter()
+        .map(|word| format!("{:016b}\n", u16::from(*word)))
+        .collect()
+}
+
+// 一命令 2 バイトのビッグエンディアン
+pub fn parse_binary(bytes: &[u8]) -> Result<Vec<Word>, RomError> {
+    if !bytes.len().is_multiple_of(2) {
+        return Err(RomError::OddLength(bytes.len()));
+    }
+    check_size(
+        bytes
+            .chunks(2)
+            .map(|pair| Word::from(u16::from_be_bytes([pair[0], pair[1]])))
+            .collect(),
+    )
+}
+
+pub fn to_binary(program: &[Word]) -> Vec<u8> {
+    program
+        .iter()
+        .flat_map(|word| u16::from(*word).to_be_bytes().to_vec())
+        .collect()
+}
+
+pub fn read_hack<P: AsRef<Path>>(path: P) -> Result<Vec<Word>, RomError> {
+    parse_hack(&fs::read_to_string(path)?)
+}
+
+pub fn write_hack<P: AsRef<Path>>(path: P, program: &[Word]) -> Result<(), RomError> {
+    Ok(fs::write(path, to_hack(program))?)
+}
+
+pub fn read_binary<P: AsRef<Path>>(path: P) -> Result<Vec<Word>, RomError> {
+    parse_binary(&fs::read(path)?)
+}
+
+pub fn write_binary<P: AsRef<Path>>(path: P, program: &[Word]) -> Result<(), RomError> {
+    Ok(fs::write(path, to_binary(program))?)
+}
+
+fn check_size(program: Vec<Word>) -> Result<Vec<Word>, RomError> {
+    if program.len() > ROM_SIZE {
+        Err(RomError::TooLarge(program.len()))
+    } else {
+        Ok(program)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        parse_binary, parse_hack, read_binary, read_hack, to_binary, to_hack, write_binary,
+        write_hack, RomError,
+    };
+    use crate::logic::Word;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn for_parse_hack() {
+        let program =
+            parse_hack("0000000000000010\r\n1110110000010000\n\n0000000000000011\n").unwrap();
+        assert_eq!(
+            program,
+            vec![Word::from(2u16), Word::from(0xec10u16), Word::from(3u16)]
+        );
+        assert_eq!(
+            to_hack(&program),
+            "0000000000000010\n1110110000010000\n0000000000000011\n"
+        );
+    }
+
+    #[test]
+    fn for_parse_hack_error() {
+        match parse_hack("0000000000000010\n\n111011000001000\n") {
+            Err(RomError::Parse { line, .. }) => assert_eq!(line, 3),
+            _ => panic!("expected a parse error"),
+        }
+        let e = parse_hack("0000000000000010\n11101100000100002\n").unwrap_err();
+        assert_eq!(
+            format!("{}", e),
+            "line 2: `Word::from_string` fail: need less than 16."
+        );
+        let e = parse_hack("000000000000001O\n").unwrap_err();
+        assert_eq!(
+            format!("{}", e),
+            "line 1: `Word::from_string` fail: cannot find 0 or 1."
+        );
+        let big = "0000000000000000\n".repeat(32769);
+        match parse_hack(&big) {
+            Err(RomError::TooLarge(len)) => assert_eq!(len, 32769),
+            _ => panic!("expected a size error"),
+        }
+    }
+
+    #[test]
+    fn for_binary() {
+        let program = vec![Word::from(0x1234u16), Word::from(0xfc10u16)];
+        assert_eq!(to_binary(&program), vec![0x12, 0x34, 0xfc, 0x10]);
+        assert_eq!(parse_binary(&to_binary(&program)).unwrap(), program);
+        match parse_binary(&[0x12, 0x34, 0x56]) {
+            Err(RomError::OddLength(len)) => assert_eq!(len, 3),
+            _ => panic!("expected a length error"),
+        }
+    }
+
+    #[test]
+    fn for_files() {
+        let dir = env::temp_dir().join(format!("nand2tetris-rs-rom-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let program = (0..100u16).map(|i| Word::from(i * 655)).collect::<Vec<_>>();
+
+        write_hack(dir.join("Prog.hack"), &program).unwrap();
+        assert_eq!(read_hack(dir.join("Prog.hack")).unwrap(), program);
+        write_binary(dir.join("Prog.bin"), &program).unwrap();
+        assert_eq!(read_binary(dir.join("Prog.bin")).unwrap(), program);
+        match read_hack(dir.join("Missing.hack")) {
+            Err(RomError::Io(_)) => (),
+            _ => panic!("expected an io error"),
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
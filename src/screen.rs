@@ -0,0 +1,240 @@
+#![allow(dead_code)]
+
+use crate::logic::bit::I;
+use crate::logic::Word;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const SCREEN: usize = 16384;
+pub const SCREEN_SIZE: usize = 8192;
+pub const WIDTH: usize = 512;
+pub const HEIGHT: usize = 256;
+
+pub fn screen_region(ram: &[Word]) -> &[Word] {
+    &ram[SCREEN..SCREEN + SCREEN_SIZE]
+}
+
+// 各 Word の最下位ビット (15 番目) が一番左の画素。I が黒
+pub fn pixel(screen: &[Word], row: usize, col: usize) -> bool {
+    screen[row * WIDTH / 16 + col / 16][15 - col % 16] == I
+}
+
+pub fn to_pbm(screen: &[Word]) -> Vec<u8> {
+    check_size(screen);
+    let mut image = format!("P4\n{} {}\n", WIDTH, HEIGHT).into_bytes();
+    image.append(&mut packed_rows(screen, true));
+    image
+}
+
+pub fn to_ppm(screen: &[Word]) -> Vec<u8> {
+    check_size(screen);
+    let mut image = format!("P6\n{} {}\n255\n", WIDTH, HEIGHT).into_bytes();
+    for row in 0..HEIGHT {
+        for col in 0..WIDTH {
+            let value = if pixel(screen, row, col) { 0 } else { 255 };
+            image.extend_from_slice(&[value, value, value]);
+        }
+    }
+    image
+}
+
+// 1 ビットのグレースケール PNG。圧縮はせず stored ブロックで書く
+pub fn to_png(screen: &[Word]) -> Vec<u8> {
+    check_size(screen);
+    let mut raw = vec![];
+    for line in packed_rows(screen, false).chunks(WIDTH / 8) {
+        raw.push(0);
+        raw.extend_from_slice(line);
+    }
+
+    let mut ihdr = vec![];
+    ihdr.extend_from_slice(&(WIDTH as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(HEIGHT as u32).to_be_bytes());
+    ihdr.extend_from_slice(&[1, 0, 0, 0, 0]);
+
+    let mut image = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+    png_chunk(&mut image, b"IHDR", &ihdr);
+    png_chunk(&mut image, b"IDAT", &zlib_stored(&raw));
+    png_chunk(&mut image, b"IEND", &[]);
+    image
+}
+
+pub fn write_pbm<P: AsRef<Path>>(path: P, screen: &[Word]) -> io::Result<()> {
+    fs::write(path, to_pbm(screen))
+}
+
+pub fn write_ppm<P: AsRef<Path>>(path: P, screen: &[Word]) -> io::Result<()> {
+    fs::write(path, to_ppm(screen))
+}
+
+pub fn write_png<P: AsRef<Path>>(path: P, screen: &[Word]) -> io::Result<()> {
+    fs::write(path, to_png(screen))
+}
+
+fn check_size(screen: &[Word]) {
+    if screen.len() != SCREEN_SIZE {
+        panic!("screen needs {} words, got {}.", SCREEN_SIZE, screen.len());
+    }
+}
+
+// 1 行 64 バイト。左の画素がバイトの最上位ビット
+fn packed_rows(screen: &[Word], black: bool) -> Vec<u8> {
+    let mut bytes = vec![0u8; WIDTH / 8 * HEIGHT];
+    for row in 0..HEIGHT {
+        for col in 0..WIDTH {
+            if pixel(screen, row, col) == black {
+                bytes[row * WIDTH / 8 + col / 8] |= 0x80 >> (col % 8);
+            }
+        }
+    }
+    bytes
+}
+
+fn png_chunk(image: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    image.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = image.len();
+    image.extend_from_slice(kind);
+    image.extend_from_slice(data);
+    let crc = crc32(&image[start..]);
+    image.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let blocks = data.chunks(65535).collect::<Vec<_>>();
+    for (i, block) in blocks.iter().enumerate() {
+        out.push(if i + 1 == blocks.len() { 1 } else { 0 });
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for byte in data.iter() {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data.iter() {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{adler32, crc32, pixel, screen_region, to_pbm, to_png, to_ppm, SCREEN_SIZE};
+    use crate::logic::bit::O;
+    use crate::logic::Word;
+
+    fn screen() -> Vec<Word> {
+        let mut screen = vec![Word::new([O; 16]); SCREEN_SIZE];
+        // 一番左上の画素
+        screen[0] = Word::from(1u16);
+        // 1 行目の 16 から 23 番目の画素
+        screen[33] = Word::from(0x00ffu16);
+        // 一番右下の画素
+        screen[SCREEN_SIZE - 1] = Word::from(0x8000u16);
+        screen
+    }
+
+    #[test]
+    fn for_pixel() {
+        let screen = screen();
+        assert!(pixel(&screen, 0, 0));
+        assert!(!pixel(&screen, 0, 1));
+        assert!(!pixel(&screen, 0, 15));
+        assert!(pixel(&screen, 1, 16));
+        assert!(pixel(&screen, 1, 23));
+        assert!(!pixel(&screen, 1, 24));
+        assert!(pixel(&screen, 255, 511));
+    }
+
+    #[test]
+    fn for_screen_region() {
+        let mut ram = vec![Word::new([O; 16]); 24577];
+        ram[16384] = Word::from(7u16);
+        ram[24576] = Word::from(65u16);
+        let screen = screen_region(&ram);
+        assert_eq!(screen.len(), SCREEN_SIZE);
+        assert_eq!(screen[0], Word::from(7u16));
+    }
+
+    #[test]
+    fn for_pbm() {
+        let image = to_pbm(&screen());
+        let header = b"P4\n512 256\n";
+        assert_eq!(&image[..header.len()], header);
+        let data = &image[header.len()..];
+        assert_eq!(data.len(), 64 * 256);
+        assert_eq!(data[0], 0x80);
+        assert_eq!(data[1], 0x00);
+        assert_eq!(data[64 + 2], 0xff);
+        assert_eq!(data[64 + 3], 0x00);
+        assert_eq!(data[64 * 256 - 1], 0x01);
+    }
+
+    #[test]
+    fn for_ppm() {
+        let image = to_ppm(&screen());
+        let header = b"P6\n512 256\n255\n";
+        assert_eq!(&image[..header.len()], header);
+        let data = &image[header.len()..];
+        assert_eq!(data.len(), 3 * 512 * 256);
+        assert_eq!(&data[0..6], &[0, 0, 0, 255, 255, 255]);
+        assert_eq!(&data[data.len() - 3..], &[0, 0, 0]);
+    }
+
+    #[test]
+    fn for_checksums() {
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn for_png() {
+        let image = to_png(&screen());
+        assert_eq!(
+            &image[0..8],
+            &[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n']
+        );
+        assert_eq!(&image[12..16], b"IHDR");
+        assert_eq!(&image[16..29], &[0, 0, 2, 0, 0, 0, 1, 0, 1, 0, 0, 0, 0]);
+        assert_eq!(
+            &image[image.len() - 12..],
+            &[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]
+        );
+
+        // IDAT の中身を取り出して画素を確かめる
+        let len = u32::from_be_bytes([image[33], image[34], image[35], image[36]]) as usize;
+        assert_eq!(&image[37..41], b"IDAT");
+        let zlib = &image[41..41 + len];
+        assert_eq!(&zlib[0..2], &[0x78, 0x01]);
+        assert_eq!(zlib[2], 1);
+        let raw = &zlib[7..zlib.len() - 4];
+        assert_eq!(raw.len(), 65 * 256);
+        assert_eq!(&raw[0..3], &[0, 0x7f, 0xff]);
+        assert_eq!(&raw[65..69], &[0, 0xff, 0xff, 0x00]);
+        assert_eq!(raw[65 * 256 - 1], 0xfe);
+        assert_eq!(&zlib[zlib.len() - 4..], &adler32(raw).to_be_bytes());
+        let crc = crc32(&image[37..41 + len]);
+        assert_eq!(&image[41 + len..45 + len], &crc.to_be_bytes());
+    }
+}
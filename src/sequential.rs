@@ -1,6 +1,8 @@
-#![allow(dead_code, non_snake_case)]
+#![allow(dead_code, non_snake_case, clippy::upper_case_acronyms)]
 
-use crate::logic::bit::{self, I, O};
+use crate::logic::bit::{self, O};
+use crate::logic::{Mux, Word};
+use crate::snapshot::{Reader, Snapshot, SnapshotError, Writer};
 use ClockState::{Tick, Tock};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -58,6 +60,14 @@ impl DFF {
             Tock => self.state_new,
         }
     }
+
+    pub fn state_past(&self) -> bit {
+        self.state_past
+    }
+
+    pub fn state_new(&self) -> bit {
+        self.state_new
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -65,11 +75,142 @@ pub struct Bit {
     dff: DFF,
 }
 
+// load が I のときだけ a を取り込む。それ以外は今の値を保つ
+impl Bit {
+    pub fn new() -> Self {
+        Bit { dff: DFF::new() }
+    }
+
+    pub fn input(&mut self, a: bit, load: bit, clock: &Clock) {
+        let current = self.dff.state_new();
+        self.dff.input(Mux(current, a, load), clock);
+    }
+
+    pub fn output(&self, clock: &Clock) -> bit {
+        self.dff.output(clock)
+    }
+
+    pub fn state_past(&self) -> bit {
+        self.dff.state_past()
+    }
+
+    pub fn state_new(&self) -> bit {
+        self.dff.state_new()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Register {
+    bits: [Bit; 16],
+}
+
+impl Register {
+    pub fn new() -> Self {
+        Register {
+            bits: [Bit::new(); 16],
+        }
+    }
+
+    pub fn input(&mut self, a: Word, load: bit, clock: &Clock) {
+        for (i, b) in self.bits.iter_mut().enumerate() {
+            b.input(a[i], load, clock);
+        }
+    }
+
+    pub fn output(&self, clock: &Clock) -> Word {
+        let mut word = Word::new([O; 16]);
+        for (i, b) in self.bits.iter().enumerate() {
+            word[i] = b.output(clock);
+        }
+        word
+    }
+
+    pub fn state_past(&self) -> Word {
+        let mut word = Word::new([O; 16]);
+        for (i, b) in self.bits.iter().enumerate() {
+            word[i] = b.state_past();
+        }
+        word
+    }
+
+    pub fn state_new(&self) -> Word {
+        let mut word = Word::new([O; 16]);
+        for (i, b) in self.bits.iter().enumerate() {
+            word[i] = b.state_new();
+        }
+        word
+    }
+}
+
+impl Snapshot for Clock {
+    const KIND: &'static str = "Clock";
+    fn save(&self, w: &mut Writer) {
+        w.u8(match self.state {
+            Tick => 0,
+            Tock => 1,
+        });
+    }
+    fn load(r: &mut Reader) -> Result<Self, SnapshotError> {
+        let state = match r.u8()? {
+            0 => Tick,
+            1 => Tock,
+            n => {
+                return Err(SnapshotError::Invalid(format!(
+                    "{} is not a clock state.",
+                    n
+                )))
+            }
+        };
+        Ok(Clock { state })
+    }
+}
+
+impl Snapshot for DFF {
+    const KIND: &'static str = "DFF";
+    fn save(&self, w: &mut Writer) {
+        w.value(&self.state_past);
+        w.value(&self.state_new);
+    }
+    fn load(r: &mut Reader) -> Result<Self, SnapshotError> {
+        Ok(DFF {
+            state_past: r.value()?,
+            state_new: r.value()?,
+        })
+    }
+}
+
+impl Snapshot for Bit {
+    const KIND: &'static str = "Bit";
+    fn save(&self, w: &mut Writer) {
+        w.value(&self.dff);
+    }
+    fn load(r: &mut Reader) -> Result<Self, SnapshotError> {
+        Ok(Bit { dff: r.value()? })
+    }
+}
+
+impl Snapshot for Register {
+    const KIND: &'static str = "Register";
+    fn save(&self, w: &mut Writer) {
+        for b in self.bits.iter() {
+            w.value(b);
+        }
+    }
+    fn load(r: &mut Reader) -> Result<Self, SnapshotError> {
+        let mut register = Register::new();
+        for b in register.bits.iter_mut() {
+            *b = r.value()?;
+        }
+        Ok(register)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::ClockState::{Tick, Tock};
     use super::*;
     use crate::logic::bit::{I, O};
+    use crate::snapshot::{from_bytes, to_bytes};
 
     #[test]
     fn for_clock_new() {
@@ -81,6 +222,46 @@ mod tests {
         assert_eq!(clock.state(), Tick);
     }
 
+    #[test]
+    fn for_snapshot() {
+        // Tock の途中で保存した DFF は、読み戻しても同じ順に値を出す
+        let mut dff = DFF::new();
+        let mut clock = Clock::new();
+        dff.input(I, &clock);
+        clock.next();
+        let (saved_dff, saved_clock) = (to_bytes(&dff), to_bytes(&clock));
+        let mut dff2: DFF = from_bytes(&saved_dff).unwrap();
+        let mut clock2: Clock = from_bytes(&saved_clock).unwrap();
+        assert_eq!(clock2, clock);
+        for a in [O, I, I, O].iter() {
+            dff.input(*a, &clock);
+            dff2.input(*a, &clock2);
+            assert_eq!(dff2.output(&clock2), dff.output(&clock));
+            clock.next();
+            clock2.next();
+        }
+        let bit: Bit = from_bytes(&to_bytes(&Bit { dff })).unwrap();
+        assert_eq!(bit.dff.state_new(), dff.state_new());
+        assert!(from_bytes::<Clock>(&to_bytes(&dff)).is_err());
+    }
+
+    #[test]
+    fn for_register() {
+        let mut register = Register::new();
+        let mut clock = Clock::new();
+        register.input(Word::from(1234u16), I, &clock);
+        clock.next();
+        assert_eq!(register.output(&clock), Word::from(1234u16));
+        clock.next();
+        // load が O なら前の値のまま
+        register.input(Word::from(99u16), O, &clock);
+        assert_eq!(register.output(&clock), Word::from(1234u16));
+        clock.next();
+        assert_eq!(register.output(&clock), Word::from(1234u16));
+        let saved: Register = from_bytes(&to_bytes(&register)).unwrap();
+        assert_eq!(saved.output(&clock), Word::from(1234u16));
+    }
+
     #[test]
     fn for_dff() {
         // init: past -> O, new -> O
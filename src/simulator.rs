@@ -0,0 +1,274 @@
+#![allow(dead_code)]
+
+use crate::logic::bit::{self, I, O};
+use crate::logic::Nand;
+use crate::netlist::{Net, Netlist};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+// 入力が変わったゲートだけを評価し直すシミュレータ。
+// ゲートは作られた順がそのまま評価できる順なので、番号の小さい順に取り出せば
+// 一回の settle で同じゲートを二度評価することはない
+pub struct Simulator<'a> {
+    netlist: &'a Netlist,
+    values: Vec<bit>,
+    // ネットごとの、そのネットを入力に持つゲート
+    fanout: Vec<Vec<usize>>,
+    queue: BinaryHeap<Reverse<usize>>,
+    queued: Vec<bool>,
+    evaluations: u64,
+}
+
+impl<'a> Simulator<'a> {
+    // 入力と DFF をすべて O にして落ち着かせた状態から始める
+    pub fn new(netlist: &'a Netlist) -> Self {
+        let mut fanout = vec![vec![]; netlist.net_count()];
+        for (i, gate) in netlist.gates().iter().enumerate() {
+            fanout[gate.a.id()].push(i);
+            if gate.b != gate.a {
+                fanout[gate.b.id()].push(i);
+            }
+        }
+        let mut values = vec![O; netlist.net_count()];
+        values[netlist.constant(I).id()] = I;
+        let mut sim = Simulator {
+            netlist,
+            values,
+            fanout,
+            queue: BinaryHeap::new(),
+            queued: vec![false; netlist.gates().len()],
+            evaluations: 0,
+        };
+        for i in 0..netlist.gates().len() {
+            sim.schedule(i);
+        }
+        sim.settle();
+        sim.evaluations = 0;
+        sim
+    }
+
+    pub fn get(&self, net: Net) -> bit {
+        self.values[net.id()]
+    }
+
+    // 値が変わったときだけ、読んでいるゲートを予約する
+    pub fn set(&mut self, net: Net, value: bit) {
+        if self.values[net.id()] == value {
+            return;
+        }
+        self.values[net.id()] = value;
+        for i in 0..self.fanout[net.id()].len() {
+            self.schedule(self.fanout[net.id()][i]);
+        }
+    }
+
+    pub fn set_input(&mut self, name: &str, value: &[bit]) {
+        let nets = match self.netlist.inputs().iter().find(|(n, _)| n == name) {
+            Some((_, nets)) => nets,
+            None => panic!("`Simulator::set_input` fail: no input `{}`.", name),
+        };
+        if nets.len() != value.len() {
+            panic!(
+                "`Simulator::set_input` fail: `{}` has {} bits, got {}.",
+                name,
+                nets.len(),
+                value.len()
+            );
+        }
+        for (net, b) in nets.iter().zip(value.iter()) {
+            self.set(*net, *b);
+        }
+    }
+
+    // Netlist::eval と同じ並びの入力を与えて落ち着かせ、出力を返す
+    pub fn eval(&mut self, inputs: &[bit]) -> Vec<bit> {
+        let nets = self
+            .netlist
+            .inputs()
+            .iter()
+            .flat_map(|(_, nets)| nets.iter().copied())
+            .collect::<Vec<_>>();
+        if inputs.len() != nets.len() {
+            panic!(
+                "`Simulator::eval` fail: need {} input bits, got {}.",
+                nets.len(),
+                inputs.len()
+            );
+        }
+        for (net, value) in nets.iter().zip(inputs.iter()) {
+            self.set(*net, *value);
+        }
+        self.settle();
+        self.outputs()
+    }
+
+    // 予約されたゲートがなくなるまで評価し、評価した数を返す
+    pub fn settle(&mut self) -> u64 {
+        let start = self.evaluations;
+        while let Some(Reverse(i)) = self.queue.pop() {
+            self.queued[i] = false;
+            let gate = self.netlist.gates()[i];
+            self.evaluations += 1;
+            let value = Nand(self.values[gate.a.id()], self.values[gate.b.id()]);
+            self.set(gate.out, value);
+        }
+        self.evaluations - start
+    }
+
+    // クロックの立ち上がり。全 DFF が同時に入力を取り込んでから落ち着かせる
+    pub fn tick(&mut self) -> u64 {
+        self.settle();
+        let next = self
+            .netlist
+            .dffs()
+            .iter()
+            .map(|dff| (dff.out, self.values[dff.input.id()]))
+            .collect::<Vec<_>>();
+        for (out, value) in next {
+            self.set(out, value);
+        }
+        self.settle()
+    }
+
+    pub fn outputs(&self) -> Vec<bit> {
+        self.netlist
+            .outputs()
+            .iter()
+            .flat_map(|(_, nets)| nets.iter())
+            .map(|net| self.values[net.id()])
+            .collect()
+    }
+
+    pub fn output(&self, name: &str) -> Option<Vec<bit>> {
+        self.netlist
+            .outputs()
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, nets)| nets.iter().map(|net| self.values[net.id()]).collect())
+    }
+
+    // new からのゲート評価の総数
+    pub fn evaluations(&self) -> u64 {
+        self.evaluations
+    }
+
+    fn schedule(&mut self, gate: usize) {
+        if !self.queued[gate] {
+            self.queued[gate] = true;
+            self.queue.push(Reverse(gate));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Simulator;
+    use crate::arithmetic;
+    use crate::logic;
+    use crate::logic::bit::{I, O};
+    use crate::logic::Word;
+    use crate::netlist::{Netlist, DFF};
+
+    fn bits(value: u16) -> Vec<crate::logic::bit> {
+        Word::from(value).to_slice().to_vec()
+    }
+
+    // 毎回違う値になる簡単な擬似乱数
+    fn values(count: usize) -> Vec<u16> {
+        let mut x = 0x2545u16;
+        (0..count)
+            .map(|_| {
+                x ^= x << 7;
+                x ^= x >> 9;
+                x ^= x << 8;
+                x
+            })
+            .collect()
+    }
+
+    #[test]
+    fn for_add16() {
+        let mut n = Netlist::new();
+        let a = n.input16("a");
+        let b = n.input16("b");
+        let out = n.capture(|| arithmetic::Add16(a, b));
+        n.output16("out", out);
+        let mut sim = Simulator::new(&n);
+        let xs = values(200);
+        for pair in xs.windows(2) {
+            let (x, y) = (pair[0], pair[1]);
+            let expected = arithmetic::Add16(Word::from(x), Word::from(y));
+            let actual = sim.eval(&[bits(x), bits(y)].concat());
+            assert_eq!(actual, expected.to_slice().to_vec(), "{} + {}", x, y);
+            assert_eq!(actual, n.eval(&[bits(x), bits(y)].concat()));
+        }
+
+        // 最下位ビットの入れ替えは、全ゲートの評価よりずっと少なくて済む
+        sim.eval(&[bits(2), bits(4)].concat());
+        sim.set_input("a", &bits(3));
+        let evaluated = sim.settle();
+        assert!(evaluated > 0);
+        assert!((evaluated as usize) < n.nand_count() / 4);
+        assert_eq!(sim.output("out"), Some(bits(7)));
+        assert_eq!(sim.settle(), 0);
+    }
+
+    #[test]
+    fn for_alu() {
+        let mut n = Netlist::new();
+        let x = n.input16("x");
+        let y = n.input16("y");
+        let c = n.input_bus("c", 6);
+        let (out, zr, ng) = n.capture(|| arithmetic::ALU(x, y, c[0], c[1], c[2], c[3], c[4], c[5]));
+        n.output16("out", out);
+        n.output("zr", zr);
+        n.output("ng", ng);
+        let mut sim = Simulator::new(&n);
+        let xs = values(40);
+        for pair in xs.windows(2) {
+            for control in 0..64usize {
+                let c = (0..6)
+                    .map(|i| if control >> (5 - i) & 1 == 1 { I } else { O })
+                    .collect::<Vec<_>>();
+                let (out, zr, ng) = arithmetic::ALU(
+                    Word::from(pair[0]),
+                    Word::from(pair[1]),
+                    c[0],
+                    c[1],
+                    c[2],
+                    c[3],
+                    c[4],
+                    c[5],
+                );
+                let mut expected = out.to_slice().to_vec();
+                expected.push(zr);
+                expected.push(ng);
+                let input = [bits(pair[0]), bits(pair[1]), c].concat();
+                assert_eq!(sim.eval(&input), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn for_tick() {
+        // in -> DFF -> DFF の二段のシフトレジスタ
+        let mut n = Netlist::new();
+        let a = n.input("in");
+        let (first, out) = n.capture(|| {
+            let first = DFF(a);
+            (first, logic::Not(DFF(first)))
+        });
+        n.output("first", first);
+        n.output("out", out);
+        let mut sim = Simulator::new(&n);
+        assert_eq!(sim.outputs(), vec![O, I]);
+        sim.set_input("in", &[I]);
+        sim.tick();
+        assert_eq!(sim.outputs(), vec![I, I]);
+        sim.set_input("in", &[O]);
+        sim.tick();
+        assert_eq!(sim.outputs(), vec![O, O]);
+        sim.tick();
+        assert_eq!(sim.outputs(), vec![O, I]);
+    }
+}
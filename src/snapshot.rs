@@ -0,0 +1,251 @@
+#![allow(dead_code)]
+
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// 先頭は MAGIC、VERSION、KIND の順。数値はすべてビッグエンディアン
+pub const MAGIC: &[u8; 4] = b"N2TS";
+pub const VERSION: u16 = 2;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    Magic,
+    Version(u16),
+    Kind { expected: String, found: String },
+    Truncated,
+    Invalid(String),
+    Trailing(usize),
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, dest: &mut Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(dest, "{}", e),
+            SnapshotError::Magic => write!(dest, "not a snapshot."),
+            SnapshotError::Version(version) => write!(
+                dest,
+                "snapshot version {} is not supported, this build reads {}.",
+                version, VERSION
+            ),
+            SnapshotError::Kind { expected, found } => {
+                write!(dest, "snapshot holds a {}, need a {}.", found, expected)
+            }
+            SnapshotError::Truncated => write!(dest, "snapshot ends too early."),
+            SnapshotError::Invalid(message) => write!(dest, "{}", message),
+            SnapshotError::Trailing(len) => {
+                write!(dest, "snapshot has {} extra bytes at the end.", len)
+            }
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+// 保存できる状態。実装は各型のモジュールに置く
+pub trait Snapshot: Sized {
+    const KIND: &'static str;
+    fn save(&self, w: &mut Writer);
+    fn load(r: &mut Reader) -> Result<Self, SnapshotError>;
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    pub fn new() -> Self {
+        Writer::default()
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn size(&mut self, size: usize) {
+        self.u32(size as u32);
+    }
+
+    pub fn str(&mut self, s: &str) {
+        self.size(s.len());
+        self.bytes.extend_from_slice(s.as_bytes());
+    }
+
+    pub fn value<T: Snapshot>(&mut self, value: &T) {
+        value.save(self);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes, pos: 0 }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.bytes.len() - self.pos < n {
+            return Err(SnapshotError::Truncated);
+        }
+        let slice = &self.bytes[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(slice)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, SnapshotError> {
+        let b = self.take(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, SnapshotError> {
+        let b = self.take(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, SnapshotError> {
+        let b = self.take(8)?;
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(b);
+        Ok(u64::from_be_bytes(bytes))
+    }
+
+    // 残りのバイト数より大きい長さは壊れている
+    pub fn size(&mut self) -> Result<usize, SnapshotError> {
+        let size = self.u32()? as usize;
+        if size > self.remaining() {
+            return Err(SnapshotError::Truncated);
+        }
+        Ok(size)
+    }
+
+    pub fn str(&mut self) -> Result<String, SnapshotError> {
+        let size = self.size()?;
+        String::from_utf8(self.take(size)?.to_vec())
+            .map_err(|_| SnapshotError::Invalid("snapshot has a broken string.".to_string()))
+    }
+
+    pub fn value<T: Snapshot>(&mut self) -> Result<T, SnapshotError> {
+        T::load(self)
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+}
+
+pub fn to_bytes<T: Snapshot>(value: &T) -> Vec<u8> {
+    let mut w = Writer::new();
+    w.bytes.extend_from_slice(MAGIC);
+    w.u16(VERSION);
+    w.str(T::KIND);
+    value.save(&mut w);
+    w.into_bytes()
+}
+
+pub fn from_bytes<T: Snapshot>(bytes: &[u8]) -> Result<T, SnapshotError> {
+    let mut r = Reader::new(bytes);
+    if r.take(4).map_err(|_| SnapshotError::Magic)? != MAGIC {
+        return Err(SnapshotError::Magic);
+    }
+    let version = r.u16()?;
+    if version != VERSION {
+        return Err(SnapshotError::Version(version));
+    }
+    let kind = r.str()?;
+    if kind != T::KIND {
+        return Err(SnapshotError::Kind {
+            expected: T::KIND.to_string(),
+            found: kind,
+        });
+    }
+    let value = T::load(&mut r)?;
+    match r.remaining() {
+        0 => Ok(value),
+        n => Err(SnapshotError::Trailing(n)),
+    }
+}
+
+pub fn write_file<T: Snapshot, P: AsRef<Path>>(path: P, value: &T) -> Result<(), SnapshotError> {
+    fs::write(path, to_bytes(value))?;
+    Ok(())
+}
+
+pub fn read_file<T: Snapshot, P: AsRef<Path>>(path: P) -> Result<T, SnapshotError> {
+    from_bytes(&fs::read(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_bytes, to_bytes, SnapshotError};
+    use crate::logic::bit::{I, O};
+    use crate::logic::Word;
+
+    #[test]
+    fn for_header() {
+        let bytes = to_bytes(&Word::from(0x1234u16));
+        assert_eq!(
+            bytes,
+            vec![b'N', b'2', b'T', b'S', 0, 2, 0, 0, 0, 4, b'W', b'o', b'r', b'd', 0x12, 0x34]
+        );
+        assert_eq!(from_bytes::<Word>(&bytes).unwrap(), Word::from(0x1234u16));
+
+        let mut newer = bytes.clone();
+        newer[5] = 3;
+        assert!(matches!(
+            from_bytes::<Word>(&newer),
+            Err(SnapshotError::Version(3))
+        ));
+        assert!(matches!(
+            from_bytes::<Word>(b"P4\n"),
+            Err(SnapshotError::Magic)
+        ));
+        assert!(matches!(
+            from_bytes::<Word>(&bytes[..15]),
+            Err(SnapshotError::Truncated)
+        ));
+        assert!(matches!(
+            from_bytes::<Word>(&[bytes.clone(), vec![0]].concat()),
+            Err(SnapshotError::Trailing(1))
+        ));
+        assert_eq!(
+            from_bytes::<crate::logic::bit>(&bytes)
+                .unwrap_err()
+                .to_string(),
+            "snapshot holds a Word, need a bit."
+        );
+        assert_eq!(from_bytes::<crate::logic::bit>(&to_bytes(&I)).unwrap(), I);
+        assert_eq!(from_bytes::<crate::logic::bit>(&to_bytes(&O)).unwrap(), O);
+    }
+}
@@ -0,0 +1,572 @@
+#![allow(dead_code)]
+
+use crate::vm::{Arithmetic, Command, Program, Segment};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+// 共通の call / return の本体
+const CALL: &str = "$CALL";
+const RETURN: &str = "$RETURN";
+
+// optimize のとき
+// - push のすぐ後の pop で SP を戻して進める組を消す
+// - push constant と二項演算をまとめる
+// - call と return は共通のサブルーチンに飛ぶ
+pub fn translate(program: &Program, optimize: bool) -> String {
+    let mut writer = Writer {
+        lines: vec![],
+        function: String::new(),
+        counter: 0,
+        optimize,
+    };
+    if program.has_function("Sys.init") {
+        writer.emit(&["@256", "D=A", "@SP", "M=D"]);
+        writer.call("Sys.init", 0);
+    }
+    let commands = program.commands();
+    let calls = program.has_function("Sys.init")
+        || commands
+            .iter()
+            .any(|l| matches!(l.command, Command::Call(..) | Command::Return));
+    if optimize && calls {
+        writer.shared_routines();
+    }
+
+    let mut i = 0;
+    while i < commands.len() {
+        let located = &commands[i];
+        let next = commands.get(i + 1).map(|l| &l.command);
+        if let (true, Command::Push(Segment::Constant, k), Some(Command::Arithmetic(op))) =
+            (optimize, &located.command, next)
+        {
+            if writer.fused(*k, *op) {
+                i += 2;
+                continue;
+            }
+        }
+        writer.command(&located.command, &located.file);
+        i += 1;
+    }
+
+    let mut lines = writer.lines;
+    if optimize {
+        lines = peephole(lines);
+    }
+    let mut asm = lines.join("\n");
+    asm.push('\n');
+    asm
+}
+
+// ラベルとコメントと空行を除いた命令の数
+pub fn rom_size(asm: &str) -> usize {
+    asm.lines()
+        .map(|line| line.split("//").next().unwrap_or("").trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('('))
+        .count()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Report {
+    pub before: usize,
+    pub after: usize,
+}
+
+impl Display for Report {
+    fn fmt(&self, dest: &mut Formatter) -> fmt::Result {
+        let saved = self.before as i64 - self.after as i64;
+        write!(
+            dest,
+            "ROM size: {} -> {} instructions ({} saved, {}%)",
+            self.before,
+            self.after,
+            saved,
+            saved * 100 / self.before.max(1) as i64
+        )
+    }
+}
+
+pub fn report(program: &Program) -> Report {
+    Report {
+        before: rom_size(&translate(program, false)),
+        after: rom_size(&translate(program, true)),
+    }
+}
+
+struct Writer {
+    lines: Vec<String>,
+    // ラベルの前に付ける関数名
+    function: String,
+    counter: usize,
+    optimize: bool,
+}
+
+impl Writer {
+    fn emit(&mut self, lines: &[&str]) {
+        self.lines.extend(lines.iter().map(|line| line.to_string()));
+    }
+
+    fn unique(&mut self, name: &str) -> String {
+        self.counter += 1;
+        format!("{}.{}", name, self.counter)
+    }
+
+    fn push_d(&mut self) {
+        self.emit(&["@SP", "A=M", "M=D", "@SP", "M=M+1"]);
+    }
+
+    fn pop_d(&mut self) {
+        self.emit(&["@SP", "AM=M-1", "D=M"]);
+    }
+
+    fn command(&mut self, command: &Command, file: &str) {
+        self.lines.push(format!("// {}", describe(command)));
+        match command {
+            Command::Push(segment, index) => {
+                match segment {
+                    Segment::Constant => {
+                        self.emit(&[&format!("@{}", index), "D=A"]);
+                    }
+                    Segment::Local | Segment::Argument | Segment::This | Segment::That => {
+                        let base = base(*segment);
+                        self.emit(&[&format!("@{}", index), "D=A", base, "A=D+M", "D=M"]);
+                    }
+                    _ => {
+                        let address = fixed_address(*segment, *index, file);
+                        self.emit(&[&address, "D=M"]);
+                    }
+                }
+                self.push_d();
+            }
+            Command::Pop(segment, index) => match segment {
+                Segment::Local | Segment::Argument | Segment::This | Segment::That => {
+                    let base = base(*segment);
+                    self.emit(&[&format!("@{}", index), "D=A", base, "D=D+M", "@R13", "M=D"]);
+                    self.pop_d();
+                    self.emit(&["@R13", "A=M", "M=D"]);
+                }
+                _ => {
+                    self.pop_d();
+                    let address = fixed_address(*segment, *index, file);
+                    self.emit(&[&address, "M=D"]);
+                }
+            },
+            Command::Arithmetic(op) => self.arithmetic(*op),
+            Command::Label(label) => {
+                let label = format!("({}${})", self.function, label);
+                self.lines.push(label);
+            }
+            Command::Goto(label) => {
+                let label = format!("@{}${}", self.function, label);
+                self.emit(&[&label, "0;JMP"]);
+            }
+            Command::IfGoto(label) => {
+                self.pop_d();
+                let label = format!("@{}${}", self.function, label);
+                self.emit(&[&label, "D;JNE"]);
+            }
+            Command::Function(name, locals) => {
+                self.function = name.clone();
+                self.lines.push(format!("({})", name));
+                for _ in 0..*locals {
+                    self.emit(&["@SP", "A=M", "M=0", "@SP", "M=M+1"]);
+                }
+            }
+            Command::Call(name, args) => self.call(name, *args),
+            Command::Return => {
+                if self.optimize {
+                    self.emit(&[&format!("@{}", RETURN), "0;JMP"]);
+                } else {
+                    self.ret();
+                }
+            }
+        }
+    }
+
+    fn arithmetic(&mut self, op: Arithmetic) {
+        match op {
+            Arithmetic::Neg => self.emit(&["@SP", "A=M-1", "M=-M"]),
+            Arithmetic::Not => self.emit(&["@SP", "A=M-1", "M=!M"]),
+            Arithmetic::Eq | Arithmetic::Gt | Arithmetic::Lt => {
+                let jump = match op {
+                    Arithmetic::Eq => "D;JEQ",
+                    Arithmetic::Gt => "D;JGT",
+                    _ => "D;JLT",
+                };
+                let label = self.unique("$CMP");
+                self.pop_d();
+                if op == Arithmetic::Eq {
+                    self.emit(&["A=A-1", "D=M-D"]);
+                } else {
+                    self.difference(&label);
+                }
+                self.emit(&["@SP", "A=M-1", "M=-1", &format!("@{}", label), jump]);
+                self.emit(&["@SP", "A=M-1", "M=0", &format!("({})", label)]);
+            }
+            _ => {
+                self.pop_d();
+                self.emit(&["A=A-1", binary(op)]);
+            }
+        }
+    }
+
+    // D の y と スタックの先頭の x から、x - y と同じ符号の値を D に作る。
+    // 符号が違うと x - y はあふれることがあるので、x の符号を持ち 0 にならない x|1 を使う
+    fn difference(&mut self, label: &str) {
+        let part = |name: &str| format!("{}${}", label, name);
+        let (negative, sign, same, test) = (part("NEG"), part("SIGN"), part("SAME"), part("TEST"));
+        self.emit(&[
+            "@R13",
+            "M=D",
+            "@SP",
+            "A=M-1",
+            "D=M",
+            &format!("@{}", negative),
+            "D;JLT",
+        ]);
+        self.emit(&["@R13", "D=M", &format!("@{}", same), "D;JGE"]);
+        self.emit(&[&format!("@{}", sign), "0;JMP", &format!("({})", negative)]);
+        self.emit(&["@R13", "D=M", &format!("@{}", same), "D;JLT"]);
+        self.emit(&[&format!("({})", sign), "@SP", "A=M-1", "D=M", "@1", "D=D|A"]);
+        self.emit(&[&format!("@{}", test), "0;JMP", &format!("({})", same)]);
+        self.emit(&["@R13", "D=M", "@SP", "A=M-1", "D=M-D"]);
+        self.lines.push(format!("({})", test));
+    }
+
+    // push constant k の直後の二項演算は k を A から直接使う
+    fn fused(&mut self, k: u16, op: Arithmetic) -> bool {
+        let comp = match op {
+            Arithmetic::Add | Arithmetic::Sub | Arithmetic::And | Arithmetic::Or => binary(op),
+            _ => return false,
+        };
+        self.lines.push(format!(
+            "// push constant {}, {}",
+            k,
+            describe(&Command::Arithmetic(op))
+        ));
+        self.emit(&[&format!("@{}", k), "D=A", "@SP", "A=M-1", comp]);
+        true
+    }
+
+    fn call(&mut self, name: &str, args: u16) {
+        let label = self.unique(&format!("{}$ret", self.function));
+        self.lines.push(format!("// call {} {}", name, args));
+        if self.optimize {
+            self.emit(&[&format!("@{}", name), "D=A", "@R13", "M=D"]);
+            self.emit(&[&format!("@{}", args), "D=A", "@R14", "M=D"]);
+            self.emit(&[
+                &format!("@{}", label),
+                "D=A",
+                &format!("@{}", CALL),
+                "0;JMP",
+            ]);
+        } else {
+            self.emit(&[&format!("@{}", label), "D=A"]);
+            self.push_d();
+            for pointer in ["@LCL", "@ARG", "@THIS", "@THAT"].iter() {
+                self.emit(&[pointer, "D=M"]);
+                self.push_d();
+            }
+            self.emit(&["@SP", "D=M", &format!("@{}", args as usize + 5), "D=D-A"]);
+            self.emit(&["@ARG", "M=D", "@SP", "D=M", "@LCL", "M=D"]);
+            self.emit(&[&format!("@{}", name), "0;JMP"]);
+        }
+        self.lines.push(format!("({})", label));
+    }
+
+    fn ret(&mut self) {
+        self.emit(&[
+            "@LCL", "D=M", "@R13", "M=D", "@5", "A=D-A", "D=M", "@R14", "M=D",
+        ]);
+        self.pop_d();
+        self.emit(&["@ARG", "A=M", "M=D", "@ARG", "D=M+1", "@SP", "M=D"]);
+        for pointer in ["@THAT", "@THIS", "@ARG", "@LCL"].iter() {
+            self.emit(&["@R13", "AM=M-1", "D=M", pointer, "M=D"]);
+        }
+        self.emit(&["@R14", "A=M", "0;JMP"]);
+    }
+
+    // R13 に飛び先、R14 に引数の数、D に戻り先を入れて $CALL に飛ぶ
+    fn shared_routines(&mut self) {
+        let skip = self.unique("$SKIP");
+        self.emit(&[&format!("@{}", skip), "0;JMP"]);
+        self.lines.push(format!("({})", CALL));
+        self.push_d();
+        for pointer in ["@LCL", "@ARG", "@THIS", "@THAT"].iter() {
+            self.emit(&[pointer, "D=M"]);
+            self.push_d();
+        }
+        self.emit(&["@R14", "D=M", "@5", "D=D+A", "@SP", "D=M-D", "@ARG", "M=D"]);
+        self.emit(&["@SP", "D=M", "@LCL", "M=D", "@R13", "A=M", "0;JMP"]);
+        self.lines.push(format!("({})", RETURN));
+        self.ret();
+        self.lines.push(format!("({})", skip));
+    }
+}
+
+fn binary(op: Arithmetic) -> &'static str {
+    match op {
+        Arithmetic::Add => "M=D+M",
+        Arithmetic::Sub => "M=M-D",
+        Arithmetic::And => "M=D&M",
+        _ => "M=D|M",
+    }
+}
+
+fn base(segment: Segment) -> &'static str {
+    match segment {
+        Segment::Local => "@LCL",
+        Segment::Argument => "@ARG",
+        Segment::This => "@THIS",
+        _ => "@THAT",
+    }
+}
+
+fn fixed_address(segment: Segment, index: u16, file: &str) -> String {
+    match segment {
+        Segment::Temp => format!("@{}", 5 + index),
+        Segment::Pointer => format!("@{}", 3 + index),
+        _ => format!("@{}.{}", file, index),
+    }
+}
+
+fn describe(command: &Command) -> String {
+    let segment = |segment: &Segment| format!("{:?}", segment).to_lowercase();
+    match command {
+        Command::Push(s, i) => format!("push {} {}", segment(s), i),
+        Command::Pop(s, i) => format!("pop {} {}", segment(s), i),
+        Command::Arithmetic(op) => format!("{:?}", op).to_lowercase(),
+        Command::Label(label) => format!("label {}", label),
+        Command::Goto(label) => format!("goto {}", label),
+        Command::IfGoto(label) => format!("if-goto {}", label),
+        Command::Function(name, locals) => format!("function {} {}", name, locals),
+        Command::Call(name, args) => format!("call {} {}", name, args),
+        Command::Return => "return".to_string(),
+    }
+}
+
+// コメントを除いて、命令の並びを書き換える
+fn peephole(lines: Vec<String>) -> Vec<String> {
+    let mut lines = lines
+        .into_iter()
+        .filter(|line| !line.starts_with("//"))
+        .collect::<Vec<_>>();
+    let mut out: Vec<String> = vec![];
+    let mut i = 0;
+    while i < lines.len() {
+        // push の SP++ のすぐ後の pop の SP--
+        if lines[i..].starts_with(&["@SP", "M=M+1", "@SP", "AM=M-1"].map(String::from)) {
+            out.extend(["@SP", "A=M"].iter().map(|s| s.to_string()));
+            i += 4;
+            continue;
+        }
+        out.push(std::mem::take(&mut lines[i]));
+        i += 1;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{report, rom_size, translate};
+    use crate::assembler::assemble;
+    use crate::emulator::Computer;
+    use crate::logic::Word;
+    use crate::vm::{Program, Vm};
+
+    const FIB: &str = "
+function Main.fibonacci 0
+    push argument 0
+    push constant 2
+    lt
+    if-goto BASE
+    push argument 0
+    push constant 2
+    sub
+    call Main.fibonacci 1
+    push argument 0
+    push constant 1
+    sub
+    call Main.fibonacci 1
+    add
+    return
+label BASE
+    push argument 0
+    return
+";
+
+    const SYS: &str = "
+function Sys.init 2
+    push constant 12
+    call Main.fibonacci 1
+    pop static 0
+    push constant 3000
+    pop pointer 1
+    push constant 5
+    pop local 0
+label LOOP
+    push local 0
+    push constant 0
+    eq
+    if-goto DONE
+    push local 0
+    push local 0
+    push constant 1
+    sub
+    pop local 0
+    push local 1
+    add
+    pop local 1
+    push local 1
+    neg
+    pop that 0
+    push local 1
+    push constant 20
+    gt
+    pop that 1
+    push constant 255
+    not
+    push constant 7
+    and
+    push constant 8
+    or
+    pop static 1
+    goto LOOP
+label DONE
+    push local 1
+    pop temp 3
+label HALT
+    goto HALT
+";
+
+    // Hack で動かした結果と VM インタプリタの結果を比べる
+    fn check(files: &[(&str, &str)], addresses: &[usize]) -> (usize, usize) {
+        let program = Program::parse(files).unwrap();
+        let mut vm = Vm::new(program.clone());
+        vm.run(100_000).unwrap();
+        let expected = addresses.iter().map(|a| vm.peek(*a)).collect::<Vec<_>>();
+
+        let mut sizes = vec![];
+        for optimize in [false, true].iter() {
+            let asm = translate(&program, *optimize);
+            let code = assemble("Test.asm", &asm).unwrap().code;
+            sizes.push(code.len());
+            assert_eq!(code.len(), rom_size(&asm));
+            let mut computer = Computer::new(code).unwrap();
+            computer.run(1_000_000).unwrap();
+            assert!(computer.is_halted());
+            let actual = addresses
+                .iter()
+                .map(|a| u16::from(computer.peek(*a)) as i16)
+                .collect::<Vec<_>>();
+            assert_eq!(actual, expected, "optimize: {}", optimize);
+        }
+        (sizes[0], sizes[1])
+    }
+
+    #[test]
+    fn for_differential() {
+        let files = [("Main", FIB), ("Sys", SYS)];
+        let program = Program::parse(&files).unwrap();
+        let mut vm = Vm::new(program);
+        vm.run(100_000).unwrap();
+        assert_eq!(vm.peek(16), 144);
+        assert_eq!(vm.peek(8), 15);
+
+        let (before, after) = check(&files, &[16, 17, 8, 3000, 3001]);
+        assert!(after < before);
+    }
+
+    // 符号の違う二つの差は 16 ビットからあふれるが、比較の結果は VM と同じになる
+    #[test]
+    fn for_comparison_overflow() {
+        let src = "
+function Sys.init 0
+    push constant 30000
+    push constant 30000
+    neg
+    gt
+    pop temp 0
+    push constant 30000
+    neg
+    push constant 30000
+    lt
+    pop temp 1
+    push constant 32767
+    push constant 32767
+    neg
+    push constant 1
+    sub
+    gt
+    pop temp 2
+    push constant 32767
+    neg
+    push constant 1
+    sub
+    push constant 32767
+    lt
+    pop temp 3
+    push constant 32767
+    push constant 30000
+    neg
+    lt
+    pop temp 4
+    push constant 0
+    push constant 1
+    neg
+    gt
+    pop temp 5
+    push constant 30000
+    push constant 30000
+    neg
+    eq
+    pop temp 6
+    push constant 7
+    push constant 5
+    gt
+    pop temp 7
+label HALT
+    goto HALT
+";
+        check(&[("Sys", src)], &[5, 6, 7, 8, 9, 10, 11, 12]);
+        let mut vm = Vm::new(Program::parse(&[("Sys", src)]).unwrap());
+        vm.run(1000).unwrap();
+        let results = (5..13).map(|a| vm.peek(a)).collect::<Vec<_>>();
+        assert_eq!(results, vec![-1, -1, -1, -1, 0, -1, 0, -1]);
+    }
+
+    #[test]
+    fn for_without_bootstrap() {
+        let src = "push constant 7\npush constant 8\nadd\npush constant 9\nsub\n\
+                   push constant 3\npush constant 3\neq\npop temp 0\n";
+        let asm = translate(&Program::parse(&[("Simple", src)]).unwrap(), false);
+        assert!(asm.starts_with("// push constant 7\n@7\nD=A\n@SP\n"));
+        let mut computer = Computer::new(assemble("Simple.asm", &asm).unwrap().code).unwrap();
+        computer.poke(0, Word::from(256u16));
+        computer.run(1000).unwrap();
+        assert_eq!(computer.peek(256), Word::from(6u16));
+        assert_eq!(computer.peek(5), Word::from(0xffffu16));
+        assert_eq!(computer.peek(0), Word::from(257u16));
+    }
+
+    #[test]
+    fn for_peephole() {
+        let src = "push constant 7\npush constant 8\nadd\npush local 0\npop temp 0\n";
+        let asm = translate(&Program::parse(&[("Simple", src)]).unwrap(), true);
+        assert_eq!(
+            asm,
+            "@7\nD=A\n@SP\nA=M\nM=D\n@SP\nM=M+1\n@8\nD=A\n@SP\nA=M-1\nM=D+M\n\
+             @0\nD=A\n@LCL\nA=D+M\nD=M\n@SP\nA=M\nM=D\n@SP\nA=M\nD=M\n@5\nM=D\n"
+        );
+    }
+
+    #[test]
+    fn for_report() {
+        let program = Program::parse(&[("Main", FIB), ("Sys", SYS)]).unwrap();
+        let report = report(&program);
+        assert!(report.after < report.before);
+        assert!(format!("{}", report).starts_with(&format!(
+            "ROM size: {} -> {} instructions",
+            report.before, report.after
+        )));
+    }
+}
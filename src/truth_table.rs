@@ -0,0 +1,314 @@
+#![allow(dead_code)]
+
+use crate::logic::bit::{self, I, O};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+pub const MAX_INPUTS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Markdown,
+    Csv,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row {
+    pub inputs: Vec<bit>,
+    pub outputs: Vec<bit>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub inputs: Vec<bit>,
+    pub expected: Vec<bit>,
+    pub actual: Vec<bit>,
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, dest: &mut Formatter) -> fmt::Result {
+        write!(
+            dest,
+            "inputs {}: expected {}, got {}",
+            bits_to_string(&self.inputs),
+            bits_to_string(&self.expected),
+            bits_to_string(&self.actual)
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TruthTable {
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+    rows: Vec<Row>,
+}
+
+impl TruthTable {
+    // 入力の組み合わせを全て列挙する (先頭の入力が最上位ビット)
+    pub fn new<F>(inputs: &[&str], outputs: &[&str], chip: F) -> Self
+    where
+        F: Fn(&[bit]) -> Vec<bit>,
+    {
+        if inputs.len() > MAX_INPUTS {
+            panic!(
+                "`TruthTable::new` fail: {} inputs, need {} or less.",
+                inputs.len(),
+                MAX_INPUTS
+            );
+        }
+        let rows = (0..1usize << inputs.len())
+            .map(|n| {
+                let inputs = to_bits(n, inputs.len());
+                let outputs = chip(&inputs);
+                Row { inputs, outputs }
+            })
+            .collect::<Vec<_>>();
+        for row in rows.iter() {
+            if row.outputs.len() != outputs.len() {
+                panic!(
+                    "`TruthTable::new` fail: chip returned {} outputs, need {}.",
+                    row.outputs.len(),
+                    outputs.len()
+                );
+            }
+        }
+        TruthTable {
+            inputs: inputs.iter().map(|s| s.to_string()).collect(),
+            outputs: outputs.iter().map(|s| s.to_string()).collect(),
+            rows,
+        }
+    }
+
+    pub fn rows(&self) -> &[Row] {
+        &self.rows
+    }
+
+    pub fn check<F>(&self, reference: F) -> Result<(), Vec<Mismatch>>
+    where
+        F: Fn(&[bit]) -> Vec<bit>,
+    {
+        let mismatches = self
+            .rows
+            .iter()
+            .filter_map(|row| {
+                let expected = reference(&row.inputs);
+                if expected == row.outputs {
+                    None
+                } else {
+                    Some(Mismatch {
+                        inputs: row.inputs.clone(),
+                        expected,
+                        actual: row.outputs.clone(),
+                    })
+                }
+            })
+            .collect::<Vec<_>>();
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(mismatches)
+        }
+    }
+
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Text => self.render_text(),
+            Format::Markdown => self.render_markdown(),
+            Format::Csv => self.render_csv(),
+        }
+    }
+
+    // nand2tetris の .cmp ファイルと同じ形式
+    fn render_text(&self) -> String {
+        let headers = self.headers();
+        let widths = headers.iter().map(|h| h.len() + 4).collect::<Vec<_>>();
+        let line = |cells: Vec<String>| -> String {
+            let mut buf = "|".to_string();
+            for (cell, width) in cells.iter().zip(widths.iter()) {
+                buf = format!("{}{:^width$}|", buf, cell, width = width);
+            }
+            buf
+        };
+        let mut lines = vec![line(headers.clone())];
+        for row in self.rows.iter() {
+            lines.push(line(row_cells(row)));
+        }
+        lines.join("\n") + "\n"
+    }
+
+    fn render_markdown(&self) -> String {
+        let headers = self.headers();
+        let mut lines = vec![
+            format!("| {} |", headers.join(" | ")),
+            format!(
+                "|{}|",
+                headers
+                    .iter()
+                    .map(|h| "-".repeat(h.len() + 2))
+                    .collect::<Vec<_>>()
+                    .join("|")
+            ),
+        ];
+        for row in self.rows.iter() {
+            let cells = row_cells(row)
+                .iter()
+                .zip(headers.iter())
+                .map(|(cell, h)| format!("{:^width$}", cell, width = h.len()))
+                .collect::<Vec<_>>();
+            lines.push(format!("| {} |", cells.join(" | ")));
+        }
+        lines.join("\n") + "\n"
+    }
+
+    fn render_csv(&self) -> String {
+        let mut lines = vec![self.headers().join(",")];
+        for row in self.rows.iter() {
+            lines.push(row_cells(row).join(","));
+        }
+        lines.join("\n") + "\n"
+    }
+
+    fn headers(&self) -> Vec<String> {
+        self.inputs
+            .iter()
+            .chain(self.outputs.iter())
+            .cloned()
+            .collect()
+    }
+}
+
+impl Display for TruthTable {
+    fn fmt(&self, dest: &mut Formatter) -> fmt::Result {
+        write!(dest, "{}", self.render(Format::Text))
+    }
+}
+
+pub fn to_bits(value: usize, width: usize) -> Vec<bit> {
+    (0..width)
+        .rev()
+        .map(|i| if (value >> i) & 1 == 1 { I } else { O })
+        .collect()
+}
+
+pub fn from_bits(bits: &[bit]) -> usize {
+    bits.iter()
+        .fold(0, |acc, b| (acc << 1) | if *b == I { 1 } else { 0 })
+}
+
+fn bits_to_string(bits: &[bit]) -> String {
+    bits.iter()
+        .map(|b| if *b == I { '1' } else { '0' })
+        .collect()
+}
+
+fn row_cells(row: &Row) -> Vec<String> {
+    row.inputs
+        .iter()
+        .chain(row.outputs.iter())
+        .map(|b| if *b == I { "1" } else { "0" }.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_bits, to_bits, Format, TruthTable};
+    use crate::arithmetic::FullAdder;
+    use crate::logic::bit::{I, O};
+    use crate::logic::{DMux4Way, DMux8Way, Mux, Nand, Xor};
+
+    #[test]
+    fn for_to_bits() {
+        assert_eq!(to_bits(0, 3), vec![O, O, O]);
+        assert_eq!(to_bits(5, 3), vec![I, O, I]);
+        assert_eq!(from_bits(&[I, I, O]), 6);
+        assert_eq!(from_bits(&to_bits(12345, 20)), 12345);
+    }
+
+    #[test]
+    fn for_render() {
+        let table = TruthTable::new(&["a", "b"], &["out"], |i| vec![Nand(i[0], i[1])]);
+        assert_eq!(
+            table.render(Format::Text),
+            "|  a  |  b  |  out  |\n\
+             |  0  |  0  |   1   |\n\
+             |  0  |  1  |   1   |\n\
+             |  1  |  0  |   1   |\n\
+             |  1  |  1  |   0   |\n"
+        );
+        assert_eq!(
+            table.render(Format::Markdown),
+            "| a | b | out |\n\
+             |---|---|-----|\n\
+             | 0 | 0 |  1  |\n\
+             | 0 | 1 |  1  |\n\
+             | 1 | 0 |  1  |\n\
+             | 1 | 1 |  0  |\n"
+        );
+        assert_eq!(
+            table.render(Format::Csv),
+            "a,b,out\n0,0,1\n0,1,1\n1,0,1\n1,1,0\n"
+        );
+    }
+
+    #[test]
+    fn for_check() {
+        let table = TruthTable::new(&["a", "b"], &["out"], |i| vec![Xor(i[0], i[1])]);
+        assert_eq!(
+            table.check(|i| vec![if i[0] != i[1] { I } else { O }]),
+            Ok(())
+        );
+        let mismatches = table.check(|i| to_bits(from_bits(i) & 1, 1)).unwrap_err();
+        assert_eq!(mismatches.len(), 2);
+        assert_eq!(mismatches[0].inputs, vec![I, O]);
+        assert_eq!(
+            format!("{}", mismatches[0]),
+            "inputs 10: expected 0, got 1".to_string()
+        );
+    }
+
+    #[test]
+    fn for_exhaustive_chips() {
+        let table = TruthTable::new(&["a", "b", "sel"], &["out"], |i| {
+            vec![Mux(i[0], i[1], i[2])]
+        });
+        assert_eq!(
+            table.check(|i| vec![if i[2] == I { i[1] } else { i[0] }]),
+            Ok(())
+        );
+
+        let table = TruthTable::new(&["a", "b", "c"], &["carry", "sum"], |i| {
+            FullAdder(i[0], i[1], i[2]).to_vec()
+        });
+        assert_eq!(
+            table.check(|i| to_bits(i.iter().filter(|b| **b == I).count(), 2)),
+            Ok(())
+        );
+
+        let table = TruthTable::new(&["in", "sel1", "sel0"], &["a", "b", "c", "d"], |i| {
+            DMux4Way(i[0], [i[1], i[2]]).to_vec()
+        });
+        assert_eq!(
+            table.check(|i| to_bits(from_bits(&i[0..1]) << (3 - from_bits(&i[1..])), 4)),
+            Ok(())
+        );
+
+        let table = TruthTable::new(
+            &["in", "sel2", "sel1", "sel0"],
+            &["a", "b", "c", "d", "e", "f", "g", "h"],
+            |i| DMux8Way(i[0], [i[1], i[2], i[3]]).to_vec(),
+        );
+        assert_eq!(
+            table.check(|i| to_bits(from_bits(&i[0..1]) << (7 - from_bits(&i[1..])), 8)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn for_too_many_inputs() {
+        let names = ["x"; 21];
+        TruthTable::new(&names, &["out"], |i| vec![i[0]]);
+    }
+}
@@ -0,0 +1,408 @@
+#![allow(dead_code)]
+
+use crate::logic::bit::{self, I, O};
+use crate::logic::Word;
+use crate::sequential::ClockState::{Tick, Tock};
+use crate::sequential::{Bit, Clock, Register, DFF};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::io::Write;
+
+pub trait Trace {
+    // (信号名, 値) の組。名前が空なら probe 名をそのまま使う
+    fn signals(&self) -> Vec<(&'static str, Vec<bit>)>;
+}
+
+impl Trace for bit {
+    fn signals(&self) -> Vec<(&'static str, Vec<bit>)> {
+        vec![("", vec![*self])]
+    }
+}
+
+impl Trace for Word {
+    fn signals(&self) -> Vec<(&'static str, Vec<bit>)> {
+        vec![("", self.to_slice().to_vec())]
+    }
+}
+
+impl Trace for DFF {
+    fn signals(&self) -> Vec<(&'static str, Vec<bit>)> {
+        vec![
+            ("state_past", vec![self.state_past()]),
+            ("state_new", vec![self.state_new()]),
+        ]
+    }
+}
+
+impl Trace for Bit {
+    fn signals(&self) -> Vec<(&'static str, Vec<bit>)> {
+        vec![
+            ("state_past", vec![self.state_past()]),
+            ("state_new", vec![self.state_new()]),
+        ]
+    }
+}
+
+impl Trace for Register {
+    fn signals(&self) -> Vec<(&'static str, Vec<bit>)> {
+        vec![
+            ("state_past", self.state_past().to_slice().to_vec()),
+            ("state_new", self.state_new().to_slice().to_vec()),
+        ]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceError {
+    Name(String),
+    Duplicate(String),
+}
+
+impl Display for TraceError {
+    fn fmt(&self, dest: &mut Formatter) -> fmt::Result {
+        match self {
+            TraceError::Name(name) => write!(
+                dest,
+                "`{}` is not a signal name, use printable characters without spaces.",
+                name
+            ),
+            TraceError::Duplicate(name) => write!(dest, "`{}` is already watched.", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Probe(usize);
+
+#[derive(Debug, Clone)]
+struct Var {
+    name: String,
+    width: usize,
+    code: String,
+}
+
+#[derive(Debug, Clone)]
+struct Scope {
+    name: String,
+    vars: Vec<usize>,
+    nested: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Tracer {
+    timescale: String,
+    vars: Vec<Var>,
+    scopes: Vec<Scope>,
+    current: Vec<Option<Vec<bit>>>,
+    dumped: Vec<Option<Vec<bit>>>,
+    body: Vec<String>,
+    time: u64,
+}
+
+impl Tracer {
+    pub fn new() -> Self {
+        let mut tracer = Tracer {
+            timescale: "1ns".to_string(),
+            vars: vec![],
+            scopes: vec![],
+            current: vec![],
+            dumped: vec![],
+            body: vec![],
+            time: 0,
+        };
+        tracer.add_scope("clk", vec![("", 1)]);
+        tracer
+    }
+
+    pub fn timescale(mut self, timescale: &str) -> Self {
+        self.timescale = timescale.to_string();
+        self
+    }
+
+    // 名前は $var の行にそのまま入るので、空白を含む名前や clk と重なる名前は受け付けない
+    pub fn watch<T: Trace>(&mut self, name: &str, value: &T) -> Result<Probe, TraceError> {
+        if self.time != 0 {
+            panic!("`Tracer::watch` fail: signals must be added before the first sample.");
+        }
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_graphic()) {
+            return Err(TraceError::Name(name.to_string()));
+        }
+        if self.scopes.iter().any(|scope| scope.name == name) {
+            return Err(TraceError::Duplicate(name.to_string()));
+        }
+        let signals = value
+            .signals()
+            .into_iter()
+            .map(|(name, value)| (name, value.len()))
+            .collect();
+        let probe = self.add_scope(name, signals);
+        self.record(probe, value);
+        Ok(probe)
+    }
+
+    pub fn record<T: Trace>(&mut self, probe: Probe, value: &T) {
+        let vars = self.scopes[probe.0].vars.clone();
+        let signals = value.signals();
+        if signals.len() != vars.len() {
+            panic!("`Tracer::record` fail: value does not match the watched signals.");
+        }
+        for (var, (_, value)) in vars.into_iter().zip(signals) {
+            if value.len() != self.vars[var].width {
+                panic!(
+                    "`Tracer::record` fail: `{}` has width {}, got {}.",
+                    self.vars[var].name,
+                    self.vars[var].width,
+                    value.len()
+                );
+            }
+            self.current[var] = Some(value);
+        }
+    }
+
+    // Tick / Tock ごとに呼び、記録済みの値を現在時刻で確定させる
+    pub fn sample(&mut self, clock: &Clock) {
+        let clk = match clock.state() {
+            Tick => O,
+            Tock => I,
+        };
+        self.current[0] = Some(vec![clk]);
+        let mut changes = vec![];
+        for var in 0..self.vars.len() {
+            if self.current[var] != self.dumped[var] {
+                if let Some(value) = &self.current[var] {
+                    changes.push(self.value_change(var, value));
+                }
+                self.dumped[var] = self.current[var].clone();
+            }
+        }
+        if self.time == 0 {
+            self.body.push("#0".to_string());
+            self.body.push("$dumpvars".to_string());
+            self.body.append(&mut changes);
+            self.body.push("$end".to_string());
+        } else if !changes.is_empty() {
+            self.body.push(format!("#{}", self.time));
+            self.body.append(&mut changes);
+        }
+        self.time += 1;
+    }
+
+    pub fn write<W: Write>(&self, dest: &mut W) -> io::Result<()> {
+        writeln!(dest, "$version nand2tetris-rs $end")?;
+        writeln!(dest, "$timescale {} $end", self.timescale)?;
+        writeln!(dest, "$scope module top $end")?;
+        for scope in self.scopes.iter() {
+            if scope.nested {
+                writeln!(dest, "$scope module {} $end", scope.name)?;
+            }
+            for var in scope.vars.iter() {
+                let var = &self.vars[*var];
+                if var.width == 1 {
+                    writeln!(dest, "$var wire 1 {} {} $end", var.code, var.name)?;
+                } else {
+                    writeln!(
+                        dest,
+                        "$var wire {} {} {} [{}:0] $end",
+                        var.width,
+                        var.code,
+                        var.name,
+                        var.width - 1
+                    )?;
+                }
+            }
+            if scope.nested {
+                writeln!(dest, "$upscope $end")?;
+            }
+        }
+        writeln!(dest, "$upscope $end")?;
+        writeln!(dest, "$enddefinitions $end")?;
+        for line in self.body.iter() {
+            writeln!(dest, "{}", line)?;
+        }
+        writeln!(dest, "#{}", self.time)
+    }
+
+    fn add_scope(&mut self, name: &str, signals: Vec<(&'static str, usize)>) -> Probe {
+        let nested = !(signals.len() == 1 && signals[0].0.is_empty());
+        let mut vars = vec![];
+        for (var_name, width) in signals {
+            let code = id_code(self.vars.len());
+            self.vars.push(Var {
+                name: if nested { var_name } else { name }.to_string(),
+                width,
+                code,
+            });
+            self.current.push(None);
+            self.dumped.push(None);
+            vars.push(self.vars.len() - 1);
+        }
+        self.scopes.push(Scope {
+            name: name.to_string(),
+            vars,
+            nested,
+        });
+        Probe(self.scopes.len() - 1)
+    }
+
+    fn value_change(&self, var: usize, value: &[bit]) -> String {
+        let bits = value
+            .iter()
+            .map(|b| if *b == I { '1' } else { '0' })
+            .collect::<String>();
+        if self.vars[var].width == 1 {
+            format!("{}{}", bits, self.vars[var].code)
+        } else {
+            format!("b{} {}", bits, self.vars[var].code)
+        }
+    }
+}
+
+impl Display for Tracer {
+    fn fmt(&self, dest: &mut Formatter) -> fmt::Result {
+        let mut buf = vec![];
+        self.write(&mut buf).map_err(|_| fmt::Error)?;
+        write!(dest, "{}", String::from_utf8_lossy(&buf))
+    }
+}
+
+// VCD の識別子は印字可能な ASCII (! から ~) の 94 進数
+fn id_code(mut n: usize) -> String {
+    let mut code = String::new();
+    loop {
+        code.push((b'!' + (n % 94) as u8) as char);
+        n /= 94;
+        if n == 0 {
+            break;
+        }
+        n -= 1;
+    }
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{id_code, TraceError, Tracer};
+    use crate::logic::bit::{I, O};
+    use crate::logic::Word;
+    use crate::sequential::{Clock, Register, DFF};
+
+    #[test]
+    fn for_id_code() {
+        assert_eq!(id_code(0), "!");
+        assert_eq!(id_code(93), "~");
+        assert_eq!(id_code(94), "!!");
+        assert_eq!(id_code(95), "\"!");
+    }
+
+    #[test]
+    fn for_tracer_dff() {
+        let mut dff = DFF::new();
+        let mut clock = Clock::new();
+        let mut tracer = Tracer::new();
+        let probe = tracer.watch("dff", &dff).unwrap();
+        let out = tracer.watch("out", &dff.output(&clock)).unwrap();
+
+        for a in [I, I, O].iter() {
+            for _ in 0..2 {
+                dff.input(*a, &clock);
+                tracer.record(probe, &dff);
+                tracer.record(out, &dff.output(&clock));
+                tracer.sample(&clock);
+                clock.next();
+            }
+        }
+
+        assert_eq!(
+            tracer.to_string(),
+            "$version nand2tetris-rs $end\n\
+             $timescale 1ns $end\n\
+             $scope module top $end\n\
+             $var wire 1 ! clk $end\n\
+             $scope module dff $end\n\
+             $var wire 1 \" state_past $end\n\
+             $var wire 1 # state_new $end\n\
+             $upscope $end\n\
+             $var wire 1 $ out $end\n\
+             $upscope $end\n\
+             $enddefinitions $end\n\
+             #0\n\
+             $dumpvars\n\
+             0!\n\
+             0\"\n\
+             1#\n\
+             0$\n\
+             $end\n\
+             #1\n\
+             1!\n\
+             1$\n\
+             #2\n\
+             0!\n\
+             1\"\n\
+             #3\n\
+             1!\n\
+             #4\n\
+             0!\n\
+             0#\n\
+             #5\n\
+             1!\n\
+             0$\n\
+             #6\n"
+        );
+    }
+
+    #[test]
+    fn for_tracer_word() {
+        let clock = Clock::new();
+        let mut tracer = Tracer::new().timescale("10ns");
+        let mut word = Word::new([O; 16]);
+        let probe = tracer.watch("ram", &word).unwrap();
+        tracer.sample(&clock);
+        word[15] = I;
+        tracer.record(probe, &word);
+        tracer.sample(&clock);
+
+        let vcd = tracer.to_string();
+        assert!(vcd.contains("$timescale 10ns $end\n"));
+        assert!(vcd.contains("$var wire 16 \" ram [15:0] $end\n"));
+        assert!(vcd.contains("b0000000000000000 \"\n"));
+        assert!(vcd.ends_with("#1\nb0000000000000001 \"\n#2\n"));
+    }
+
+    #[test]
+    fn for_tracer_register() {
+        let mut register = Register::new();
+        let mut clock = Clock::new();
+        let mut tracer = Tracer::new();
+        let probe = tracer.watch("a", &register).unwrap();
+        tracer.sample(&clock);
+        register.input(Word::from(5u16), I, &clock);
+        tracer.record(probe, &register);
+        tracer.sample(&clock);
+        clock.next();
+
+        let vcd = tracer.to_string();
+        assert!(vcd.contains("$scope module a $end\n"));
+        assert!(vcd.contains("$var wire 16 # state_new [15:0] $end\n"));
+        assert!(vcd.ends_with("#1\nb0000000000000101 #\n#2\n"));
+    }
+
+    #[test]
+    fn for_watch_names() {
+        let mut tracer = Tracer::new();
+        assert_eq!(
+            tracer.watch("my reg", &I),
+            Err(TraceError::Name("my reg".to_string()))
+        );
+        assert_eq!(
+            tracer.watch("clk", &I),
+            Err(TraceError::Duplicate("clk".to_string()))
+        );
+        tracer.watch("out", &O).unwrap();
+        assert_eq!(
+            tracer.watch("out", &O).unwrap_err().to_string(),
+            "`out` is already watched."
+        );
+        assert!(!tracer.to_string().contains("my reg"));
+    }
+}
@@ -0,0 +1,393 @@
+#![allow(dead_code)]
+
+use crate::logic::bit::{I, O};
+use crate::netlist::{self, Driver, Instance, Net, Netlist, Port};
+use std::collections::{HashMap, HashSet};
+
+// チップの種類ごとに一つの module を出力する (子チップが先)
+pub fn to_verilog(netlist: &Netlist) -> String {
+    let mut emitted = HashSet::new();
+    let mut modules = vec![];
+    for (index, instance) in netlist.instances().iter().enumerate() {
+        if instance.parent.is_none() {
+            emit(netlist, index, &mut emitted, &mut modules);
+        }
+    }
+    modules.join("\n")
+}
+
+// DFF を含むインスタンスとその親は clk を受け取る
+fn clocked(netlist: &Netlist) -> Vec<bool> {
+    let instances = netlist.instances();
+    let mut clocked = vec![false; instances.len()];
+    for dff in netlist.dffs().iter() {
+        if let Some(owner) = dff.owner {
+            clocked[owner] = true;
+        }
+    }
+    for index in (0..instances.len()).rev() {
+        if let (true, Some(parent)) = (clocked[index], instances[index].parent) {
+            clocked[parent] = true;
+        }
+    }
+    clocked
+}
+
+// 入力を共有したり定数をつないだりしたインスタンスから module を作ると配線が崩れるので、
+// 別々の入力ネットで組み直したものを使う
+fn standalone(instance: &Instance) -> Option<Netlist> {
+    let mut n = Netlist::new();
+    let inputs = instance
+        .inputs
+        .iter()
+        .map(|port| n.input_bus(port.name, port.nets.len()))
+        .collect::<Vec<_>>();
+    if netlist::build(&mut n, &instance.chip, &inputs) {
+        Some(n)
+    } else {
+        None
+    }
+}
+
+// netlist::build が組める全チップ。幅の決まらない NWay は 4 本のもの
+pub fn library() -> String {
+    let mut n = Netlist::new();
+    for chip in netlist::CHIPS.iter() {
+        let inputs = chip
+            .inputs
+            .iter()
+            .enumerate()
+            .map(|(k, width)| n.input_bus(&format!("{}{}", chip.name, k), *width))
+            .collect::<Vec<_>>();
+        netlist::build(&mut n, chip.name, &inputs);
+    }
+    to_verilog(&n)
+}
+
+fn emit(netlist: &Netlist, index: usize, emitted: &mut HashSet<String>, modules: &mut Vec<String>) {
+    let chip = &netlist.instances()[index].chip;
+    if !emitted.insert(chip.clone()) {
+        return;
+    }
+    let fresh = standalone(&netlist.instances()[index]);
+    let (source, root) = match fresh.as_ref() {
+        Some(n) => (n, 0),
+        None => (netlist, index),
+    };
+    for (child, instance) in source.instances().iter().enumerate() {
+        if instance.parent == Some(root) {
+            emit(source, child, emitted, modules);
+        }
+    }
+    modules.push(module(source, root, &clocked(source)));
+}
+
+fn module(netlist: &Netlist, index: usize, clocked: &[bool]) -> String {
+    let instance = &netlist.instances()[index];
+    let mut names: HashMap<Net, String> = HashMap::new();
+    // 定数は誰も駆動しない。子の出力が定数 (ShiftLeft16 の最下位など) なら未使用の配線へつなぐ
+    let mut driven: HashSet<Net> = [netlist.constant(O), netlist.constant(I)]
+        .iter()
+        .copied()
+        .collect();
+    for port in instance.inputs.iter() {
+        for (k, net) in port.nets.iter().enumerate() {
+            names.entry(*net).or_insert_with(|| port_bit(port, k));
+            driven.insert(*net);
+        }
+    }
+    let name = |net: Net, names: &HashMap<Net, String>| -> String {
+        match (names.get(&net), netlist.driver(net)) {
+            (Some(name), _) => name.clone(),
+            (None, Driver::Const(O)) => "1'b0".to_string(),
+            (None, Driver::Const(I)) => "1'b1".to_string(),
+            (None, _) => format!("w{}", net.id()),
+        }
+    };
+
+    let mut wires = vec![];
+    let mut regs = vec![];
+    let mut unused = 0;
+    let mut body = vec![];
+    for (child, sub) in netlist.instances().iter().enumerate() {
+        if sub.parent != Some(index) {
+            continue;
+        }
+        let mut pins = vec![];
+        if clocked[child] {
+            pins.push(".clk(clk)".to_string());
+        }
+        for port in sub.inputs.iter() {
+            // 入力ポートをそのまま渡すときはポート名だけで書く
+            let whole = instance
+                .inputs
+                .iter()
+                .find(|outer| outer.nets.len() > 1 && outer.nets == port.nets);
+            let nets = match whole {
+                Some(outer) => vec![outer.name.to_string()],
+                None => port
+                    .nets
+                    .iter()
+                    .map(|net| name(*net, &names))
+                    .collect::<Vec<_>>(),
+            };
+            pins.push(format!(".{}({})", port.name, concat(nets)));
+        }
+        for port in sub.outputs.iter() {
+            let mut nets = vec![];
+            for net in port.nets.iter() {
+                // 同じネットを二度駆動しないよう、重複した出力は未使用の配線へ
+                if driven.insert(*net) {
+                    wires.push(*net);
+                    nets.push(name(*net, &names));
+                } else {
+                    nets.push(format!("nc{}", unused));
+                    unused += 1;
+                }
+            }
+            pins.push(format!(".{}({})", port.name, concat(nets)));
+        }
+        body.push(format!(
+            "    {} u{} ({});",
+            sub.chip,
+            child,
+            pins.join(", ")
+        ));
+    }
+    for (gate_index, gate) in netlist.gates().iter().enumerate() {
+        if gate.owner == Some(index) {
+            driven.insert(gate.out);
+            wires.push(gate.out);
+            body.push(format!(
+                "    nand g{} ({}, {}, {});",
+                gate_index,
+                name(gate.out, &names),
+                name(gate.a, &names),
+                name(gate.b, &names)
+            ));
+        }
+    }
+    for dff in netlist.dffs().iter() {
+        if dff.owner == Some(index) {
+            driven.insert(dff.out);
+            regs.push(dff.out);
+            body.push(format!(
+                "    always @(posedge clk) {} <= {};",
+                name(dff.out, &names),
+                name(dff.input, &names)
+            ));
+        }
+    }
+    for port in instance.outputs.iter() {
+        for (k, net) in port.nets.iter().enumerate() {
+            body.push(format!(
+                "    assign {} = {};",
+                port_bit(port, k),
+                name(*net, &names)
+            ));
+        }
+    }
+
+    let mut ports = vec![];
+    if clocked[index] {
+        ports.push("    input clk".to_string());
+    }
+    for port in instance.inputs.iter() {
+        ports.push(format!("    input {}{}", range(port), port.name));
+    }
+    for port in instance.outputs.iter() {
+        ports.push(format!("    output {}{}", range(port), port.name));
+    }
+
+    let mut lines = vec![
+        format!("module {} (", instance.chip),
+        ports.join(",\n"),
+        ");".to_string(),
+    ];
+    wires.sort();
+    wires.dedup();
+    for net in wires.iter() {
+        lines.push(format!("    wire w{};", net.id()));
+    }
+    for k in 0..unused {
+        lines.push(format!("    wire nc{};", k));
+    }
+    for net in regs.iter() {
+        lines.push(format!("    reg w{} = 1'b0;", net.id()));
+    }
+    lines.append(&mut body);
+    lines.push("endmodule".to_string());
+    lines.join("\n") + "\n"
+}
+
+// Word の 0 番目は最上位ビットなので [15:0] の 15 に対応する
+fn port_bit(port: &Port, k: usize) -> String {
+    if port.nets.len() == 1 {
+        port.name.to_string()
+    } else {
+        format!("{}[{}]", port.name, port.nets.len() - 1 - k)
+    }
+}
+
+fn range(port: &Port) -> String {
+    if port.nets.len() == 1 {
+        "".to_string()
+    } else {
+        format!("[{}:0] ", port.nets.len() - 1)
+    }
+}
+
+fn concat(nets: Vec<String>) -> String {
+    if nets.len() == 1 {
+        nets[0].clone()
+    } else {
+        format!("{{{}}}", nets.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{library, to_verilog};
+    use crate::netlist::{self, Netlist, DFF};
+    use crate::{arithmetic, logic};
+
+    #[test]
+    fn for_not_and_dff() {
+        let mut n = Netlist::new();
+        let a = n.input("a");
+        n.capture(|| DFF(logic::Not(a)));
+        assert_eq!(
+            to_verilog(&n),
+            "module Not (\n    \
+               input in,\n    \
+               output out\n\
+             );\n    \
+               wire w3;\n    \
+               nand g0 (w3, in, in);\n    \
+               assign out = w3;\n\
+             endmodule\n\
+             \n\
+             module DFF (\n    \
+               input clk,\n    \
+               input in,\n    \
+               output out\n\
+             );\n    \
+               reg w3 = 1'b0;\n    \
+               always @(posedge clk) w3 <= in;\n    \
+               assign out = w3;\n\
+             endmodule\n"
+        );
+    }
+
+    #[test]
+    fn for_xor() {
+        let mut n = Netlist::new();
+        let a = n.input("a");
+        let b = n.input("b");
+        n.capture(|| logic::Xor(a, b));
+        let verilog = to_verilog(&n);
+        let modules = verilog
+            .lines()
+            .filter(|line| line.starts_with("module "))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            modules,
+            vec![
+                "module Not (",
+                "module And (",
+                "module Or (",
+                "module Xor ("
+            ]
+        );
+        assert!(verilog.contains("    Not u1 (.in(b), .out(w4));\n"));
+        assert!(verilog.contains("    And u2 (.a(a), .b(w4), .out(w7));\n"));
+        assert!(verilog.contains("    assign out = w14;\n"));
+    }
+
+    #[test]
+    fn for_library() {
+        let verilog = library();
+        // build が扱うチップはすべて出力される (NWay は既定の幅で)
+        for chip in netlist::CHIPS.iter() {
+            let header = if chip.name.ends_with('_') {
+                format!("module {}4 (\n", chip.name)
+            } else {
+                format!("module {} (\n", chip.name)
+            };
+            assert_eq!(verilog.matches(&header).count(), 1, "{}", chip.name);
+        }
+        // 定数や入力が子の出力ポートに現れても駆動しない
+        assert!(!verilog.contains("wire w0;"));
+        assert!(!verilog.contains("wire w1;"));
+        assert!(!verilog.contains(", 1'b0}));"));
+        assert!(verilog.contains("    input [15:0] x,\n"));
+        assert!(verilog.contains("    Mux16 u1 (.a(x), .b({1'b0, "));
+        let alu = verilog.split("module ALU (").nth(1).unwrap();
+        let msb = alu
+            .lines()
+            .find(|line| line.starts_with("    assign out[15] = w"))
+            .unwrap();
+        assert!(alu.contains(&msb.replace("out[15]", "ng")));
+        // MuxNWay16_4 と DMuxNWay_4 はそれぞれ 2 方向版も使う
+        assert_eq!(
+            verilog.matches("endmodule").count(),
+            netlist::CHIPS.len() + 2
+        );
+        assert!(!verilog.contains("module Nand"));
+    }
+
+    // 最初のインスタンスが入力を共有していたり定数をつないでいたりしても module は崩れない
+    #[test]
+    fn for_shared_inputs() {
+        let mut n = Netlist::new();
+        let x = n.input16("x");
+        let y = n.input16("y");
+        let s = n.input_bus("s", 6);
+        n.capture(|| arithmetic::ALU(x, y, s[0], s[1], s[2], s[3], s[4], s[5]));
+        let verilog = to_verilog(&n);
+        let mux16 = verilog.split("module Mux16 (").nth(1).unwrap();
+        let mux16 = mux16.split("endmodule").next().unwrap();
+        assert!(mux16.contains("    Mux u6 (.a(a[14]), .b(b[14]), .sel(sel), .out("));
+        assert!(!mux16.contains("1'b0"));
+
+        let mut n = Netlist::new();
+        let x = n.input16("x");
+        let y = n.input16("y");
+        let one = n.constant(crate::logic::bit::I);
+        n.capture(|| logic::Mux8Way16(x, y, x, y, x, y, x, y, [one, one, one]));
+        let verilog = to_verilog(&n);
+        let mux8 = verilog.split("module Mux8Way16 (").nth(1).unwrap();
+        let mux8 = mux8.split("endmodule").next().unwrap();
+        assert!(mux8.contains(".a(e[15]), .b(f[15]), .sel(sel[0])"));
+        assert!(mux8.contains(".a(g[15]), .b(h[15]), .sel(sel[0])"));
+        assert!(!mux8.contains("1'b1"));
+    }
+
+    #[test]
+    fn for_nway() {
+        let mut n = Netlist::new();
+        let ins = (0..4)
+            .map(|i| n.input16(&format!("in{}", i)))
+            .collect::<Vec<_>>();
+        let inc = n.input("in");
+        let sel = n.input_bus("sel", 2);
+        n.capture(|| {
+            logic::MuxNWay16(&ins, &sel);
+            logic::DMuxNWay(inc, &sel);
+        });
+        let verilog = to_verilog(&n);
+        // 幅ごとに一つずつのモジュールで、自分自身は含まない
+        for chip in ["MuxNWay16_4", "MuxNWay16_2", "DMuxNWay_4", "DMuxNWay_2"].iter() {
+            let header = format!("module {} (", chip);
+            assert_eq!(verilog.matches(&header).count(), 1, "{}", chip);
+            let module = verilog.split(&header).nth(1).unwrap();
+            let module = module.split("endmodule").next().unwrap();
+            assert!(!module.contains(&format!("    {} u", chip)), "{}", chip);
+        }
+        let mux4 = verilog.split("module MuxNWay16_4 (").nth(1).unwrap();
+        assert!(mux4.contains("    MuxNWay16_2 u83 (.in({in[31], "));
+        let dmux4 = verilog.split("module DMuxNWay_4 (").nth(1).unwrap();
+        assert!(dmux4.contains("    DMuxNWay_2 u5 (.in(w8), .sel(sel[0]), .out({w15, w18}));"));
+        assert!(!verilog.contains("NWay16_1 ") && !verilog.contains("NWay_1 "));
+    }
+}
@@ -0,0 +1,729 @@
+#![allow(dead_code)]
+
+use crate::emulator::RAM_SIZE;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+pub const SP: usize = 0;
+pub const LCL: usize = 1;
+pub const ARG: usize = 2;
+pub const THIS: usize = 3;
+pub const THAT: usize = 4;
+pub const TEMP: usize = 5;
+pub const STATIC: usize = 16;
+pub const STACK: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment {
+    Argument,
+    Local,
+    Static,
+    Constant,
+    This,
+    That,
+    Pointer,
+    Temp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arithmetic {
+    Add,
+    Sub,
+    Neg,
+    Eq,
+    Gt,
+    Lt,
+    And,
+    Or,
+    Not,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Push(Segment, u16),
+    Pop(Segment, u16),
+    Arithmetic(Arithmetic),
+    Label(String),
+    Goto(String),
+    IfGoto(String),
+    Function(String, u16),
+    Call(String, u16),
+    Return,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VmError {
+    Parse {
+        file: String,
+        line: usize,
+        message: String,
+    },
+    Runtime {
+        pc: usize,
+        function: String,
+        message: String,
+    },
+}
+
+impl Display for VmError {
+    fn fmt(&self, dest: &mut Formatter) -> fmt::Result {
+        match self {
+            VmError::Parse {
+                file,
+                line,
+                message,
+            } => write!(dest, "{}:{}: {}", file, line, message),
+            VmError::Runtime {
+                pc,
+                function,
+                message,
+            } => write!(dest, "in {} (command {}): {}", function, pc, message),
+        }
+    }
+}
+
+// 一つの命令と、それが書かれたファイルと行
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Located {
+    pub command: Command,
+    pub file: String,
+    pub line: usize,
+}
+
+// 複数の .vm ファイルをつなげたもの
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    commands: Vec<Located>,
+    functions: HashMap<String, usize>,
+    // goto と if-goto の位置から飛び先
+    targets: HashMap<usize, usize>,
+    // (ファイル名, 番号) から static のアドレス
+    statics: HashMap<(String, u16), usize>,
+}
+
+impl Program {
+    // files は (ファイル名, ソース)。ファイル名は拡張子を除いたもの (static の名前に使う)
+    pub fn parse(files: &[(&str, &str)]) -> Result<Self, VmError> {
+        let mut commands = vec![];
+        for (file, src) in files.iter() {
+            for (index, line) in src.lines().enumerate() {
+                let line = line.split("//").next().unwrap_or("").trim();
+                if line.is_empty() {
+                    continue;
+                }
+                let command = parse_command(line).map_err(|message| VmError::Parse {
+                    file: file.to_string(),
+                    line: index + 1,
+                    message,
+                })?;
+                commands.push(Located {
+                    command,
+                    file: file.to_string(),
+                    line: index + 1,
+                });
+            }
+        }
+
+        let mut functions = HashMap::new();
+        let mut labels = HashMap::new();
+        let mut statics = HashMap::new();
+        let mut function = String::new();
+        for (pc, located) in commands.iter().enumerate() {
+            let error = |message: String| VmError::Parse {
+                file: located.file.clone(),
+                line: located.line,
+                message,
+            };
+            match &located.command {
+                Command::Function(name, _) => {
+                    if functions.insert(name.clone(), pc).is_some() {
+                        return Err(error(format!("function `{}` is defined twice.", name)));
+                    }
+                    function = name.clone();
+                }
+                Command::Label(label) => {
+                    let key = (function.clone(), label.clone());
+                    if labels.insert(key, pc).is_some() {
+                        return Err(error(format!("label `{}` is defined twice.", label)));
+                    }
+                }
+                Command::Push(Segment::Static, index) | Command::Pop(Segment::Static, index) => {
+                    let next = STATIC + statics.len();
+                    statics
+                        .entry((located.file.clone(), *index))
+                        .or_insert(next);
+                    if statics.len() > STACK - STATIC {
+                        return Err(error("too many static variables.".to_string()));
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        // goto の飛び先は関数の中で探す
+        let mut targets = HashMap::new();
+        let mut function = String::new();
+        for (pc, located) in commands.iter().enumerate() {
+            match &located.command {
+                Command::Function(name, _) => function = name.clone(),
+                Command::Goto(label) | Command::IfGoto(label) => {
+                    match labels.get(&(function.clone(), label.clone())) {
+                        Some(target) => targets.insert(pc, *target),
+                        None => {
+                            return Err(VmError::Parse {
+                                file: located.file.clone(),
+                                line: located.line,
+                                message: format!("unknown label `{}`.", label),
+                            })
+                        }
+                    };
+                }
+                _ => (),
+            }
+        }
+
+        Ok(Program {
+            commands,
+            functions,
+            targets,
+            statics,
+        })
+    }
+
+    pub fn commands(&self) -> &[Located] {
+        &self.commands
+    }
+
+    pub fn has_function(&self, name: &str) -> bool {
+        self.functions.contains_key(name)
+    }
+}
+
+fn parse_command(line: &str) -> Result<Command, String> {
+    let words = line.split_whitespace().collect::<Vec<_>>();
+    let number = |word: &str| {
+        word.parse::<u16>()
+            .ok()
+            .filter(|n| *n <= 32767)
+            .ok_or_else(|| format!("`{}` is not a number in 0..32767.", word))
+    };
+    let arithmetic = |a| Ok(Command::Arithmetic(a));
+    match words.as_slice() {
+        ["push", segment, index] => Ok(Command::Push(parse_segment(segment)?, number(index)?)),
+        ["pop", "constant", _] => Err("cannot pop to the constant segment.".to_string()),
+        ["pop", segment, index] => Ok(Command::Pop(parse_segment(segment)?, number(index)?)),
+        ["add"] => arithmetic(Arithmetic::Add),
+        ["sub"] => arithmetic(Arithmetic::Sub),
+        ["neg"] => arithmetic(Arithmetic::Neg),
+        ["eq"] => arithmetic(Arithmetic::Eq),
+        ["gt"] => arithmetic(Arithmetic::Gt),
+        ["lt"] => arithmetic(Arithmetic::Lt),
+        ["and"] => arithmetic(Arithmetic::And),
+        ["or"] => arithmetic(Arithmetic::Or),
+        ["not"] => arithmetic(Arithmetic::Not),
+        ["label", label] => Ok(Command::Label(label.to_string())),
+        ["goto", label] => Ok(Command::Goto(label.to_string())),
+        ["if-goto", label] => Ok(Command::IfGoto(label.to_string())),
+        ["function", name, locals] => Ok(Command::Function(name.to_string(), number(locals)?)),
+        ["call", name, args] => Ok(Command::Call(name.to_string(), number(args)?)),
+        ["return"] => Ok(Command::Return),
+        _ => Err(format!("cannot parse `{}`.", line)),
+    }
+}
+
+fn parse_segment(segment: &str) -> Result<Segment, String> {
+    match segment {
+        "argument" => Ok(Segment::Argument),
+        "local" => Ok(Segment::Local),
+        "static" => Ok(Segment::Static),
+        "constant" => Ok(Segment::Constant),
+        "this" => Ok(Segment::This),
+        "that" => Ok(Segment::That),
+        "pointer" => Ok(Segment::Pointer),
+        "temp" => Ok(Segment::Temp),
+        _ => Err(format!("unknown segment `{}`.", segment)),
+    }
+}
+
+// OS の関数を Rust で実装するためのフック
+pub trait Native {
+    // 実装していない関数には None を返す。そのときは VM のコードを実行する
+    fn call(&mut self, name: &str, args: &[i16], ram: &mut [i16]) -> Option<Result<i16, String>>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub function: String,
+    // 呼び出し元に戻る位置。Sys.init や最初の関数には無い
+    pub return_address: Option<usize>,
+    pub arg: usize,
+    pub local: usize,
+}
+
+pub struct Vm {
+    program: Program,
+    ram: Vec<i16>,
+    pc: usize,
+    frames: Vec<Frame>,
+    native: Option<Box<dyn Native>>,
+    steps: u64,
+    halted: bool,
+}
+
+impl Vm {
+    // Sys.init があれば SP = 256 にして呼び出す。無ければ最初の命令から始める
+    pub fn new(program: Program) -> Self {
+        let mut vm = Vm {
+            program,
+            ram: vec![0; RAM_SIZE],
+            pc: 0,
+            frames: vec![],
+            native: None,
+            steps: 0,
+            halted: false,
+        };
+        vm.ram[SP] = STACK as i16;
+        if let Some(pc) = vm.program.functions.get("Sys.init") {
+            vm.pc = *pc;
+            vm.ram[SP] += 5;
+            vm.ram[ARG] = STACK as i16;
+            vm.ram[LCL] = vm.ram[SP];
+            vm.frames.push(Frame {
+                function: "Sys.init".to_string(),
+                return_address: None,
+                arg: STACK,
+                local: STACK + 5,
+            });
+        }
+        vm
+    }
+
+    pub fn with_native(mut self, native: Box<dyn Native>) -> Self {
+        self.native = Some(native);
+        self
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn ram(&self) -> &[i16] {
+        &self.ram
+    }
+
+    pub fn peek(&self, address: usize) -> i16 {
+        self.ram[address]
+    }
+
+    pub fn poke(&mut self, address: usize, value: i16) {
+        self.ram[address] = value;
+    }
+
+    pub fn sp(&self) -> usize {
+        self.ram[SP] as u16 as usize
+    }
+
+    // 256 から SP の手前まで
+    pub fn stack(&self) -> &[i16] {
+        &self.ram[STACK..self.sp().clamp(STACK, RAM_SIZE)]
+    }
+
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    // pointer と temp と constant は固定なので None
+    pub fn segment_base(&self, segment: Segment) -> Option<usize> {
+        let pointer = match segment {
+            Segment::Argument => ARG,
+            Segment::Local => LCL,
+            Segment::This => THIS,
+            Segment::That => THAT,
+            _ => return None,
+        };
+        Some(self.ram[pointer] as u16 as usize)
+    }
+
+    pub fn static_address(&self, file: &str, index: u16) -> Option<usize> {
+        self.program
+            .statics
+            .get(&(file.to_string(), index))
+            .copied()
+    }
+
+    pub fn current(&self) -> Option<&Located> {
+        self.program.commands.get(self.pc)
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted || self.pc >= self.program.commands.len()
+    }
+
+    pub fn step(&mut self) -> Result<(), VmError> {
+        if self.is_halted() {
+            return Ok(());
+        }
+        let located = self.program.commands[self.pc].clone();
+        let mut next = self.pc + 1;
+        match located.command {
+            Command::Push(segment, index) => {
+                let value = match segment {
+                    Segment::Constant => index as i16,
+                    _ => {
+                        let address = self.address(segment, index, &located.file)?;
+                        self.ram[address]
+                    }
+                };
+                self.push(value)?;
+            }
+            Command::Pop(segment, index) => {
+                let address = self.address(segment, index, &located.file)?;
+                self.ram[address] = self.pop()?;
+            }
+            Command::Arithmetic(op) => self.arithmetic(op)?,
+            Command::Label(_) => (),
+            Command::Goto(_) => next = self.program.targets[&self.pc],
+            Command::IfGoto(_) => {
+                if self.pop()? != 0 {
+                    next = self.program.targets[&self.pc];
+                }
+            }
+            Command::Function(_, locals) => {
+                for _ in 0..locals {
+                    self.push(0)?;
+                }
+            }
+            Command::Call(name, args) => next = self.call(&name, args, next)?,
+            Command::Return => next = self.ret()?,
+        }
+        self.pc = next;
+        self.steps += 1;
+        Ok(())
+    }
+
+    // 止まるか max_steps に達するまで実行し、実行した命令数を返す
+    pub fn run(&mut self, max_steps: u64) -> Result<u64, VmError> {
+        let start = self.steps;
+        while self.steps - start < max_steps && !self.is_halted() {
+            self.step()?;
+        }
+        Ok(self.steps - start)
+    }
+
+    fn error(&self, message: String) -> VmError {
+        VmError::Runtime {
+            pc: self.pc,
+            function: self.function_name(),
+            message,
+        }
+    }
+
+    fn function_name(&self) -> String {
+        match self.frames.last() {
+            Some(frame) => frame.function.clone(),
+            None => "<top level>".to_string(),
+        }
+    }
+
+    fn address(&self, segment: Segment, index: u16, file: &str) -> Result<usize, VmError> {
+        let index = index as usize;
+        let address = match segment {
+            Segment::Pointer if index < 2 => THIS + index,
+            Segment::Temp if index < 8 => TEMP + index,
+            Segment::Pointer | Segment::Temp => {
+                return Err(self.error(format!("index {} is out of the segment.", index)))
+            }
+            Segment::Static => self.program.statics[&(file.to_string(), index as u16)],
+            Segment::Constant => unreachable!(),
+            _ => self.segment_base(segment).unwrap_or(0) + index,
+        };
+        if address >= RAM_SIZE {
+            return Err(self.error(format!("address {} is out of memory.", address)));
+        }
+        Ok(address)
+    }
+
+    fn push(&mut self, value: i16) -> Result<(), VmError> {
+        let sp = self.sp();
+        if sp >= RAM_SIZE {
+            return Err(self.error("stack overflow.".to_string()));
+        }
+        self.ram[sp] = value;
+        self.ram[SP] += 1;
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<i16, VmError> {
+        let sp = self.sp();
+        if sp == 0 || sp > RAM_SIZE {
+            return Err(self.error("stack underflow.".to_string()));
+        }
+        self.ram[SP] -= 1;
+        Ok(self.ram[sp - 1])
+    }
+
+    fn arithmetic(&mut self, op: Arithmetic) -> Result<(), VmError> {
+        let truth = |b: bool| if b { -1 } else { 0 };
+        let y = self.pop()?;
+        let value = match op {
+            Arithmetic::Neg => y.wrapping_neg(),
+            Arithmetic::Not => !y,
+            _ => {
+                let x = self.pop()?;
+                match op {
+                    Arithmetic::Add => x.wrapping_add(y),
+                    Arithmetic::Sub => x.wrapping_sub(y),
+                    Arithmetic::Eq => truth(x == y),
+                    Arithmetic::Gt => truth(x > y),
+                    Arithmetic::Lt => truth(x < y),
+                    Arithmetic::And => x & y,
+                    _ => x | y,
+                }
+            }
+        };
+        self.push(value)
+    }
+
+    fn call(&mut self, name: &str, args: u16, return_address: usize) -> Result<usize, VmError> {
+        let sp = self.sp();
+        let arg = match sp.checked_sub(args as usize) {
+            Some(arg) => arg,
+            None => return Err(self.error("stack underflow.".to_string())),
+        };
+
+        // ネイティブの実装があればそちらを使う
+        if let Some(mut native) = self.native.take() {
+            let values = self.ram[arg..sp].to_vec();
+            let result = native.call(name, &values, &mut self.ram);
+            self.native = Some(native);
+            if let Some(result) = result {
+                let value =
+                    result.map_err(|message| self.error(format!("{}: {}", name, message)))?;
+                self.ram[SP] = arg as i16;
+                self.push(value)?;
+                return Ok(return_address);
+            }
+        }
+
+        let target = match self.program.functions.get(name) {
+            Some(target) => *target,
+            None => return Err(self.error(format!("unknown function `{}`.", name))),
+        };
+        self.push(return_address as i16)?;
+        for pointer in [LCL, ARG, THIS, THAT].iter() {
+            self.push(self.ram[*pointer])?;
+        }
+        self.ram[ARG] = arg as i16;
+        self.ram[LCL] = self.ram[SP];
+        self.frames.push(Frame {
+            function: name.to_string(),
+            return_address: Some(return_address),
+            arg,
+            local: self.sp(),
+        });
+        Ok(target)
+    }
+
+    fn ret(&mut self) -> Result<usize, VmError> {
+        let frame = self.ram[LCL] as u16 as usize;
+        if frame < 5 {
+            return Err(self.error("return without a frame.".to_string()));
+        }
+        let return_address = self.ram[frame - 5] as u16 as usize;
+        let value = self.pop()?;
+        let arg = self.ram[ARG] as u16 as usize;
+        self.ram[arg] = value;
+        self.ram[SP] = (arg + 1) as i16;
+        self.ram[THAT] = self.ram[frame - 1];
+        self.ram[THIS] = self.ram[frame - 2];
+        self.ram[ARG] = self.ram[frame - 3];
+        self.ram[LCL] = self.ram[frame - 4];
+        // 一番外の関数から戻ったら止まる
+        match self.frames.pop() {
+            Some(Frame {
+                return_address: Some(_),
+                ..
+            }) => Ok(return_address),
+            _ => {
+                self.halted = true;
+                Ok(self.pc)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Command, Frame, Native, Program, Segment, Vm, VmError, ARG, LCL, SP};
+
+    const SIMPLE: &str = "push constant 7\npush constant 8\nadd\npush constant 3\nlt\n\
+                          push constant 5\nneg\n";
+
+    // 再帰でフィボナッチ数を求める
+    const FIB: &str = "
+function Main.fibonacci 0
+    push argument 0
+    push constant 2
+    lt                     // n < 2
+    if-goto IF_TRUE
+    goto IF_FALSE
+label IF_TRUE
+    push argument 0
+    return
+label IF_FALSE
+    push argument 0
+    push constant 2
+    sub
+    call Main.fibonacci 1
+    push argument 0
+    push constant 1
+    sub
+    call Main.fibonacci 1
+    add
+    return
+";
+
+    const SYS: &str = "
+function Sys.init 0
+    push constant 10
+    call Main.fibonacci 1
+    pop static 0
+    push constant 6
+    push constant 7
+    call Math.multiply 2
+    pop static 1
+label WHILE
+    goto WHILE
+";
+
+    struct Math;
+
+    impl Native for Math {
+        fn call(&mut self, name: &str, args: &[i16], _: &mut [i16]) -> Option<Result<i16, String>> {
+            match name {
+                "Math.multiply" => Some(Ok(args[0].wrapping_mul(args[1]))),
+                "Math.divide" if args[1] == 0 => Some(Err("division by zero.".to_string())),
+                "Math.divide" => Some(Ok(args[0] / args[1])),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn for_stack_arithmetic() {
+        let mut vm = Vm::new(Program::parse(&[("Simple", SIMPLE)]).unwrap());
+        assert_eq!(vm.run(100).unwrap(), 7);
+        assert!(vm.is_halted());
+        assert_eq!(vm.stack(), &[0, -5]);
+        assert_eq!(vm.peek(SP), 258);
+    }
+
+    #[test]
+    fn for_calls_and_natives() {
+        let program = Program::parse(&[("Main", FIB), ("Sys", SYS)]).unwrap();
+        let mut vm = Vm::new(program).with_native(Box::new(Math));
+        assert_eq!(vm.frames()[0].function, "Sys.init");
+
+        // fibonacci(10) の途中で呼び出しの深さを見る
+        let mut deepest = 0;
+        while vm.static_address("Sys", 1).map(|a| vm.peek(a)) != Some(42) {
+            vm.step().unwrap();
+            deepest = deepest.max(vm.frames().len());
+        }
+        assert_eq!(deepest, 11);
+        assert_eq!(vm.peek(vm.static_address("Sys", 0).unwrap()), 55);
+        assert_eq!(vm.frames().len(), 1);
+        assert_eq!(vm.stack(), &[0, 0, 0, 0, 0]);
+        assert_eq!(vm.segment_base(Segment::Local), Some(261));
+        assert_eq!(vm.segment_base(Segment::Argument), Some(256));
+        assert_eq!(vm.segment_base(Segment::Temp), None);
+
+        // 終わりの無限ループは止まらない
+        assert_eq!(vm.run(10).unwrap(), 10);
+        assert!(!vm.is_halted());
+    }
+
+    #[test]
+    fn for_return_from_top() {
+        let src = "function Main.main 1\npush constant 3\npop local 0\npush local 0\nreturn\n";
+        let mut vm = Vm::new(Program::parse(&[("Main", src)]).unwrap());
+        // テストスクリプトと同じように自分で枠を用意する
+        vm.poke(SP, 261);
+        vm.poke(LCL, 261);
+        vm.poke(ARG, 256);
+        vm.run(100).unwrap();
+        assert!(vm.is_halted());
+        assert_eq!(vm.peek(256), 3);
+        assert_eq!(vm.peek(SP), 257);
+        assert_eq!(vm.frames(), &[] as &[Frame]);
+    }
+
+    #[test]
+    fn for_parse() {
+        let program = Program::parse(&[("A", "push constant 1\n  call Foo.bar 1 // x\n")]).unwrap();
+        let commands = program
+            .commands()
+            .iter()
+            .map(|c| c.command.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            commands,
+            vec![
+                Command::Push(Segment::Constant, 1),
+                Command::Call("Foo.bar".to_string(), 1)
+            ]
+        );
+
+        let error = |src| Program::parse(&[("A", src)]).unwrap_err().to_string();
+        assert_eq!(
+            error("push constant 1\npop constant 0\n"),
+            "A:2: cannot pop to the constant segment."
+        );
+        assert_eq!(error("push stack 1\n"), "A:1: unknown segment `stack`.");
+        assert_eq!(
+            error("push constant 40000\n"),
+            "A:1: `40000` is not a number in 0..32767."
+        );
+        assert_eq!(error("mul\n"), "A:1: cannot parse `mul`.");
+        assert_eq!(
+            error("function f 0\ngoto END\n"),
+            "A:2: unknown label `END`."
+        );
+        assert_eq!(
+            error("function f 0\nlabel L\nlabel L\n"),
+            "A:3: label `L` is defined twice."
+        );
+    }
+
+    #[test]
+    fn for_runtime_errors() {
+        let run = |src: &str| {
+            let program = Program::parse(&[("Main", src)]).unwrap();
+            Vm::new(program)
+                .with_native(Box::new(Math))
+                .run(100)
+                .unwrap_err()
+        };
+        assert_eq!(
+            run("function Sys.init 0\npush constant 1\npush constant 0\ncall Math.divide 2\n"),
+            VmError::Runtime {
+                pc: 3,
+                function: "Sys.init".to_string(),
+                message: "Math.divide: division by zero.".to_string()
+            }
+        );
+        assert_eq!(
+            run("function Sys.init 0\ncall Output.printInt 0\n").to_string(),
+            "in Sys.init (command 1): unknown function `Output.printInt`."
+        );
+        assert_eq!(
+            run("push temp 8\n").to_string(),
+            "in <top level> (command 0): index 8 is out of the segment."
+        );
+    }
+}